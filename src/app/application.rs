@@ -9,19 +9,12 @@ use crate::app::{
     preview_manager::PreviewManager,
     ui_builder::{self, UiMode},
 };
-use crate::domain::DisplayMode;
+use crate::domain::{DisplayMode, PreviewPosition};
 use crate::services::preview::create_prod_preview_service;
 use crate::services::process::ShellExec;
 use crate::ui::list::ListState;
 use crate::window_state::WindowState;
 
-fn parse_config(config_path: &str) -> Result<crate::config::Config, String> {
-    let content = std::fs::read_to_string(config_path)
-        .map_err(|e| format!("Failed to read config file {}: {}", config_path, e))?;
-    toml::from_str(&content)
-        .map_err(|e| format!("Failed to parse config file {}: {}", config_path, e))
-}
-
 fn get_default_config_path() -> String {
     let config_dir = dirs::config_dir()
         .unwrap_or_else(|| PathBuf::from("."))
@@ -51,6 +44,24 @@ pub struct Args {
 
     #[arg(short = 'm', long = "multi", help = "Enable multi-selection mode")]
     pub multi: bool,
+
+    #[arg(
+        long = "duplicates",
+        help = "Show only images with identical contents, grouped together"
+    )]
+    pub duplicates: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, clap::Subcommand)]
+pub enum Command {
+    /// Inspect and manage the preview cache
+    Cache {
+        #[command(subcommand)]
+        action: crate::cli::CacheAction,
+    },
 }
 
 pub struct PantryApp {
@@ -73,6 +84,17 @@ impl PantryApp {
     }
 
     pub fn run(self) {
+        if let Some(Command::Cache { action }) = &self.args.command {
+            let code = match crate::cli::run_cache(action, &self.args.config) {
+                Ok(()) => 0,
+                Err(e) => {
+                    eprintln!("pantry: {}", e);
+                    1
+                }
+            };
+            std::process::exit(code);
+        }
+
         let app = Application::builder()
             .application_id("io.github.lonerorz.pantry")
             .build();
@@ -83,17 +105,33 @@ impl PantryApp {
     fn build_ui(&self, app: &Application) {
         use crate::app::preview_manager::PreviewUpdater;
 
-        let raw_manager = PreviewManager::new(create_prod_preview_service());
-        let preview_manager: Rc<RefCell<dyn PreviewUpdater>> = Rc::new(RefCell::new(raw_manager));
-
         let search_query: crate::ui::search::SearchState = Rc::new(RefCell::new(String::new()));
 
         let parsed_config = if !self.is_stdin {
-            Some(parse_config(&self.args.config))
+            Some(crate::config::load(&self.args.config))
         } else {
             None
         };
 
+        let (cache_config, providers, listing_depth) = match &parsed_config {
+            Some(Ok(config)) => (
+                config.cache.clone(),
+                config.providers.clone(),
+                crate::config::get_config_listing_depth(config, &self.args.category),
+            ),
+            _ => (
+                Default::default(),
+                Default::default(),
+                crate::constants::DEFAULT_LISTING_DEPTH,
+            ),
+        };
+        let raw_manager = PreviewManager::new(
+            create_prod_preview_service(&cache_config)
+                .with_providers(providers)
+                .with_listing_depth(listing_depth),
+        );
+        let preview_manager: Rc<RefCell<dyn PreviewUpdater>> = Rc::new(RefCell::new(raw_manager));
+
         let mode = if self.is_stdin {
             UiMode::Stdin
         } else {
@@ -104,12 +142,23 @@ impl PantryApp {
                         &self.args.category,
                         &self.args.display,
                     );
-                    UiMode::Config { display_mode }
+                    let preview = crate::config::get_config_preview_position(
+                        config,
+                        &self.args.category,
+                        &display_mode,
+                    );
+                    UiMode::Config {
+                        display_mode,
+                        preview,
+                        wrap: crate::config::get_config_preview_wrap(config, &self.args.category),
+                    }
                 }
                 Err(e) => {
                     log::error!("{}", e);
                     UiMode::Config {
                         display_mode: DisplayMode::Text,
+                        preview: PreviewPosition::None,
+                        wrap: true,
                     }
                 }
             }
@@ -123,11 +172,22 @@ impl PantryApp {
             &preview_manager,
         );
 
+        let browser = match &parsed_config {
+            Some(Ok(config)) => crate::services::files::browser_for(config, &self.args.category),
+            _ => None,
+        };
+        if let Some(browser) = &browser {
+            search_entry.set_placeholder_text(Some(&browser.dir().to_string_lossy()));
+        }
+        let browser = browser.map(|browser| Rc::new(RefCell::new(browser)));
+
         event_handlers::setup_keyboard_controller(
             &window,
             &list_state,
             &search_entry,
+            &preview_area_rc_opt,
             self.args.multi,
+            browser,
         );
 
         if let Some(Ok(config)) = parsed_config {
@@ -161,6 +221,7 @@ impl PantryApp {
         let list_state = list_state.clone();
         let preview_area_rc_opt_clone = preview_area_rc_opt.clone();
         let preview_manager_clone = preview_manager.clone();
+        let duplicates = self.args.duplicates;
 
         glib::spawn_future_local(async move {
             let load_result = gio::spawn_blocking(move || {
@@ -171,6 +232,11 @@ impl PantryApp {
                     &display_arg,
                     &executor,
                 );
+                let processed_items = if duplicates {
+                    crate::services::duplicates::group_duplicates(processed_items)
+                } else {
+                    processed_items
+                };
                 Ok::<Vec<crate::domain::item::Item>, String>(processed_items)
             })
             .await;
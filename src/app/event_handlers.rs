@@ -1,28 +1,71 @@
+use crate::domain::SourceMode;
+use crate::services::process::ShellExec;
+use crate::services::{applications, clipboard, files};
 use crate::ui::list::ListState;
+use crate::ui::preview::PreviewArea;
 use gtk4::gdk::ModifierType;
 use gtk4::{ApplicationWindow, EventControllerKey, PropagationPhase, prelude::*};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 pub fn setup_keyboard_controller(
     window: &ApplicationWindow,
     list_state: &ListState,
     search_entry: &gtk4::SearchEntry,
+    preview_area_rc_opt: &Option<Rc<RefCell<PreviewArea>>>,
     multi_mode: bool,
+    browser: Option<Rc<RefCell<files::Browser>>>,
 ) {
     let controller = EventControllerKey::new();
     controller.set_propagation_phase(PropagationPhase::Capture);
 
     let list_state = list_state.clone();
     let search_entry = search_entry.clone();
+    let preview_area_rc_opt = preview_area_rc_opt.clone();
 
     controller.connect_key_pressed(move |controller, keyval, _, state| {
         let has_ctrl = state.contains(ModifierType::CONTROL_MASK);
         let has_shift = state.contains(ModifierType::SHIFT_MASK);
 
         if keyval == gtk4::gdk::Key::Return || keyval == gtk4::gdk::Key::KP_Enter {
+            // In a file browser, folders are entered rather than chosen
+            if let Some(browser) = &browser
+                && let Some(item) = list_state.selected_item()
+            {
+                let entered = browser.borrow_mut().enter(Path::new(&item.value));
+                if entered {
+                    show_folder(browser, &list_state, &search_entry, None);
+                    return glib::Propagation::Stop;
+                }
+            }
             handle_selection(&list_state);
             return glib::Propagation::Stop;
         }
 
+        if keyval == gtk4::gdk::Key::BackSpace
+            && search_entry.text().is_empty()
+            && let Some(browser) = &browser
+        {
+            let left = browser.borrow_mut().up();
+            if let Some(left) = left {
+                show_folder(browser, &list_state, &search_entry, Some(left));
+            }
+            return glib::Propagation::Stop;
+        }
+
+        if has_ctrl
+            && keyval == gtk4::gdk::Key::h
+            && let Some(browser) = &browser
+        {
+            browser.borrow_mut().toggle_hidden();
+            let selected = list_state
+                .selected_item()
+                .map(|item| PathBuf::from(item.value));
+            show_folder(browser, &list_state, &search_entry, selected);
+            return glib::Propagation::Stop;
+        }
+
         let is_cancel = keyval == gtk4::gdk::Key::Escape
             || (has_ctrl && (keyval == gtk4::gdk::Key::g || keyval == gtk4::gdk::Key::c));
 
@@ -98,23 +141,101 @@ pub fn setup_keyboard_controller(
             return glib::Propagation::Stop;
         }
 
+        // Shift+Delete drops a clipboard entry from the history
+        if has_shift
+            && keyval == gtk4::gdk::Key::Delete
+            && let Some(item) = list_state.selected_item()
+            && item.source == SourceMode::Clipboard
+        {
+            match clipboard::delete(&item.value, &ShellExec) {
+                Ok(()) => list_state.remove_selected(),
+                Err(e) => log::error!("{}", e),
+            }
+            return glib::Propagation::Stop;
+        }
+
         if has_ctrl && keyval == gtk4::gdk::Key::u {
             search_entry.set_text("");
             return glib::Propagation::Stop;
         }
 
+        // Zoom and rotate an image preview
+        if has_ctrl
+            && let Some(preview_area_rc) = &preview_area_rc_opt
+            && preview_area_rc
+                .borrow()
+                .handle_viewer_key(keyval, has_shift)
+        {
+            return glib::Propagation::Stop;
+        }
+
         glib::Propagation::Proceed
     });
     window.add_controller(controller);
 }
 
+/// List the browser's current folder on a worker, keeping `select` selected when it is still shown
+fn show_folder(
+    browser: &Rc<RefCell<files::Browser>>,
+    list_state: &ListState,
+    search_entry: &gtk4::SearchEntry,
+    select: Option<PathBuf>,
+) {
+    let shown = browser.borrow().clone();
+    search_entry.set_text("");
+    search_entry.set_placeholder_text(Some(&shown.dir().to_string_lossy()));
+
+    let browser = browser.clone();
+    let list_state = list_state.clone();
+    glib::spawn_future_local(async move {
+        let listed = shown.clone();
+        let Ok(items) = gio::spawn_blocking(move || listed.list()).await else {
+            log::error!("Listing {} failed", shown.dir().display());
+            return;
+        };
+        // Moving on again already started a newer listing
+        if *browser.borrow() != shown {
+            return;
+        }
+        list_state.replace_items(&items);
+        let selected = select.is_some_and(|path| list_state.select_value(&path.to_string_lossy()));
+        if !selected {
+            list_state.select_first();
+        }
+    });
+}
+
 pub fn handle_selection(list_state: &ListState) {
-    let mut selected_values = list_state.marked_values();
+    let marked = list_state.marked_items();
 
-    if selected_values.is_empty()
+    // Clipboard entries go back on the clipboard instead of to stdout
+    if marked.is_empty()
         && let Some(item) = list_state.selected_item()
+        && item.source == SourceMode::Clipboard
     {
-        selected_values.push(item.value);
+        if let Err(e) = clipboard::copy(&item, &ShellExec) {
+            log::error!("{}", e);
+        }
+        list_state.close_window(true);
+        return;
+    }
+
+    let items = if marked.is_empty() {
+        list_state.selected_item().into_iter().collect()
+    } else {
+        marked
+    };
+    let mut selected_values = Vec::new();
+    for item in items {
+        match &item.launch {
+            // Applications are started, not printed
+            Some(launcher) => {
+                if let Err(e) = applications::launch(launcher) {
+                    log::error!("{}", e);
+                }
+            }
+            None => selected_values.push(item.value),
+        }
     }
 
     for (idx, val) in selected_values.iter().enumerate() {
@@ -1,9 +1,14 @@
-use crate::services::preview::{PreviewPayload, ProdPreviewService};
+use crate::constants::{PREFETCH_NICE, PREFETCH_RADIUS, PREVIEW_WORKERS};
+use crate::domain::item::Item;
+use crate::services::jobs::{CancelToken, JobLimiter};
+use crate::services::preview::{PreviewPayload, PreviewSize, ProdPreviewService};
 use crate::ui::list::ListState;
 use crate::ui::preview::PreviewArea;
 use gtk4::{gio, glib};
 use std::cell::{Cell, RefCell};
 use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, mpsc};
 
 /// Abstract preview update interface — erases C, E, D generics from the UI layer
 pub trait PreviewUpdater {
@@ -18,15 +23,22 @@ pub trait PreviewUpdater {
 pub struct PreviewManager {
     service: ProdPreviewService,
     next_task_id: Cell<u64>,
-    active_task_id: Cell<u64>,
+    /// Shared with background jobs, which give up once a newer selection takes over
+    active_task_id: Arc<AtomicU64>,
+    /// Shared by selected and prefetch jobs so scrolling can't pile up decoders or ffmpeg runs
+    jobs: JobLimiter,
+    prefetcher: Prefetcher,
 }
 
 impl PreviewManager {
     pub fn new(service: ProdPreviewService) -> Self {
+        let jobs = JobLimiter::new(PREVIEW_WORKERS);
         Self {
+            prefetcher: Prefetcher::start(service.clone(), jobs.clone()),
             service,
             next_task_id: Cell::new(1),
-            active_task_id: Cell::new(0),
+            active_task_id: Arc::new(AtomicU64::new(0)),
+            jobs,
         }
     }
 
@@ -42,17 +54,34 @@ impl PreviewManager {
             return;
         };
 
-        if let Some(cached) = self.service.try_cache(&item) {
-            preview_area_rc.borrow().render(cached, &item);
-            return;
-        }
+        let size = preview_area_rc.borrow().decode_size();
+        preview_area_rc.borrow().set_requested_size(size);
 
+        // Every selection change gets a new id, so prefetching for the old one stops too
         let task_id = self.next_task_id.get();
         self.next_task_id.set(task_id + 1);
-        self.active_task_id.set(task_id);
+        self.active_task_id.store(task_id, Ordering::Relaxed);
+
+        let cancel = CancelToken::for_task(self.active_task_id.clone(), task_id);
+        let neighbors = neighbor_items(list_state);
+
+        if let Some(cached) = self.service.try_cache(&item, size) {
+            preview_area_rc.borrow().render(cached, &item);
+            connect_full_resolution(&self.service, preview_area_rc, &item, &cancel);
+            spawn_media_details(
+                &self.service,
+                &self.jobs,
+                preview_area_rc,
+                item,
+                cancel.clone(),
+            );
+            self.prefetcher.request(neighbors, size, cancel);
+            return;
+        }
 
         let service = self.service.clone();
-        let active_task_id = self.active_task_id.clone();
+        let jobs = self.jobs.clone();
+        let prefetcher = self.prefetcher.clone();
 
         preview_area_rc
             .borrow()
@@ -62,20 +91,40 @@ impl PreviewManager {
         let item_clone = item.clone();
 
         glib::spawn_future_local(async move {
-            if active_task_id.get() != task_id {
+            if cancel.is_cancelled() {
                 return;
             }
 
-            let payload_result =
-                gio::spawn_blocking(move || service.resolve_payload(&item_clone)).await;
+            // A small shared thumbnail stands in until the decode below is done
+            let first_service = service.clone();
+            let first_item = item_clone.clone();
+            let first_paint =
+                gio::spawn_blocking(move || first_service.resolve_first_paint(&first_item, size))
+                    .await;
+            if let Ok(Some(payload)) = first_paint
+                && !cancel.is_cancelled()
+            {
+                preview_area.borrow().render(payload, &item);
+            }
+
+            let worker_service = service.clone();
+            let worker_jobs = jobs.clone();
+            let worker_cancel = cancel.clone();
+            let payload_result = gio::spawn_blocking(move || {
+                let _slot = worker_jobs.acquire(&worker_cancel)?;
+                Some(worker_service.resolve_cancellable(&item_clone, size, &worker_cancel))
+            })
+            .await;
 
-            if active_task_id.get() != task_id {
+            if cancel.is_cancelled() {
                 return;
             }
 
             match payload_result {
-                Ok(payload) => {
+                Ok(None) => {}
+                Ok(Some(payload)) => {
                     preview_area.borrow().render(payload, &item);
+                    connect_full_resolution(&service, &preview_area, &item, &cancel);
                 }
                 Err(_) => {
                     preview_area.borrow().render(
@@ -84,6 +133,9 @@ impl PreviewManager {
                     );
                 }
             }
+
+            spawn_media_details(&service, &jobs, &preview_area, item, cancel.clone());
+            prefetcher.request(neighbors, size, cancel);
         });
     }
 }
@@ -97,3 +149,189 @@ impl PreviewUpdater for PreviewManager {
         self.update_preview(list_state, preview_area_rc_opt);
     }
 }
+
+/// Decode the selected image at full size once the viewer is zoomed past its preview decode
+fn connect_full_resolution(
+    service: &ProdPreviewService,
+    preview_area: &Rc<RefCell<PreviewArea>>,
+    item: &Item,
+    cancel: &CancelToken,
+) {
+    if !service.has_full_resolution(item) {
+        return;
+    }
+    let service = service.clone();
+    // The viewer holding this callback belongs to the preview area, so only hold it weakly
+    let weak_area = Rc::downgrade(preview_area);
+    let item = item.clone();
+    let cancel = cancel.clone();
+
+    preview_area.borrow().connect_full_resolution(move || {
+        let service = service.clone();
+        let weak_area = weak_area.clone();
+        let item = item.clone();
+        let cancel = cancel.clone();
+
+        glib::spawn_future_local(async move {
+            let worker_cancel = cancel.clone();
+            let payload =
+                gio::spawn_blocking(move || service.resolve_full_resolution(&item, &worker_cancel))
+                    .await;
+            if let Ok(Some(payload)) = payload
+                && !cancel.is_cancelled()
+                && let Some(preview_area) = weak_area.upgrade()
+            {
+                preview_area.borrow().show_full_resolution(payload);
+            }
+        });
+    });
+}
+
+/// Probe the selected item's media in the background and add it to the details pane
+fn spawn_media_details(
+    service: &ProdPreviewService,
+    jobs: &JobLimiter,
+    preview_area: &Rc<RefCell<PreviewArea>>,
+    item: Item,
+    cancel: CancelToken,
+) {
+    if !service.has_media_details(&item) {
+        return;
+    }
+    let service = service.clone();
+    let jobs = jobs.clone();
+    let preview_area = preview_area.clone();
+
+    glib::spawn_future_local(async move {
+        let worker_cancel = cancel.clone();
+        let details = gio::spawn_blocking(move || {
+            let _slot = jobs.acquire(&worker_cancel)?;
+            Some(service.media_details(&item, &worker_cancel))
+        })
+        .await;
+        if let Ok(Some(details)) = details
+            && !cancel.is_cancelled()
+        {
+            preview_area.borrow().set_media_details(&details);
+        }
+    });
+}
+
+fn neighbor_items(list_state: &ListState) -> Vec<Item> {
+    neighbor_indices(
+        list_state.selected_index(),
+        list_state.n_items(),
+        PREFETCH_RADIUS,
+    )
+    .into_iter()
+    .filter_map(|index| list_state.item_at(index))
+    .collect()
+}
+
+/// Positions around `selected`, nearest first and the next item before the previous one
+fn neighbor_indices(selected: u32, n_items: u32, radius: u32) -> Vec<u32> {
+    if selected >= n_items {
+        return Vec::new();
+    }
+    let mut indices = Vec::new();
+    for offset in 1..=radius {
+        if let Some(next) = selected.checked_add(offset).filter(|&i| i < n_items) {
+            indices.push(next);
+        }
+        if let Some(prev) = selected.checked_sub(offset) {
+            indices.push(prev);
+        }
+    }
+    indices
+}
+
+/// Neighbors of one selection, wanted until `cancel` fires
+struct PrefetchRequest {
+    items: Vec<Item>,
+    size: PreviewSize,
+    cancel: CancelToken,
+}
+
+/// A single low-priority thread that resolves neighbors one at a time, always working on the
+/// newest selection's request
+#[derive(Clone)]
+struct Prefetcher {
+    requests: Option<mpsc::Sender<PrefetchRequest>>,
+}
+
+impl Prefetcher {
+    fn start(service: ProdPreviewService, jobs: JobLimiter) -> Self {
+        let (sender, receiver) = mpsc::channel::<PrefetchRequest>();
+        let spawned = std::thread::Builder::new()
+            .name("pantry-prefetch".to_string())
+            .spawn(move || {
+                lower_thread_priority();
+                while let Ok(mut request) = receiver.recv() {
+                    // Anything queued behind a request is for a newer selection
+                    while let Ok(newer) = receiver.try_recv() {
+                        request = newer;
+                    }
+                    for item in &request.items {
+                        let Some(_slot) = jobs.acquire(&request.cancel) else {
+                            break;
+                        };
+                        service.prefetch(item, request.size, &request.cancel);
+                    }
+                }
+            });
+        match spawned {
+            Ok(_) => Self {
+                requests: Some(sender),
+            },
+            Err(e) => {
+                log::debug!("Failed to start prefetch thread: {}", e);
+                Self { requests: None }
+            }
+        }
+    }
+
+    fn request(&self, items: Vec<Item>, size: PreviewSize, cancel: CancelToken) {
+        if let Some(requests) = &self.requests
+            && !items.is_empty()
+        {
+            let _ = requests.send(PrefetchRequest {
+                items,
+                size,
+                cancel,
+            });
+        }
+    }
+}
+
+fn lower_thread_priority() {
+    // On Linux, a thread id passed as PRIO_PROCESS affects only that thread
+    #[cfg(target_os = "linux")]
+    unsafe {
+        libc::setpriority(
+            libc::PRIO_PROCESS,
+            libc::gettid() as libc::id_t,
+            PREFETCH_NICE,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn neighbors_alternate_next_and_previous() {
+        assert_eq!(neighbor_indices(5, 10, 2), vec![6, 4, 7, 3]);
+    }
+
+    #[test]
+    fn neighbors_clamped_to_list() {
+        assert_eq!(neighbor_indices(0, 3, 3), vec![1, 2]);
+        assert_eq!(neighbor_indices(2, 3, 2), vec![1, 0]);
+    }
+
+    #[test]
+    fn no_neighbors_without_selection() {
+        assert!(neighbor_indices(gtk4::INVALID_LIST_POSITION, 3, 2).is_empty());
+    }
+}
@@ -6,8 +6,8 @@ use std::cell::RefCell;
 use std::rc::Rc;
 
 use crate::constants::MAX_ITEMS;
-use crate::domain::DisplayMode;
 use crate::domain::item::Item;
+use crate::domain::{DisplayMode, PreviewPosition};
 use crate::ui::{header, list::ListState, preview, window};
 use crate::window_state::WindowState;
 
@@ -15,7 +15,12 @@ use crate::app::preview_manager::PreviewUpdater;
 
 pub enum UiMode {
     Stdin,
-    Config { display_mode: DisplayMode },
+    Config {
+        display_mode: DisplayMode,
+        preview: PreviewPosition,
+        /// Wrap long lines in text previews
+        wrap: bool,
+    },
 }
 
 pub fn build_ui(
@@ -30,18 +35,20 @@ pub fn build_ui(
     Option<Rc<RefCell<preview::PreviewArea>>>,
     SearchEntry,
 ) {
-    let display_mode = match &mode {
-        UiMode::Stdin => DisplayMode::Text,
-        UiMode::Config { display_mode } => display_mode.clone(),
+    let (display_mode, preview, wrap) = match &mode {
+        UiMode::Stdin => (DisplayMode::Text, PreviewPosition::None, true),
+        UiMode::Config {
+            display_mode,
+            preview,
+            wrap,
+        } => (display_mode.clone(), *preview, *wrap),
     };
 
-    let (window, list_state, preview_area_rc_opt, search_entry, main_widget) = build_ui_shell(
-        window_state,
-        app,
-        &query_state,
-        &display_mode,
-        preview_manager,
-    );
+    let (window, list_state, preview_area_rc_opt, search_entry, main_widget) =
+        build_ui_shell(window_state, app, &query_state, preview, preview_manager);
+    if let Some(preview_area_rc) = &preview_area_rc_opt {
+        preview_area_rc.borrow().set_wrap(wrap);
+    }
 
     if matches!(mode, UiMode::Config { .. }) && window_state.maximized {
         window.maximize();
@@ -59,7 +66,6 @@ pub fn build_ui(
         &main_widget,
         &list_state,
         &preview_area_rc_opt,
-        display_mode,
         preview_manager,
     );
 
@@ -70,7 +76,7 @@ fn build_ui_shell(
     window_state: &WindowState,
     app: &Application,
     query_state: &crate::ui::search::SearchState,
-    display_mode: &DisplayMode,
+    preview: PreviewPosition,
     preview_manager: &Rc<RefCell<dyn PreviewUpdater>>,
 ) -> (
     ApplicationWindow,
@@ -83,7 +89,7 @@ fn build_ui_shell(
     window.set_default_size(window_state.width, window_state.height);
 
     let list_state = ListState::new(query_state.clone());
-    let (main_widget, preview_area_rc_opt) = build_main_widget(&list_state, display_mode.clone());
+    let (main_widget, preview_area_rc_opt) = build_main_widget(&list_state, preview);
 
     let (header_bar, search_entry, menu_button) = header::build_header_bar();
     header::connect_about_dialog(&window, &menu_button);
@@ -165,9 +171,9 @@ fn spawn_stdin_reader(list_state: &ListState, display_mode: &DisplayMode) {
 
 fn build_main_widget(
     list_state: &ListState,
-    display_mode: DisplayMode,
+    preview: PreviewPosition,
 ) -> (gtk4::Widget, Option<Rc<RefCell<preview::PreviewArea>>>) {
-    let (content_widget, preview_area_rc_opt) = build_content(list_state, display_mode);
+    let (content_widget, preview_area_rc_opt) = build_content(list_state, preview);
 
     let list_stack = gtk4::Stack::new();
     list_stack.set_transition_type(gtk4::StackTransitionType::Crossfade);
@@ -223,10 +229,15 @@ fn build_main_widget(
 
 fn build_content(
     list_state: &ListState,
-    display_mode: DisplayMode,
+    preview: PreviewPosition,
 ) -> (gtk4::Widget, Option<Rc<RefCell<preview::PreviewArea>>>) {
-    if matches!(display_mode, DisplayMode::Picture) {
-        let paned = gtk4::Paned::new(gtk4::Orientation::Horizontal);
+    let orientation = match preview {
+        PreviewPosition::Right => Some(Orientation::Horizontal),
+        PreviewPosition::Bottom => Some(Orientation::Vertical),
+        PreviewPosition::None => None,
+    };
+    if let Some(orientation) = orientation {
+        let paned = gtk4::Paned::new(orientation);
 
         let scrolled = wrap_in_scroll(list_state.view());
         scrolled.set_hexpand(true);
@@ -261,10 +272,9 @@ fn setup_preview_updates(
     main_widget: &gtk4::Widget,
     list_state: &ListState,
     preview_area_rc_opt: &Option<Rc<RefCell<preview::PreviewArea>>>,
-    display_mode: DisplayMode,
     preview_manager: &Rc<RefCell<dyn PreviewUpdater>>,
 ) {
-    if !matches!(display_mode, DisplayMode::Picture) {
+    if preview_area_rc_opt.is_none() {
         return;
     }
 
@@ -314,6 +324,40 @@ fn setup_preview_updates(
         },
     );
 
+    if let Some(preview_area_rc) = preview_area_rc_opt {
+        let list_state_clone = list_state.clone();
+        let preview_area_rc_clone = preview_area_rc.clone();
+        let preview_manager_clone3 = preview_manager.clone();
+        let resize_timeout_id = Rc::new(RefCell::new(None::<glib::SourceId>));
+
+        preview_area_rc.borrow().connect_resized(move || {
+            if let Some(old_id) = resize_timeout_id.borrow_mut().take() {
+                old_id.remove();
+            }
+
+            let list_state_inner = list_state_clone.clone();
+            let preview_area_inner = preview_area_rc_clone.clone();
+            let preview_manager_inner = preview_manager_clone3.clone();
+            let resize_timeout_inner = resize_timeout_id.clone();
+
+            let new_id = glib::timeout_add_local(
+                std::time::Duration::from_millis(crate::constants::PREVIEW_RESIZE_DEBOUNCE_MS),
+                move || {
+                    resize_timeout_inner.borrow_mut().take();
+
+                    if preview_area_inner.borrow().needs_larger_decode() {
+                        preview_manager_inner
+                            .borrow()
+                            .update_preview(&list_state_inner, &Some(preview_area_inner.clone()));
+                    }
+                    glib::ControlFlow::Break
+                },
+            );
+
+            resize_timeout_id.borrow_mut().replace(new_id);
+        });
+    }
+
     if let Some(paned_widget) = main_widget.downcast_ref::<gtk4::Paned>() {
         let paned_widget_clone = paned_widget.clone();
         window.connect_realize(move |win| {
@@ -322,9 +366,13 @@ fn setup_preview_updates(
             glib::timeout_add_local(
                 std::time::Duration::from_millis(crate::constants::SELECTION_UPDATE_DELAY_MS),
                 move || {
-                    let width = win_clone.default_size().0;
-                    let position =
-                        (width as f64 * crate::constants::MAX_WINDOW_WIDTH_FRACTION) as i32;
+                    let (width, height) = win_clone.default_size();
+                    let position = match paned_widget_clone.orientation() {
+                        Orientation::Vertical => {
+                            (height as f64 * crate::constants::LIST_HEIGHT_FRACTION) as i32
+                        }
+                        _ => (width as f64 * crate::constants::MAX_WINDOW_WIDTH_FRACTION) as i32,
+                    };
                     paned_widget_clone.set_position(position);
                     glib::ControlFlow::Break
                 },
@@ -0,0 +1,265 @@
+use std::fs;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+const MAGIC: [u8; 4] = *b"PNTY";
+/// Bumped when stored pixels change meaning; 2 stores them EXIF-oriented
+const VERSION: u16 = 2;
+pub const HEADER_LEN: usize = 36;
+const MAX_PIXEL_BYTES: u64 = 100 * 1024 * 1024;
+/// Prefix of the temporary files entries and the index are written to before the rename
+pub const TEMP_PREFIX: &str = ".tmp";
+/// Temporary files this old belong to writes that were interrupted, not ones in progress
+pub const STALE_TEMP_AGE: Duration = Duration::from_secs(3600);
+
+/// Layout of the decoded pixels stored in an entry
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum PixelFormat {
+    Rgba8 = 1,
+}
+
+/// Fixed-size, little-endian header at the start of every cache entry
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Header {
+    pub format: PixelFormat,
+    pub width: u32,
+    pub height: u32,
+    /// Size of the source file when the entry was written
+    pub source_size: u64,
+    /// Source mtime in nanoseconds since the Unix epoch
+    pub source_mtime: u64,
+    /// CRC-32 of the compressed payload
+    pub checksum: u32,
+}
+
+impl Header {
+    fn to_bytes(self) -> [u8; HEADER_LEN] {
+        let mut buf = [0u8; HEADER_LEN];
+        buf[0..4].copy_from_slice(&MAGIC);
+        buf[4..6].copy_from_slice(&VERSION.to_le_bytes());
+        buf[6] = self.format as u8;
+        buf[8..12].copy_from_slice(&self.width.to_le_bytes());
+        buf[12..16].copy_from_slice(&self.height.to_le_bytes());
+        buf[16..24].copy_from_slice(&self.source_size.to_le_bytes());
+        buf[24..32].copy_from_slice(&self.source_mtime.to_le_bytes());
+        buf[32..36].copy_from_slice(&self.checksum.to_le_bytes());
+        buf
+    }
+
+    fn from_bytes(buf: &[u8; HEADER_LEN]) -> Option<Self> {
+        let u32_at = |i: usize| u32::from_le_bytes(buf[i..i + 4].try_into().unwrap());
+        let u64_at = |i: usize| u64::from_le_bytes(buf[i..i + 8].try_into().unwrap());
+
+        if buf[0..4] != MAGIC || u16::from_le_bytes([buf[4], buf[5]]) != VERSION {
+            return None;
+        }
+        let format = match buf[6] {
+            1 => PixelFormat::Rgba8,
+            _ => return None,
+        };
+        Some(Self {
+            format,
+            width: u32_at(8),
+            height: u32_at(12),
+            source_size: u64_at(16),
+            source_mtime: u64_at(24),
+            checksum: u32_at(32),
+        })
+    }
+
+    /// Whether the entry was made from the source file as it is now
+    pub fn matches_source(&self, source: &Path) -> bool {
+        source_fingerprint(source) == Some((self.source_size, self.source_mtime))
+    }
+}
+
+/// Size and mtime (ns) of a source file
+pub fn source_fingerprint(source: &Path) -> Option<(u64, u64)> {
+    let meta = fs::metadata(source).ok()?;
+    let mtime = meta
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_nanos() as u64;
+    Some((meta.len(), mtime))
+}
+
+pub fn read_header(path: &Path) -> Option<Header> {
+    let mut file = fs::File::open(path).ok()?;
+    let mut buf = [0u8; HEADER_LEN];
+    file.read_exact(&mut buf).ok()?;
+    Header::from_bytes(&buf)
+}
+
+/// Write an entry to a temporary file, sync it and rename it into place, so neither readers
+/// nor a crash can leave a partial file under the final name. Returns the number of bytes
+/// written.
+pub fn write_entry(
+    path: &Path,
+    source: &Path,
+    rgba: &[u8],
+    width: i32,
+    height: i32,
+) -> io::Result<u64> {
+    if width <= 0 || height <= 0 || rgba.len() as u64 != width as u64 * height as u64 * 4 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "pixel data does not match dimensions",
+        ));
+    }
+
+    let payload = lz4_flex::block::compress_prepend_size(rgba);
+    let (source_size, source_mtime) = source_fingerprint(source).unwrap_or((0, 0));
+    let header = Header {
+        format: PixelFormat::Rgba8,
+        width: width as u32,
+        height: height as u32,
+        source_size,
+        source_mtime,
+        checksum: crc32fast::hash(&payload),
+    };
+
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let temp = tempfile::Builder::new()
+        .prefix(TEMP_PREFIX)
+        .tempfile_in(dir)?;
+    {
+        let mut writer = BufWriter::new(temp.as_file());
+        writer.write_all(&header.to_bytes())?;
+        writer.write_all(&payload)?;
+        writer.flush()?;
+    }
+    temp.as_file().sync_all()?;
+    temp.persist(path).map_err(|e| e.error)?;
+
+    Ok((HEADER_LEN + payload.len()) as u64)
+}
+
+/// Read and verify an entry; any mismatch is treated as a miss
+pub fn read_entry(path: &Path) -> Option<(Vec<u8>, i32, i32)> {
+    let mut file = fs::File::open(path).ok()?;
+    let mut buf = [0u8; HEADER_LEN];
+    file.read_exact(&mut buf).ok()?;
+    let Some(header) = Header::from_bytes(&buf) else {
+        log::debug!("Unrecognised cache header in {}", path.display());
+        return None;
+    };
+
+    let expected_size = header.width as u64 * header.height as u64 * 4;
+    if header.width == 0 || header.height == 0 || expected_size > MAX_PIXEL_BYTES {
+        return None;
+    }
+
+    let mut payload = Vec::new();
+    if let Err(e) = file.read_to_end(&mut payload) {
+        log::debug!("Failed to read data from cache {}: {}", path.display(), e);
+        return None;
+    }
+    if crc32fast::hash(&payload) != header.checksum {
+        log::debug!("Checksum mismatch in cache {}", path.display());
+        return None;
+    }
+
+    let rgba = match lz4_flex::block::decompress_size_prepended(&payload) {
+        Ok(data) => data,
+        Err(e) => {
+            log::debug!("Failed to decompress cache {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    if rgba.len() as u64 != expected_size {
+        return None;
+    }
+    Some((rgba, header.width as i32, header.height as i32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn header_is_little_endian() {
+        let header = Header {
+            format: PixelFormat::Rgba8,
+            width: 0x0102,
+            height: 3,
+            source_size: 7,
+            source_mtime: 9,
+            checksum: 0xAABBCCDD,
+        };
+        let bytes = header.to_bytes();
+        assert_eq!(&bytes[0..4], b"PNTY");
+        assert_eq!(&bytes[8..12], &[0x02, 0x01, 0, 0]);
+        assert_eq!(&bytes[32..36], &[0xDD, 0xCC, 0xBB, 0xAA]);
+        assert_eq!(Header::from_bytes(&bytes), Some(header));
+    }
+
+    #[test]
+    fn unknown_version_is_rejected() {
+        let mut bytes = Header {
+            format: PixelFormat::Rgba8,
+            width: 1,
+            height: 1,
+            source_size: 0,
+            source_mtime: 0,
+            checksum: 0,
+        }
+        .to_bytes();
+        bytes[4] = 99;
+        assert_eq!(Header::from_bytes(&bytes), None);
+    }
+
+    #[test]
+    fn records_source_fingerprint() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("a.png");
+        let entry = dir.path().join("a.raw");
+        fs::write(&source, b"image").unwrap();
+        write_entry(&entry, &source, &[0u8; 4], 1, 1).unwrap();
+
+        let header = read_header(&entry).unwrap();
+        assert_eq!(header.source_size, 5);
+        assert!(header.matches_source(&source));
+
+        fs::write(&source, b"edited image").unwrap();
+        assert!(!header.matches_source(&source));
+    }
+
+    #[test]
+    fn flipped_payload_byte_fails_checksum() {
+        let dir = tempdir().unwrap();
+        let entry = dir.path().join("a.raw");
+        let data: Vec<u8> = (0..400).map(|i| i as u8).collect();
+        write_entry(&entry, Path::new("missing.png"), &data, 10, 10).unwrap();
+
+        let mut bytes = fs::read(&entry).unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xFF;
+        fs::write(&entry, bytes).unwrap();
+        assert!(read_entry(&entry).is_none());
+    }
+
+    #[test]
+    fn write_leaves_no_temp_files() {
+        let dir = tempdir().unwrap();
+        let entry = dir.path().join("a.raw");
+        write_entry(&entry, Path::new("missing.png"), &[0u8; 40], 10, 1).unwrap();
+        let names: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, vec![std::ffi::OsString::from("a.raw")]);
+    }
+
+    #[test]
+    fn mismatched_dimensions_are_rejected() {
+        let dir = tempdir().unwrap();
+        let entry = dir.path().join("a.raw");
+        assert!(write_entry(&entry, Path::new("missing.png"), &[0u8; 39], 10, 1).is_err());
+        assert!(!entry.exists());
+    }
+}
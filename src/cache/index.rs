@@ -0,0 +1,280 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const INDEX_FILE: &str = "index";
+const INDEX_HEADER: &str = "pantry-cache-index 1";
+const FLUSH_EVERY_CHANGES: usize = 64;
+const FLUSH_EVERY: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IndexEntry {
+    pub size: u64,
+    /// Seconds since the Unix epoch
+    pub last_access: u64,
+}
+
+/// Persistent size and last-access bookkeeping for the cache directory,
+/// so eviction never has to list and stat every entry
+pub struct CacheIndex {
+    cache_dir: PathBuf,
+    entries: HashMap<String, IndexEntry>,
+    total_size: u64,
+    dirty: usize,
+    last_flush: Instant,
+}
+
+impl CacheIndex {
+    /// Load the saved index and reconcile it with the entries actually on disk
+    pub fn load(cache_dir: &Path) -> Self {
+        let mut entries = read_index_file(&cache_dir.join(INDEX_FILE)).unwrap_or_default();
+        let mut dirty = 0;
+
+        let on_disk: HashSet<String> = fs::read_dir(cache_dir)
+            .map(|rd| {
+                rd.flatten()
+                    .filter_map(|e| e.file_name().into_string().ok())
+                    .filter(|name| name.ends_with(".raw"))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let before = entries.len();
+        entries.retain(|name, _| on_disk.contains(name));
+        dirty += before - entries.len();
+
+        // Entries written by another process since the last flush
+        for name in on_disk {
+            if entries.contains_key(&name) {
+                continue;
+            }
+            if let Ok(meta) = fs::metadata(cache_dir.join(&name)) {
+                let last_access = meta.modified().map(to_secs).unwrap_or(0);
+                entries.insert(
+                    name,
+                    IndexEntry {
+                        size: meta.len(),
+                        last_access,
+                    },
+                );
+                dirty += 1;
+            }
+        }
+
+        let total_size = entries.values().map(|e| e.size).sum();
+        Self {
+            cache_dir: cache_dir.to_path_buf(),
+            entries,
+            total_size,
+            dirty,
+            last_flush: Instant::now(),
+        }
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.total_size
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&String, &IndexEntry)> {
+        self.entries.iter()
+    }
+
+    pub fn record(&mut self, name: String, size: u64) {
+        let entry = IndexEntry {
+            size,
+            last_access: now_secs(),
+        };
+        if let Some(old) = self.entries.insert(name, entry) {
+            self.total_size -= old.size;
+        }
+        self.total_size += size;
+        self.mark_dirty();
+    }
+
+    pub fn touch(&mut self, name: &str) {
+        if let Some(entry) = self.entries.get_mut(name) {
+            entry.last_access = now_secs();
+            self.mark_dirty();
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<IndexEntry> {
+        let entry = self.entries.remove(name)?;
+        self.total_size -= entry.size;
+        self.mark_dirty();
+        Some(entry)
+    }
+
+    /// Least recently used names whose removal brings the total down to `target`
+    pub fn eviction_candidates(&self, target: u64) -> Vec<String> {
+        if self.total_size <= target {
+            return Vec::new();
+        }
+        let mut by_age: Vec<(&String, &IndexEntry)> = self.entries.iter().collect();
+        by_age.sort_by_key(|(_, e)| e.last_access);
+
+        let mut remaining = self.total_size;
+        let mut names = Vec::new();
+        for (name, entry) in by_age {
+            if remaining <= target {
+                break;
+            }
+            remaining -= entry.size;
+            names.push(name.clone());
+        }
+        names
+    }
+
+    fn mark_dirty(&mut self) {
+        self.dirty += 1;
+        if (self.dirty >= FLUSH_EVERY_CHANGES || self.last_flush.elapsed() >= FLUSH_EVERY)
+            && let Err(e) = self.flush()
+        {
+            log::debug!("Failed to write cache index: {}", e);
+        }
+    }
+
+    /// Write the index atomically next to the entries
+    pub fn flush(&mut self) -> io::Result<()> {
+        let temp = tempfile::Builder::new()
+            .prefix(super::format::TEMP_PREFIX)
+            .tempfile_in(&self.cache_dir)?;
+        {
+            let mut writer = BufWriter::new(temp.as_file());
+            writeln!(writer, "{}", INDEX_HEADER)?;
+            for (name, entry) in &self.entries {
+                writeln!(writer, "{}\t{}\t{}", name, entry.size, entry.last_access)?;
+            }
+            writer.flush()?;
+        }
+        temp.persist(self.cache_dir.join(INDEX_FILE))
+            .map_err(|e| e.error)?;
+        self.dirty = 0;
+        self.last_flush = Instant::now();
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub fn set_last_access(&mut self, name: &str, last_access: u64) {
+        if let Some(entry) = self.entries.get_mut(name) {
+            entry.last_access = last_access;
+        }
+    }
+}
+
+impl Drop for CacheIndex {
+    fn drop(&mut self) {
+        if self.dirty > 0 {
+            let _ = self.flush();
+        }
+    }
+}
+
+fn read_index_file(path: &Path) -> Option<HashMap<String, IndexEntry>> {
+    let file = fs::File::open(path).ok()?;
+    let mut lines = BufReader::new(file).lines().map_while(Result::ok);
+    if lines.next()? != INDEX_HEADER {
+        return None;
+    }
+
+    let mut entries = HashMap::new();
+    for line in lines {
+        let mut fields = line.split('\t');
+        let (Some(name), Some(size), Some(last_access)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let (Ok(size), Ok(last_access)) = (size.parse(), last_access.parse()) else {
+            continue;
+        };
+        entries.insert(name.to_string(), IndexEntry { size, last_access });
+    }
+    Some(entries)
+}
+
+pub fn to_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn now_secs() -> u64 {
+    to_secs(SystemTime::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn flush_and_reload_round_trip() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("a.raw"), [0u8; 10]).unwrap();
+
+        let mut index = CacheIndex::load(dir.path());
+        index.record("a.raw".to_string(), 10);
+        index.set_last_access("a.raw", 42);
+        index.flush().unwrap();
+        drop(index);
+
+        let index = CacheIndex::load(dir.path());
+        let entry = index.entries().find(|(n, _)| *n == "a.raw").unwrap().1;
+        assert_eq!(entry.last_access, 42);
+        assert_eq!(index.total_size(), 10);
+    }
+
+    #[test]
+    fn load_picks_up_unindexed_files_and_drops_missing() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("kept.raw"), [0u8; 5]).unwrap();
+        fs::write(dir.path().join("gone.raw"), [0u8; 7]).unwrap();
+        let mut index = CacheIndex::load(dir.path());
+        index.flush().unwrap();
+        drop(index);
+
+        fs::remove_file(dir.path().join("gone.raw")).unwrap();
+        fs::write(dir.path().join("new.raw"), [0u8; 3]).unwrap();
+
+        let index = CacheIndex::load(dir.path());
+        let mut names: Vec<&String> = index.entries().map(|(n, _)| n).collect();
+        names.sort();
+        assert_eq!(names, vec!["kept.raw", "new.raw"]);
+        assert_eq!(index.total_size(), 8);
+    }
+
+    #[test]
+    fn eviction_candidates_oldest_first() {
+        let dir = tempdir().unwrap();
+        let mut index = CacheIndex::load(dir.path());
+        for (name, access) in [("a.raw", 30), ("b.raw", 10), ("c.raw", 20)] {
+            index.record(name.to_string(), 100);
+            index.set_last_access(name, access);
+        }
+        assert_eq!(index.eviction_candidates(300), Vec::<String>::new());
+        assert_eq!(index.eviction_candidates(150), vec!["b.raw", "c.raw"]);
+    }
+
+    #[test]
+    fn record_replaces_size() {
+        let dir = tempdir().unwrap();
+        let mut index = CacheIndex::load(dir.path());
+        index.record("a.raw".to_string(), 100);
+        index.record("a.raw".to_string(), 40);
+        assert_eq!(index.total_size(), 40);
+        index.remove("a.raw");
+        assert_eq!(index.total_size(), 0);
+    }
+
+    #[test]
+    fn corrupt_index_is_ignored() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join(INDEX_FILE), b"garbage").unwrap();
+        fs::write(dir.path().join("a.raw"), [0u8; 4]).unwrap();
+        let index = CacheIndex::load(dir.path());
+        assert_eq!(index.total_size(), 4);
+    }
+}
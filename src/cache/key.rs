@@ -0,0 +1,174 @@
+use super::format::source_fingerprint;
+use glib::{Checksum, ChecksumType};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+/// How cache file names are derived from a source file
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheKeyMode {
+    /// Hash of (absolute path, size, mtime): cheap, invalidates on edit
+    #[default]
+    Metadata,
+    /// Hash of the file bytes: survives moves and shares entries between copies
+    Content,
+}
+
+/// Stable, fixed-length cache key for a source file
+pub fn cache_key(path: &Path, mode: CacheKeyMode, hashes: &ContentHashes) -> String {
+    match mode {
+        CacheKeyMode::Metadata => metadata_hash(path),
+        CacheKeyMode::Content => hashes.hash(path).unwrap_or_else(|| metadata_hash(path)),
+    }
+}
+
+/// Size and mtime, as `source_fingerprint` reports them
+type Fingerprint = (u64, u64);
+
+/// Content hashes by path, reused while a file keeps its size and mtime
+#[derive(Debug, Default)]
+pub struct ContentHashes {
+    known: Mutex<HashMap<PathBuf, (Fingerprint, String)>>,
+}
+
+impl ContentHashes {
+    /// The hash of this version of the file if it was computed before; never reads the file
+    pub fn known(&self, path: &Path) -> Option<String> {
+        let fingerprint = source_fingerprint(path)?;
+        let known = self.known.lock().ok()?;
+        known
+            .get(path)
+            .filter(|(known_fingerprint, _)| *known_fingerprint == fingerprint)
+            .map(|(_, hash)| hash.clone())
+    }
+
+    pub fn hash(&self, path: &Path) -> Option<String> {
+        if let Some(hash) = self.known(path) {
+            return Some(hash);
+        }
+        // Taken before reading, so an edit made while hashing forces a new hash next time
+        let fingerprint = source_fingerprint(path)?;
+        let hash = content_hash(path)?;
+        if let Ok(mut known) = self.known.lock() {
+            known.insert(path.to_path_buf(), (fingerprint, hash.clone()));
+        }
+        Some(hash)
+    }
+}
+
+pub fn metadata_hash(path: &Path) -> String {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let (size, mtime) = fs::metadata(path)
+        .map(|meta| {
+            let mtime = meta
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_nanos())
+                .unwrap_or(0);
+            (meta.len(), mtime)
+        })
+        .unwrap_or((0, 0));
+
+    let mut checksum = new_checksum();
+    checksum.update(absolute.as_os_str().as_encoded_bytes());
+    checksum.update(&size.to_le_bytes());
+    checksum.update(&mtime.to_le_bytes());
+    checksum.string().unwrap_or_default()
+}
+
+pub fn content_hash(path: &Path) -> Option<String> {
+    let mut file = fs::File::open(path).ok()?;
+    let mut checksum = new_checksum();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf).ok()?;
+        if n == 0 {
+            break;
+        }
+        checksum.update(&buf[..n]);
+    }
+    checksum.string()
+}
+
+fn new_checksum() -> Checksum {
+    Checksum::new(ChecksumType::Sha256).expect("SHA-256 is always supported by GLib")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn metadata_hash_is_stable() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("a.png");
+        fs::write(&path, b"image").unwrap();
+        assert_eq!(metadata_hash(&path), metadata_hash(&path));
+        assert_eq!(metadata_hash(&path).len(), 64);
+    }
+
+    #[test]
+    fn metadata_hash_changes_with_mtime() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("a.png");
+        fs::write(&path, b"image").unwrap();
+        let before = metadata_hash(&path);
+
+        let later = std::time::SystemTime::now() + std::time::Duration::from_secs(10);
+        filetime::set_file_mtime(&path, filetime::FileTime::from_system_time(later)).unwrap();
+        assert_ne!(before, metadata_hash(&path));
+    }
+
+    #[test]
+    fn content_hash_ignores_location() {
+        let dir = tempdir().unwrap();
+        let a = dir.path().join("a.png");
+        let b = dir.path().join("moved.png");
+        fs::write(&a, b"same bytes").unwrap();
+        fs::write(&b, b"same bytes").unwrap();
+        assert_eq!(content_hash(&a), content_hash(&b));
+        assert_ne!(metadata_hash(&a), metadata_hash(&b));
+    }
+
+    #[test]
+    fn content_hash_differs_for_different_bytes() {
+        let dir = tempdir().unwrap();
+        let a = dir.path().join("a.png");
+        let b = dir.path().join("b.png");
+        fs::write(&a, b"one").unwrap();
+        fs::write(&b, b"two").unwrap();
+        assert_ne!(content_hash(&a), content_hash(&b));
+    }
+
+    #[test]
+    fn content_mode_falls_back_for_missing_file() {
+        let path = Path::new("/nonexistent/pantry/a.png");
+        let hashes = ContentHashes::default();
+        assert_eq!(
+            cache_key(path, CacheKeyMode::Content, &hashes),
+            cache_key(path, CacheKeyMode::Metadata, &hashes)
+        );
+    }
+
+    #[test]
+    fn content_hashes_are_reused_until_the_file_changes() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("a.png");
+        fs::write(&path, b"one").unwrap();
+        let hashes = ContentHashes::default();
+        assert_eq!(hashes.known(&path), None);
+        let first = hashes.hash(&path).unwrap();
+        assert_eq!(hashes.known(&path), Some(first.clone()));
+
+        fs::write(&path, b"three").unwrap();
+        assert_eq!(hashes.known(&path), None);
+        assert_ne!(hashes.hash(&path).unwrap(), first);
+    }
+}
@@ -1,14 +1,43 @@
-use crate::constants::CACHE_MAX_SIZE_BYTES;
+pub mod format;
+pub mod index;
+pub mod key;
+pub mod thumbnails;
+
+use crate::config::CacheConfig;
+use index::{CacheIndex, IndexEntry};
 use std::fs;
-use std::io::{self, BufWriter, Read, Write};
+use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub use key::CacheKeyMode;
+pub use thumbnails::FreedesktopThumbnails;
 
 pub trait CacheAdapter: Send + Sync {
-    fn get_cache_path(&self, category: &str, original_path: &Path) -> PathBuf;
+    fn get_cache_path(
+        &self,
+        category: &str,
+        original_path: &Path,
+        max_width: i32,
+        max_height: i32,
+    ) -> PathBuf;
+    /// `get_cache_path` if it needs nothing more than file metadata, so it is cheap enough for
+    /// the UI thread; `None` when the key would mean reading the file
+    fn fast_cache_path(
+        &self,
+        category: &str,
+        original_path: &Path,
+        max_width: i32,
+        max_height: i32,
+    ) -> Option<PathBuf> {
+        Some(self.get_cache_path(category, original_path, max_width, max_height))
+    }
     fn is_cache_valid(&self, cache_path: &Path, original_path: &Path) -> bool;
     fn save_raw_cache(
         &self,
         path: &Path,
+        original_path: &Path,
         raw_data: &[u8],
         width: i32,
         height: i32,
@@ -19,10 +48,15 @@ pub trait CacheAdapter: Send + Sync {
 #[derive(Clone)]
 pub struct CacheManager {
     cache_dir: PathBuf,
+    key_mode: CacheKeyMode,
+    max_size: u64,
+    /// Loaded on first use so startup never scans the cache directory
+    index: Arc<Mutex<Option<CacheIndex>>>,
+    content_hashes: Arc<key::ContentHashes>,
 }
 
 impl CacheManager {
-    pub fn new() -> Self {
+    pub fn new(config: &CacheConfig) -> Self {
         let mut cache_dir = dirs::cache_dir()
             .unwrap_or_else(|| PathBuf::from("."))
             .join("pantry");
@@ -32,140 +66,264 @@ impl CacheManager {
             cache_dir = PathBuf::from(".");
         }
 
-        Self { cache_dir }
+        Self::with_config(cache_dir, config)
+    }
+
+    fn with_config(cache_dir: PathBuf, config: &CacheConfig) -> Self {
+        Self {
+            cache_dir,
+            key_mode: config.key,
+            max_size: config.max_size,
+            index: Arc::new(Mutex::new(None)),
+            content_hashes: Arc::new(key::ContentHashes::default()),
+        }
     }
 
     #[cfg(test)]
     pub fn with_cache_dir(cache_dir: PathBuf) -> Self {
-        Self { cache_dir }
+        Self::with_config(cache_dir, &CacheConfig::default())
+    }
+
+    fn entry_path(&self, key: &str, max_width: i32, max_height: i32) -> PathBuf {
+        self.cache_dir
+            .join(format!("{}_{}x{}.raw", key, max_width, max_height))
+    }
+
+    fn with_index<R>(&self, f: impl FnOnce(&mut CacheIndex) -> R) -> Option<R> {
+        let mut guard = self.index.lock().ok()?;
+        let index = guard.get_or_insert_with(|| CacheIndex::load(&self.cache_dir));
+        Some(f(index))
     }
 }
 
+fn entry_name(path: &Path) -> Option<String> {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .map(|n| n.to_string())
+}
+
 impl CacheAdapter for CacheManager {
-    fn get_cache_path(&self, category: &str, original_path: &Path) -> PathBuf {
-        self.cache_dir.join(format!(
-            "{}_{}.raw",
-            category,
-            crate::utils::path_to_safe_filename(original_path)
-        ))
+    /// Entries are keyed by source file only, so categories sharing an image share its cache
+    fn get_cache_path(
+        &self,
+        _category: &str,
+        original_path: &Path,
+        max_width: i32,
+        max_height: i32,
+    ) -> PathBuf {
+        let key = key::cache_key(original_path, self.key_mode, &self.content_hashes);
+        self.entry_path(&key, max_width, max_height)
     }
 
+    /// Content keys are only known once a worker has hashed the file
+    fn fast_cache_path(
+        &self,
+        _category: &str,
+        original_path: &Path,
+        max_width: i32,
+        max_height: i32,
+    ) -> Option<PathBuf> {
+        let key = match self.key_mode {
+            CacheKeyMode::Metadata => key::metadata_hash(original_path),
+            CacheKeyMode::Content => self.content_hashes.known(original_path)?,
+        };
+        Some(self.entry_path(&key, max_width, max_height))
+    }
+
+    /// Valid while the source still has the size and mtime recorded in the entry header
     fn is_cache_valid(&self, cache_path: &Path, original_path: &Path) -> bool {
-        if !cache_path.exists() {
-            return false;
-        }
-        match (
-            original_path.metadata().and_then(|m| m.modified()),
-            cache_path.metadata().and_then(|m| m.modified()),
-        ) {
-            (Ok(orig_time), Ok(cache_time)) => cache_time >= orig_time,
-            _ => false,
-        }
+        format::read_header(cache_path).is_some_and(|header| header.matches_source(original_path))
     }
 
     fn save_raw_cache(
         &self,
         path: &Path,
+        original_path: &Path,
         raw_data: &[u8],
         width: i32,
         height: i32,
     ) -> io::Result<()> {
-        let file = fs::File::create(path)?;
-        let mut writer = BufWriter::new(file);
-        writer.write_all(&width.to_ne_bytes())?;
-        writer.write_all(&height.to_ne_bytes())?;
-        let compressed = lz4_flex::block::compress_prepend_size(raw_data);
-        writer.write_all(&compressed)?;
-        writer.flush()?;
-
+        let size = format::write_entry(path, original_path, raw_data, width, height)?;
+        if let Some(name) = entry_name(path) {
+            self.with_index(|index| index.record(name, size));
+        }
         self.evict_if_needed();
 
         Ok(())
     }
 
     fn load_raw_cache(&self, path: &Path) -> Option<(Vec<u8>, i32, i32)> {
-        let mut file = match fs::File::open(path) {
-            Ok(f) => f,
-            Err(e) => {
-                log::debug!("Failed to open cache file {}: {}", path.display(), e);
-                return None;
-            }
-        };
-        let mut width_buf = [0u8; 4];
-        let mut height_buf = [0u8; 4];
-        if let Err(e) = file.read_exact(&mut width_buf) {
-            log::debug!("Failed to read width from cache {}: {}", path.display(), e);
-            return None;
-        }
-        if let Err(e) = file.read_exact(&mut height_buf) {
-            log::debug!("Failed to read height from cache {}: {}", path.display(), e);
-            return None;
+        let entry = format::read_entry(path)?;
+        if let Some(name) = entry_name(path) {
+            self.with_index(|index| index.touch(&name));
         }
+        Some(entry)
+    }
+}
 
-        let width = i32::from_ne_bytes(width_buf);
-        let height = i32::from_ne_bytes(height_buf);
+/// Summary of the pantry cache directory
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub total_bytes: u64,
+    pub least_recent_access: Option<SystemTime>,
+    pub most_recent_access: Option<SystemTime>,
+}
 
-        let expected_size = (width as u64) * (height as u64) * 4;
-        if width <= 0 || height <= 0 || expected_size > 100 * 1024 * 1024 {
-            return None;
-        }
+/// What a clear or prune pass removed
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Removed {
+    pub entries: usize,
+    pub bytes: u64,
+}
 
-        let mut compressed = Vec::new();
-        if let Err(e) = file.read_to_end(&mut compressed) {
-            log::debug!("Failed to read data from cache {}: {}", path.display(), e);
-            return None;
-        }
+impl std::ops::AddAssign for Removed {
+    fn add_assign(&mut self, other: Removed) {
+        self.entries += other.entries;
+        self.bytes += other.bytes;
+    }
+}
+
+impl CacheManager {
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache_dir
+    }
 
-        // Decompress; old uncompressed caches fail here → return None → silent re-cache
-        let raw_data = match lz4_flex::block::decompress_size_prepended(&compressed) {
-            Ok(data) => data,
-            Err(e) => {
-                log::debug!("Failed to decompress cache {}: {}", path.display(), e);
-                return None;
+    pub fn stats(&self) -> CacheStats {
+        self.with_index(|index| {
+            let to_time = |secs: u64| UNIX_EPOCH + Duration::from_secs(secs);
+            let accesses = || index.entries().map(|(_, entry)| entry.last_access);
+            CacheStats {
+                entries: index.entries().count(),
+                total_bytes: index.total_size(),
+                least_recent_access: accesses().min().map(to_time),
+                most_recent_access: accesses().max().map(to_time),
             }
-        };
+        })
+        .unwrap_or_default()
+    }
 
-        if raw_data.len() == expected_size as usize {
-            Some((raw_data, width, height))
-        } else {
-            None
-        }
+    /// Drop the named entries from the index, returning them with their sizes for
+    /// `delete_entries`, which runs once the index lock is released
+    fn take_names(index: &mut CacheIndex, names: Vec<String>) -> Vec<(String, u64)> {
+        names
+            .into_iter()
+            .filter_map(|name| index.remove(&name).map(|entry| (name, entry.size)))
+            .collect()
     }
-}
 
-impl CacheManager {
-    fn evict_if_needed(&self) {
-        let mut entries: Vec<(PathBuf, u64, std::time::SystemTime)> = Vec::new();
-        let mut total_size: u64 = 0;
-
-        if let Ok(read_dir) = fs::read_dir(&self.cache_dir) {
-            for entry in read_dir.flatten() {
-                let path = entry.path();
-                if path.extension().and_then(|e| e.to_str()) == Some("raw")
-                    && let Ok(meta) = fs::metadata(&path)
-                {
-                    let size = meta.len();
-                    let mtime = meta.modified().unwrap_or(std::time::SystemTime::UNIX_EPOCH);
-                    total_size += size;
-                    entries.push((path, size, mtime));
+    fn delete_entries(&self, entries: Vec<(String, u64)>) -> Removed {
+        let mut removed = Removed::default();
+        for (name, size) in entries {
+            match fs::remove_file(self.cache_dir.join(&name)) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    log::debug!("Failed to remove cache entry {}: {}", name, e);
+                    continue;
                 }
             }
+            removed.entries += 1;
+            removed.bytes += size;
         }
+        removed
+    }
 
-        if total_size <= CACHE_MAX_SIZE_BYTES {
-            return;
-        }
+    fn remove_matching(&self, matches: impl Fn(&str, &IndexEntry) -> bool) -> Removed {
+        let taken = self
+            .with_index(|index| {
+                let names = index
+                    .entries()
+                    .filter(|(name, entry)| matches(name, entry))
+                    .map(|(name, _)| name.clone())
+                    .collect();
+                Self::take_names(index, names)
+            })
+            .unwrap_or_default();
+        self.delete_entries(taken)
+    }
 
-        entries.sort_by(|a, b| a.2.cmp(&b.2));
+    pub fn clear(&self) -> Removed {
+        let mut removed = self.remove_matching(|_, _| true);
+        removed += self.remove_stale_temp_files();
+        removed
+    }
 
-        for (path, size, _) in &entries {
-            if total_size <= CACHE_MAX_SIZE_BYTES {
-                break;
+    /// Temporary files left by interrupted writes, which the index never sees
+    fn remove_stale_temp_files(&self) -> Removed {
+        let mut removed = Removed::default();
+        let Ok(read_dir) = fs::read_dir(&self.cache_dir) else {
+            return removed;
+        };
+        let Some(cutoff) = SystemTime::now().checked_sub(format::STALE_TEMP_AGE) else {
+            return removed;
+        };
+        for entry in read_dir.flatten() {
+            if !entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(format::TEMP_PREFIX)
+            {
+                continue;
             }
-            if fs::remove_file(path).is_ok() {
-                total_size -= size;
+            let Ok(meta) = entry.metadata() else {
+                continue;
+            };
+            let stale = meta.modified().is_ok_and(|modified| modified < cutoff);
+            if stale && fs::remove_file(entry.path()).is_ok() {
+                removed.entries += 1;
+                removed.bytes += meta.len();
             }
         }
+        removed
+    }
+
+    /// Remove every cached size of one source file
+    pub fn remove_entries_for(&self, original_path: &Path) -> Removed {
+        let prefix = format!(
+            "{}_",
+            key::cache_key(original_path, self.key_mode, &self.content_hashes)
+        );
+        self.remove_matching(|name, _| name.starts_with(&prefix))
+    }
+
+    /// Drop entries unused for `max_age`, then the least recently used until under `max_size`,
+    /// and any stale temporary files
+    pub fn prune(&self, max_size: Option<u64>, max_age: Option<Duration>) -> Removed {
+        let mut removed = self.remove_stale_temp_files();
+        if let Some(cutoff) = max_age.and_then(|age| SystemTime::now().checked_sub(age)) {
+            let cutoff = index::to_secs(cutoff);
+            removed += self.remove_matching(|_, entry| entry.last_access < cutoff);
+        }
+        if let Some(max_size) = max_size {
+            removed += self.evict_to(max_size).unwrap_or_default();
+        }
+        removed
+    }
+
+    fn evict_to(&self, target: u64) -> Option<Removed> {
+        let taken = self.with_index(|index| {
+            let names = index.eviction_candidates(target);
+            Self::take_names(index, names)
+        })?;
+        Some(self.delete_entries(taken))
+    }
+
+    /// Once over the limit, evict down to 90% of it so saves don't trigger eviction one by one
+    fn evict_if_needed(&self) {
+        let over_limit = self
+            .with_index(|index| index.total_size() > self.max_size)
+            .unwrap_or(false);
+        if !over_limit {
+            return;
+        }
+        if let Some(removed) = self.evict_to(self.max_size / 10 * 9) {
+            log::debug!(
+                "Evicted {} cache entries ({} bytes)",
+                removed.entries,
+                removed.bytes
+            );
+        }
     }
 }
 
@@ -186,7 +344,9 @@ mod tests {
 
         let (w, h) = (10, 10);
         let data: Vec<u8> = vec![42u8; (w * h * 4) as usize];
-        cache.save_raw_cache(&path, &data, w, h).unwrap();
+        cache
+            .save_raw_cache(&path, Path::new("original.png"), &data, w, h)
+            .unwrap();
 
         let (loaded, lw, lh) = cache.load_raw_cache(&path).unwrap();
         assert_eq!(lw, w);
@@ -202,7 +362,9 @@ mod tests {
 
         let (w, h) = (100, 50);
         let data: Vec<u8> = vec![42u8; (w * h * 4) as usize];
-        cache.save_raw_cache(&path, &data, w, h).unwrap();
+        cache
+            .save_raw_cache(&path, Path::new("original.png"), &data, w, h)
+            .unwrap();
 
         let (loaded, lw, lh) = cache.load_raw_cache(&path).unwrap();
         assert_eq!(lw, w);
@@ -263,7 +425,9 @@ mod tests {
         let cached = dir.path().join("test.raw");
 
         fs::write(&original, b"image data").unwrap();
-        cache.save_raw_cache(&cached, &[0u8; 40], 10, 1).unwrap();
+        cache
+            .save_raw_cache(&cached, &original, &[0u8; 40], 10, 1)
+            .unwrap();
 
         assert!(cache.is_cache_valid(&cached, &original));
     }
@@ -288,7 +452,9 @@ mod tests {
         let cached = dir.path().join("test.raw");
 
         fs::write(&original, b"new image").unwrap();
-        cache.save_raw_cache(&cached, &[0u8; 40], 10, 1).unwrap();
+        cache
+            .save_raw_cache(&cached, &original, &[0u8; 40], 10, 1)
+            .unwrap();
 
         // Make original newer than cache by rewriting it after a tiny delay
         std::thread::sleep(std::time::Duration::from_millis(10));
@@ -298,56 +464,194 @@ mod tests {
     }
 
     #[test]
-    fn cache_valid_with_explicit_timestamps() {
+    fn cache_validity_follows_recorded_source_mtime() {
         let dir = tempdir().unwrap();
         let cache = make_cache_manager(dir.path());
         let original = dir.path().join("original.png");
         let cached = dir.path().join("test.raw");
 
-        fs::write(&original, b"image").unwrap();
-        cache.save_raw_cache(&cached, &[0u8; 40], 10, 1).unwrap();
-
         let cache_time = std::time::SystemTime::now();
         let orig_time = cache_time - std::time::Duration::from_secs(1);
 
-        filetime::set_file_mtime(&cached, filetime::FileTime::from_system_time(cache_time))
-            .unwrap();
+        fs::write(&original, b"image").unwrap();
         filetime::set_file_mtime(&original, filetime::FileTime::from_system_time(orig_time))
             .unwrap();
+        cache
+            .save_raw_cache(&cached, &original, &[0u8; 40], 10, 1)
+            .unwrap();
 
+        // The entry's own mtime no longer matters
+        filetime::set_file_mtime(&cached, filetime::FileTime::from_system_time(orig_time)).unwrap();
         assert!(cache.is_cache_valid(&cached, &original));
+
+        // A source restored with an older mtime is still a different file
+        let older = orig_time - std::time::Duration::from_secs(60);
+        filetime::set_file_mtime(&original, filetime::FileTime::from_system_time(older)).unwrap();
+        assert!(!cache.is_cache_valid(&cached, &original));
+    }
+
+    #[test]
+    fn legacy_entries_are_misses() {
+        let dir = tempdir().unwrap();
+        let cache = make_cache_manager(dir.path());
+        let path = dir.path().join("legacy.raw");
+        let mut legacy = Vec::new();
+        legacy.extend_from_slice(&10i32.to_ne_bytes());
+        legacy.extend_from_slice(&1i32.to_ne_bytes());
+        legacy.extend_from_slice(&lz4_flex::block::compress_prepend_size(&[0u8; 40]));
+        fs::write(&path, legacy).unwrap();
+
+        assert!(cache.load_raw_cache(&path).is_none());
+        assert!(!cache.is_cache_valid(&path, &path));
+    }
+
+    #[test]
+    fn get_cache_path_shared_across_categories() {
+        let dir = tempdir().unwrap();
+        let cache = make_cache_manager(dir.path());
+        let original = Path::new("/home/user/photo.png");
+        let a = cache.get_cache_path("wallpapers", original, 800, 600);
+        let b = cache.get_cache_path("favorites", original, 800, 600);
+        assert_eq!(a, b);
+        assert!(a.to_string_lossy().ends_with("_800x600.raw"));
     }
 
     #[test]
-    fn get_cache_path_contains_category_and_name() {
+    fn get_cache_path_bounded_for_long_paths() {
         let dir = tempdir().unwrap();
         let cache = make_cache_manager(dir.path());
-        let path = cache.get_cache_path("my_cat", Path::new("/home/user/photo.png"));
-        let name = path.file_name().unwrap().to_str().unwrap();
-        assert!(name.starts_with("my_cat_"));
-        assert!(name.ends_with(".raw"));
+        let long = format!("/{}/photo.png", "nested/".repeat(100));
+        let path = cache.get_cache_path("cat", Path::new(&long), 800, 600);
+        assert!(path.file_name().unwrap().len() < 255);
     }
 
     #[test]
-    fn eviction_deletes_oldest_when_over_limit() {
+    fn content_keys_survive_moves() {
+        let dir = tempdir().unwrap();
+        let config = CacheConfig {
+            key: CacheKeyMode::Content,
+            ..CacheConfig::default()
+        };
+        let cache = CacheManager::with_config(dir.path().to_path_buf(), &config);
+        let a = dir.path().join("a.png");
+        let b = dir.path().join("b.png");
+        fs::write(&a, b"same").unwrap();
+        fs::write(&b, b"same").unwrap();
+        assert_eq!(
+            cache.get_cache_path("x", &a, 800, 600),
+            cache.get_cache_path("y", &b, 800, 600)
+        );
+    }
+
+    #[test]
+    fn content_keys_need_a_hash_before_the_fast_path() {
+        let dir = tempdir().unwrap();
+        let config = CacheConfig {
+            key: CacheKeyMode::Content,
+            ..CacheConfig::default()
+        };
+        let cache = CacheManager::with_config(dir.path().to_path_buf(), &config);
+        let a = dir.path().join("a.png");
+        fs::write(&a, b"image").unwrap();
+        assert_eq!(cache.fast_cache_path("x", &a, 800, 600), None);
+        let path = cache.get_cache_path("x", &a, 800, 600);
+        assert_eq!(cache.fast_cache_path("x", &a, 800, 600), Some(path));
+    }
+
+    #[test]
+    fn get_cache_path_differs_by_size() {
         let dir = tempdir().unwrap();
         let cache = make_cache_manager(dir.path());
+        let original = Path::new("/home/user/photo.png");
+        let small = cache.get_cache_path("my_cat", original, 800, 600);
+        let large = cache.get_cache_path("my_cat", original, 1792, 1280);
+        assert_ne!(small, large);
+    }
 
-        // Create 3 files, set total > CACHE_MAX_SIZE_BYTES for eviction
-        // We can't easily hit 1GB in tests, so we'll test the eviction logic
-        // by creating files and verifying the oldest is deleted
+    fn make_limited_cache_manager(dir: &Path, max_size: u64) -> CacheManager {
+        let config = CacheConfig {
+            max_size,
+            ..CacheConfig::default()
+        };
+        CacheManager::with_config(dir.to_path_buf(), &config)
+    }
+
+    fn set_last_access(cache: &CacheManager, path: &Path, secs: u64) {
+        let name = entry_name(path).unwrap();
+        cache.with_index(|index| index.set_last_access(&name, secs));
+    }
+
+    #[test]
+    fn entries_under_limit_are_kept() {
+        let dir = tempdir().unwrap();
+        let cache = make_cache_manager(dir.path());
         let file1 = dir.path().join("old.raw");
         let file2 = dir.path().join("new.raw");
 
         let data = vec![0u8; 100];
-        cache.save_raw_cache(&file1, &data, 10, 1).unwrap();
-        cache.save_raw_cache(&file2, &data, 10, 1).unwrap();
+        cache
+            .save_raw_cache(&file1, Path::new("original.png"), &data, 5, 5)
+            .unwrap();
+        cache
+            .save_raw_cache(&file2, Path::new("original.png"), &data, 5, 5)
+            .unwrap();
 
-        // Both should exist since total << CACHE_MAX_SIZE_BYTES
         assert!(file1.exists());
         assert!(file2.exists());
     }
 
+    #[test]
+    fn eviction_removes_least_recently_used_in_a_batch() {
+        let dir = tempdir().unwrap();
+        let data = vec![0u8; 400];
+        let entry_size =
+            (format::HEADER_LEN + lz4_flex::block::compress_prepend_size(&data).len()) as u64;
+        let cache = make_limited_cache_manager(dir.path(), entry_size * 3);
+        let paths: Vec<PathBuf> = (0..3)
+            .map(|i| dir.path().join(format!("{i}.raw")))
+            .collect();
+        for (i, path) in paths.iter().enumerate() {
+            cache
+                .save_raw_cache(path, Path::new("original.png"), &data, 10, 10)
+                .unwrap();
+            set_last_access(&cache, path, 100 + i as u64);
+        }
+        // Reading the oldest entry makes it the most recently used
+        assert!(cache.load_raw_cache(&paths[0]).is_some());
+
+        let fourth = dir.path().join("3.raw");
+        cache
+            .save_raw_cache(&fourth, Path::new("original.png"), &data, 10, 10)
+            .unwrap();
+
+        // Down to the 90% watermark: two entries go, least recently used first
+        assert!(paths[0].exists());
+        assert!(!paths[1].exists());
+        assert!(!paths[2].exists());
+        assert!(fourth.exists());
+        assert_eq!(cache.stats().total_bytes, entry_size * 2);
+    }
+
+    #[test]
+    fn index_survives_restart() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("a.raw");
+        let cache = make_cache_manager(dir.path());
+        cache
+            .save_raw_cache(&path, Path::new("original.png"), &[0u8; 40], 10, 1)
+            .unwrap();
+        set_last_access(&cache, &path, 1234);
+        drop(cache);
+
+        let cache = make_cache_manager(dir.path());
+        let stats = cache.stats();
+        assert_eq!(stats.entries, 1);
+        assert_eq!(
+            stats.least_recent_access,
+            Some(UNIX_EPOCH + Duration::from_secs(1234))
+        );
+    }
+
     #[test]
     fn save_overwrites_existing_cache() {
         let dir = tempdir().unwrap();
@@ -357,12 +661,146 @@ mod tests {
         let data1 = vec![1u8; 40];
         let data2 = vec![2u8; 40];
 
-        cache.save_raw_cache(&path, &data1, 10, 1).unwrap();
+        cache
+            .save_raw_cache(&path, Path::new("original.png"), &data1, 10, 1)
+            .unwrap();
         let (loaded, _, _) = cache.load_raw_cache(&path).unwrap();
         assert_eq!(loaded, data1);
 
-        cache.save_raw_cache(&path, &data2, 10, 1).unwrap();
+        cache
+            .save_raw_cache(&path, Path::new("original.png"), &data2, 10, 1)
+            .unwrap();
         let (loaded, _, _) = cache.load_raw_cache(&path).unwrap();
         assert_eq!(loaded, data2);
     }
+
+    #[test]
+    fn stats_counts_entries() {
+        let dir = tempdir().unwrap();
+        let cache = make_cache_manager(dir.path());
+        let data = vec![0u8; 40];
+        for name in ["a.raw", "b.raw"] {
+            cache
+                .save_raw_cache(
+                    &dir.path().join(name),
+                    Path::new("original.png"),
+                    &data,
+                    10,
+                    1,
+                )
+                .unwrap();
+        }
+        fs::write(dir.path().join("unrelated.txt"), b"x").unwrap();
+
+        let stats = cache.stats();
+        assert_eq!(stats.entries, 2);
+        assert!(stats.total_bytes > 0);
+        assert!(stats.least_recent_access.is_some());
+    }
+
+    #[test]
+    fn clear_removes_only_cache_entries() {
+        let dir = tempdir().unwrap();
+        let cache = make_cache_manager(dir.path());
+        let entry = dir.path().join("a.raw");
+        let other = dir.path().join("keep.txt");
+        cache
+            .save_raw_cache(&entry, Path::new("original.png"), &[0u8; 40], 10, 1)
+            .unwrap();
+        fs::write(&other, b"x").unwrap();
+
+        assert_eq!(cache.clear().entries, 1);
+        assert!(!entry.exists());
+        assert!(other.exists());
+    }
+
+    #[test]
+    fn clear_sweeps_stale_temp_files() {
+        let dir = tempdir().unwrap();
+        let cache = make_cache_manager(dir.path());
+        let stale = dir.path().join(".tmpAbC123");
+        let writing = dir.path().join(".tmpXyZ789");
+        fs::write(&stale, b"partial").unwrap();
+        fs::write(&writing, b"partial").unwrap();
+        let long_ago = SystemTime::now() - Duration::from_secs(2 * 3600);
+        filetime::set_file_mtime(&stale, filetime::FileTime::from_system_time(long_ago)).unwrap();
+
+        assert_eq!(
+            cache.clear(),
+            Removed {
+                entries: 1,
+                bytes: 7
+            }
+        );
+        assert!(!stale.exists());
+        assert!(writing.exists());
+    }
+
+    #[test]
+    fn remove_entries_for_matches_all_sizes() {
+        let dir = tempdir().unwrap();
+        let cache = make_cache_manager(dir.path());
+        let original = dir.path().join("photo.png");
+        let other = dir.path().join("other.png");
+        fs::write(&original, b"image").unwrap();
+        fs::write(&other, b"other").unwrap();
+
+        for (w, h) in [(800, 600), (1792, 1280)] {
+            let path = cache.get_cache_path("cat", &original, w, h);
+            cache
+                .save_raw_cache(&path, &original, &[0u8; 40], 10, 1)
+                .unwrap();
+        }
+        let kept = cache.get_cache_path("cat", &other, 800, 600);
+        cache
+            .save_raw_cache(&kept, &other, &[0u8; 40], 10, 1)
+            .unwrap();
+
+        assert_eq!(cache.remove_entries_for(&original).entries, 2);
+        assert!(kept.exists());
+    }
+
+    #[test]
+    fn prune_by_age_removes_old_entries() {
+        let dir = tempdir().unwrap();
+        let cache = make_cache_manager(dir.path());
+        let old = dir.path().join("old.raw");
+        let new = dir.path().join("new.raw");
+        cache
+            .save_raw_cache(&old, Path::new("original.png"), &[0u8; 40], 10, 1)
+            .unwrap();
+        cache
+            .save_raw_cache(&new, Path::new("original.png"), &[0u8; 40], 10, 1)
+            .unwrap();
+
+        let long_ago = SystemTime::now() - Duration::from_secs(40 * 24 * 3600);
+        set_last_access(&cache, &old, index::to_secs(long_ago));
+
+        let removed = cache.prune(None, Some(Duration::from_secs(30 * 24 * 3600)));
+        assert_eq!(removed.entries, 1);
+        assert!(!old.exists());
+        assert!(new.exists());
+    }
+
+    #[test]
+    fn prune_by_size_removes_oldest_first() {
+        let dir = tempdir().unwrap();
+        let cache = make_cache_manager(dir.path());
+        let old = dir.path().join("old.raw");
+        let new = dir.path().join("new.raw");
+        cache
+            .save_raw_cache(&old, Path::new("original.png"), &[0u8; 40], 10, 1)
+            .unwrap();
+        cache
+            .save_raw_cache(&new, Path::new("original.png"), &[0u8; 40], 10, 1)
+            .unwrap();
+
+        set_last_access(&cache, &old, index::now_secs() - 60);
+
+        let one_entry = fs::metadata(&new).unwrap().len();
+        let removed = cache.prune(Some(one_entry), None);
+        assert_eq!(removed.entries, 1);
+        assert!(!old.exists());
+        assert!(new.exists());
+    }
 }
@@ -0,0 +1,407 @@
+use super::CacheAdapter;
+use gdk_pixbuf::{Colorspace, InterpType, Pixbuf};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Thumbnail flavors from the freedesktop.org thumbnail spec, smallest first
+const FLAVORS: &[(&str, i32)] = &[
+    ("normal", 128),
+    ("large", 256),
+    ("x-large", 512),
+    ("xx-large", 1024),
+];
+
+const MAX_CHUNK_LEN: usize = 64 * 1024;
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// Shared `~/.cache/thumbnails` store, as populated by file managers
+#[derive(Clone)]
+pub struct FreedesktopThumbnails {
+    root: PathBuf,
+    read: bool,
+    write_back: bool,
+}
+
+impl FreedesktopThumbnails {
+    pub fn new(read: bool, write_back: bool) -> Self {
+        let root = dirs::cache_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("thumbnails");
+        Self {
+            root,
+            read,
+            write_back,
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_root(root: PathBuf, write_back: bool) -> Self {
+        Self {
+            root,
+            read: true,
+            write_back,
+        }
+    }
+
+    fn flavor_dir(&self, flavor: &str) -> PathBuf {
+        self.root.join(flavor)
+    }
+}
+
+impl CacheAdapter for FreedesktopThumbnails {
+    /// The smallest flavor on disk that covers the requested box, else the largest one on disk
+    /// as a stand-in until the preview is decoded, else where a covering one would be written
+    fn get_cache_path(
+        &self,
+        _category: &str,
+        original_path: &Path,
+        max_width: i32,
+        max_height: i32,
+    ) -> PathBuf {
+        let name = thumbnail_name(original_path).unwrap_or_default();
+        let wanted = max_width.max(max_height);
+        let candidates: Vec<&str> = FLAVORS
+            .iter()
+            .filter(|(_, size)| *size >= wanted)
+            .map(|(flavor, _)| *flavor)
+            .collect();
+
+        let covering = candidates
+            .iter()
+            .map(|flavor| self.flavor_dir(flavor).join(&name))
+            .find(|path| path.is_file());
+        let largest = || {
+            FLAVORS
+                .iter()
+                .rev()
+                .map(|(flavor, _)| self.flavor_dir(flavor).join(&name))
+                .find(|path| path.is_file())
+        };
+        covering.or_else(largest).unwrap_or_else(|| {
+            let flavor = candidates.first().copied().unwrap_or("xx-large");
+            self.flavor_dir(flavor).join(&name)
+        })
+    }
+
+    /// Always false when reading is disabled, so a write-only store is never consulted
+    fn is_cache_valid(&self, cache_path: &Path, original_path: &Path) -> bool {
+        if !self.read {
+            return false;
+        }
+        let Some(chunks) = read_text_chunks(cache_path) else {
+            return false;
+        };
+        let lookup = |key: &str| {
+            chunks
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str())
+        };
+
+        let uri_matches = match (lookup("Thumb::URI"), file_uri(original_path)) {
+            (Some(stored), Some(expected)) => stored == expected,
+            _ => false,
+        };
+        let mtime_matches = match (lookup("Thumb::MTime"), source_mtime(original_path)) {
+            (Some(stored), Some(actual)) => stored.parse::<u64>().ok() == Some(actual),
+            _ => false,
+        };
+
+        uri_matches && mtime_matches
+    }
+
+    /// Writes a spec-conformant PNG when write-back is enabled. The flavor is chosen from the
+    /// decoded size, so `path` only supplies the file name.
+    fn save_raw_cache(
+        &self,
+        path: &Path,
+        original_path: &Path,
+        raw_data: &[u8],
+        width: i32,
+        height: i32,
+    ) -> io::Result<()> {
+        if !self.write_back {
+            return Ok(());
+        }
+        let (Some(uri), Some(mtime), Some(name)) = (
+            file_uri(original_path),
+            source_mtime(original_path),
+            path.file_name(),
+        ) else {
+            return Ok(());
+        };
+
+        let longest = width.max(height);
+        let (flavor, flavor_size) = FLAVORS
+            .iter()
+            .rev()
+            .find(|(_, size)| *size <= longest)
+            .copied()
+            .unwrap_or(FLAVORS[0]);
+
+        let pixbuf = Pixbuf::from_bytes(
+            &glib::Bytes::from(raw_data),
+            Colorspace::Rgb,
+            true,
+            8,
+            width,
+            height,
+            width * 4,
+        );
+        let pixbuf = if longest > flavor_size {
+            let ratio = flavor_size as f64 / longest as f64;
+            let scaled_w = ((width as f64 * ratio).round() as i32).max(1);
+            let scaled_h = ((height as f64 * ratio).round() as i32).max(1);
+            pixbuf
+                .scale_simple(scaled_w, scaled_h, InterpType::Bilinear)
+                .ok_or_else(|| io::Error::other("Failed to scale thumbnail"))?
+        } else {
+            pixbuf
+        };
+
+        let dir = self.flavor_dir(flavor);
+        fs::create_dir_all(&dir)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let _ = fs::set_permissions(&self.root, fs::Permissions::from_mode(0o700));
+            let _ = fs::set_permissions(&dir, fs::Permissions::from_mode(0o700));
+        }
+
+        // The spec requires writers to rename into place so readers never see partial files
+        let temp = tempfile::Builder::new()
+            .prefix("pantry-")
+            .suffix(".png")
+            .tempfile_in(&dir)?;
+        let mtime_str = mtime.to_string();
+        pixbuf
+            .savev(
+                temp.path(),
+                "png",
+                &[
+                    ("tEXt::Thumb::URI", uri.as_str()),
+                    ("tEXt::Thumb::MTime", mtime_str.as_str()),
+                    ("tEXt::Software", "pantry"),
+                ],
+            )
+            .map_err(io::Error::other)?;
+        temp.persist(dir.join(name)).map_err(|e| e.error)?;
+        Ok(())
+    }
+
+    fn load_raw_cache(&self, path: &Path) -> Option<(Vec<u8>, i32, i32)> {
+        match Pixbuf::from_file(path) {
+            Ok(pixbuf) => Some(crate::services::preview::decoder::pixbuf_to_rgba(&pixbuf)),
+            Err(e) => {
+                log::debug!("Failed to load thumbnail {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+}
+
+fn file_uri(path: &Path) -> Option<String> {
+    let absolute = std::path::absolute(path).ok()?;
+    glib::filename_to_uri(absolute, None)
+        .ok()
+        .map(|uri| uri.to_string())
+}
+
+fn source_mtime(path: &Path) -> Option<u64> {
+    let modified = path.metadata().and_then(|m| m.modified()).ok()?;
+    modified
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs())
+}
+
+/// Thumbnail file name: MD5 of the canonical file URI
+fn thumbnail_name(path: &Path) -> Option<String> {
+    let uri = file_uri(path)?;
+    glib::compute_checksum_for_string(glib::ChecksumType::Md5, uri.as_str())
+        .map(|hash| format!("{}.png", hash))
+}
+
+/// Read `tEXt` chunks without decoding pixel data
+fn read_text_chunks(path: &Path) -> Option<Vec<(String, String)>> {
+    let mut file = fs::File::open(path).ok()?;
+    let mut signature = [0u8; 8];
+    file.read_exact(&mut signature).ok()?;
+    if signature != PNG_SIGNATURE {
+        return None;
+    }
+
+    let mut chunks = Vec::new();
+    loop {
+        let mut header = [0u8; 8];
+        if file.read_exact(&mut header).is_err() {
+            break;
+        }
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let kind = &header[4..8];
+        if kind == b"IDAT" || kind == b"IEND" || len > MAX_CHUNK_LEN {
+            break;
+        }
+
+        let mut data = vec![0u8; len];
+        file.read_exact(&mut data).ok()?;
+        let mut crc = [0u8; 4];
+        file.read_exact(&mut crc).ok()?;
+
+        if kind == b"tEXt"
+            && let Some(sep) = data.iter().position(|&b| b == 0)
+        {
+            let key = String::from_utf8_lossy(&data[..sep]).to_string();
+            let value = String::from_utf8_lossy(&data[sep + 1..]).to_string();
+            chunks.push((key, value));
+        }
+    }
+    Some(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn png_with_text(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut out = PNG_SIGNATURE.to_vec();
+        for (key, value) in entries {
+            let data = format!("{}\0{}", key, value);
+            out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            out.extend_from_slice(b"tEXt");
+            out.extend_from_slice(data.as_bytes());
+            out.extend_from_slice(&[0u8; 4]);
+        }
+        out.extend_from_slice(&0u32.to_be_bytes());
+        out.extend_from_slice(b"IEND");
+        out.extend_from_slice(&[0u8; 4]);
+        out
+    }
+
+    #[test]
+    fn thumbnail_name_matches_spec_example() {
+        assert_eq!(
+            thumbnail_name(Path::new("/home/jens/photos/me.png")).unwrap(),
+            "c6ee772d9e49320e97ec29a7eb5b1697.png"
+        );
+    }
+
+    #[test]
+    fn picks_smallest_covering_flavor() {
+        let dir = tempdir().unwrap();
+        let thumbs = FreedesktopThumbnails::with_root(dir.path().to_path_buf(), false);
+        let path = thumbs.get_cache_path("cat", Path::new("/tmp/a.png"), 800, 600);
+        assert!(path.starts_with(dir.path().join("xx-large")));
+        let path = thumbs.get_cache_path("cat", Path::new("/tmp/a.png"), 200, 100);
+        assert!(path.starts_with(dir.path().join("large")));
+    }
+
+    #[test]
+    fn falls_back_to_a_smaller_flavor_on_disk() {
+        let dir = tempdir().unwrap();
+        let thumbs = FreedesktopThumbnails::with_root(dir.path().to_path_buf(), false);
+        let original = Path::new("/tmp/a.png");
+        let large = dir
+            .path()
+            .join("large")
+            .join(thumbnail_name(original).unwrap());
+        fs::create_dir_all(large.parent().unwrap()).unwrap();
+        fs::write(&large, b"png").unwrap();
+        assert_eq!(thumbs.get_cache_path("cat", original, 800, 600), large);
+    }
+
+    #[test]
+    fn valid_when_uri_and_mtime_match() {
+        let dir = tempdir().unwrap();
+        let thumbs = FreedesktopThumbnails::with_root(dir.path().join("thumbnails"), false);
+        let original = dir.path().join("photo.png");
+        fs::write(&original, b"image").unwrap();
+
+        let uri = file_uri(&original).unwrap();
+        let mtime = source_mtime(&original).unwrap().to_string();
+        let thumb = dir.path().join("thumb.png");
+        fs::write(
+            &thumb,
+            png_with_text(&[("Thumb::URI", &uri), ("Thumb::MTime", &mtime)]),
+        )
+        .unwrap();
+
+        assert!(thumbs.is_cache_valid(&thumb, &original));
+        let write_only = FreedesktopThumbnails {
+            read: false,
+            ..thumbs
+        };
+        assert!(!write_only.is_cache_valid(&thumb, &original));
+    }
+
+    #[test]
+    fn invalid_when_mtime_differs() {
+        let dir = tempdir().unwrap();
+        let thumbs = FreedesktopThumbnails::with_root(dir.path().join("thumbnails"), false);
+        let original = dir.path().join("photo.png");
+        fs::write(&original, b"image").unwrap();
+
+        let uri = file_uri(&original).unwrap();
+        let thumb = dir.path().join("thumb.png");
+        fs::write(
+            &thumb,
+            png_with_text(&[("Thumb::URI", &uri), ("Thumb::MTime", "1")]),
+        )
+        .unwrap();
+
+        assert!(!thumbs.is_cache_valid(&thumb, &original));
+    }
+
+    #[test]
+    fn invalid_when_not_png() {
+        let dir = tempdir().unwrap();
+        let thumbs = FreedesktopThumbnails::with_root(dir.path().join("thumbnails"), false);
+        let original = dir.path().join("photo.png");
+        let thumb = dir.path().join("thumb.png");
+        fs::write(&original, b"image").unwrap();
+        fs::write(&thumb, b"not a png").unwrap();
+
+        assert!(!thumbs.is_cache_valid(&thumb, &original));
+    }
+
+    #[test]
+    fn write_back_round_trip() {
+        let dir = tempdir().unwrap();
+        let thumbs = FreedesktopThumbnails::with_root(dir.path().join("thumbnails"), true);
+        let original = dir.path().join("photo.png");
+        fs::write(&original, b"image").unwrap();
+
+        let data = vec![200u8; 600 * 300 * 4];
+        let path = thumbs.get_cache_path("cat", &original, 800, 600);
+        thumbs
+            .save_raw_cache(&path, &original, &data, 600, 300)
+            .unwrap();
+
+        let written = dir
+            .path()
+            .join("thumbnails")
+            .join("x-large")
+            .join(path.file_name().unwrap());
+        assert!(thumbs.is_cache_valid(&written, &original));
+        let (_, w, h) = thumbs.load_raw_cache(&written).unwrap();
+        assert_eq!((w, h), (512, 256));
+    }
+
+    #[test]
+    fn write_back_disabled_writes_nothing() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("thumbnails");
+        let thumbs = FreedesktopThumbnails::with_root(root.clone(), false);
+        let original = dir.path().join("photo.png");
+        fs::write(&original, b"image").unwrap();
+
+        let path = thumbs.get_cache_path("cat", &original, 800, 600);
+        thumbs
+            .save_raw_cache(&path, &original, &[0u8; 40], 10, 1)
+            .unwrap();
+        assert!(!root.exists());
+    }
+}
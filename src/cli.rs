@@ -0,0 +1,267 @@
+use clap::Subcommand;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::cache::{CacheManager, Removed};
+use crate::constants::{
+    DEFAULT_WINDOW_HEIGHT, DEFAULT_WINDOW_WIDTH, MAX_WINDOW_WIDTH_FRACTION, PREVIEW_DETAILS_HEIGHT,
+};
+use crate::domain::DisplayMode;
+use crate::domain::item::Item;
+use crate::services::preview::{PreviewPayload, PreviewSize, create_prod_preview_service};
+use crate::services::process::ShellExec;
+use crate::utils::{format_size, parse_size};
+
+#[derive(Debug, Subcommand)]
+pub enum CacheAction {
+    /// Show cache location, entry count and size
+    Stats,
+    /// Delete cached previews, optionally only for one category
+    Clear {
+        #[arg(short = 'c', long = "category")]
+        category: Option<String>,
+    },
+    /// Delete old entries and shrink the cache to a size limit
+    Prune {
+        /// e.g. 500M, 2G
+        #[arg(long = "max-size", value_parser = parse_size)]
+        max_size: Option<u64>,
+        /// e.g. 12h, 30d, 2w
+        #[arg(long = "max-age", value_parser = parse_duration)]
+        max_age: Option<Duration>,
+    },
+    /// Generate previews for every item in a category ahead of time
+    Warm {
+        #[arg(short = 'c', long = "category")]
+        category: String,
+        /// Decode box, e.g. 1024x768 (defaults to the preview pane of a default-sized window,
+        /// 800x768, which is what a first preview looks up at scale 1)
+        #[arg(long = "size", value_parser = parse_preview_size)]
+        size: Option<PreviewSize>,
+        /// Number of parallel workers (defaults to the number of CPUs)
+        #[arg(short = 'j', long = "jobs")]
+        jobs: Option<usize>,
+    },
+}
+
+pub fn run_cache(action: &CacheAction, config_path: &str) -> Result<(), String> {
+    let config = crate::config::load(config_path);
+    let cache_config = config.as_ref().map(|c| c.cache.clone()).unwrap_or_default();
+    let cache = CacheManager::new(&cache_config);
+
+    match action {
+        CacheAction::Stats => {
+            let stats = cache.stats();
+            println!("Location: {}", cache.cache_dir().display());
+            println!("Entries:  {}", stats.entries);
+            println!("Size:     {}", format_size(stats.total_bytes));
+            println!("Limit:    {}", format_size(cache_config.max_size));
+            if let Some(least) = stats.least_recent_access {
+                println!("Least recently used: {} ago", format_age(least));
+            }
+            if let Some(most) = stats.most_recent_access {
+                println!("Most recently used:  {} ago", format_age(most));
+            }
+        }
+        CacheAction::Clear { category: None } => {
+            print_removed(cache.clear());
+        }
+        CacheAction::Clear {
+            category: Some(category),
+        } => {
+            let items = category_items(&config?, category)?;
+            let mut removed = Removed::default();
+            for item in &items {
+                let r = cache.remove_entries_for(&crate::utils::expand_tilde(&item.value));
+                removed.entries += r.entries;
+                removed.bytes += r.bytes;
+            }
+            print_removed(removed);
+        }
+        CacheAction::Prune { max_size, max_age } => {
+            if max_size.is_none() && max_age.is_none() {
+                return Err("prune needs --max-size and/or --max-age".to_string());
+            }
+            print_removed(cache.prune(*max_size, *max_age));
+        }
+        CacheAction::Warm {
+            category,
+            size,
+            jobs,
+        } => {
+            let config = config?;
+            let items = category_items(&config, category)?;
+            warm(
+                &items,
+                &config.cache,
+                size.unwrap_or_else(standard_preview_size),
+                *jobs,
+            );
+        }
+    }
+    Ok(())
+}
+
+fn category_items(config: &crate::config::Config, category: &str) -> Result<Vec<Item>, String> {
+    if !config.categories.contains_key(category) {
+        return Err(format!("No category named [{}]", category));
+    }
+    let items =
+        crate::services::pipeline::run(config, &Some(category.to_string()), &None, &ShellExec);
+    Ok(items
+        .into_iter()
+        .filter(|item| matches!(item.display, DisplayMode::Picture))
+        .collect())
+}
+
+fn warm(
+    items: &[Item],
+    cache_config: &crate::config::CacheConfig,
+    size: PreviewSize,
+    jobs: Option<usize>,
+) {
+    let workers = jobs
+        .or_else(|| std::thread::available_parallelism().ok().map(|n| n.get()))
+        .unwrap_or(1)
+        .clamp(1, items.len().max(1));
+    let service = create_prod_preview_service(cache_config);
+    let next = AtomicUsize::new(0);
+    let cached = AtomicUsize::new(0);
+    let failed = Mutex::new(Vec::new());
+    let started = Instant::now();
+
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                loop {
+                    let idx = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(idx) else {
+                        break;
+                    };
+                    if service.try_cache(item, size).is_some() {
+                        cached.fetch_add(1, Ordering::Relaxed);
+                        continue;
+                    }
+                    if let PreviewPayload::Error(e) = service.resolve_payload(item, size)
+                        && let Ok(mut failed) = failed.lock()
+                    {
+                        failed.push(format!("{}: {}", item.value, e));
+                    }
+                }
+            });
+        }
+    });
+
+    let failed = failed.into_inner().unwrap_or_default();
+    for failure in &failed {
+        eprintln!("{}", failure);
+    }
+    let cached = cached.into_inner();
+    println!(
+        "Warmed {} previews, {} already cached ({} failed) in {:.1}s",
+        items.len() - cached - failed.len(),
+        cached,
+        failed.len(),
+        started.elapsed().as_secs_f64()
+    );
+}
+
+fn print_removed(removed: Removed) {
+    println!(
+        "Removed {} entries ({})",
+        removed.entries,
+        format_size(removed.bytes)
+    );
+}
+
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration: {}", s))?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "" | "d" => 24 * 3600,
+        "w" => 7 * 24 * 3600,
+        _ => return Err(format!("unknown duration unit in {}", s)),
+    };
+    number
+        .checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("duration too long: {}", s))
+}
+
+/// Decode box of the preview pane in a default-sized window
+fn standard_preview_size() -> PreviewSize {
+    let width = DEFAULT_WINDOW_WIDTH as f64 * (1.0 - MAX_WINDOW_WIDTH_FRACTION);
+    PreviewSize::bucketed(width as i32, DEFAULT_WINDOW_HEIGHT - PREVIEW_DETAILS_HEIGHT)
+}
+
+fn parse_preview_size(s: &str) -> Result<PreviewSize, String> {
+    let (w, h) = s
+        .split_once('x')
+        .ok_or_else(|| format!("expected WIDTHxHEIGHT, got {}", s))?;
+    let width = w.parse().map_err(|_| format!("invalid width: {}", w))?;
+    let height = h.parse().map_err(|_| format!("invalid height: {}", h))?;
+    Ok(PreviewSize::bucketed(width, height))
+}
+
+fn format_age(time: SystemTime) -> String {
+    let secs = SystemTime::now()
+        .duration_since(time)
+        .unwrap_or_default()
+        .as_secs();
+    match secs {
+        s if s < 3600 => format!("{}m", s / 60),
+        s if s < 24 * 3600 => format!("{}h", s / 3600),
+        s => format!("{}d", s / (24 * 3600)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration_units() {
+        assert_eq!(
+            parse_duration("30d").unwrap(),
+            Duration::from_secs(30 * 24 * 3600)
+        );
+        assert_eq!(
+            parse_duration("12h").unwrap(),
+            Duration::from_secs(12 * 3600)
+        );
+        assert_eq!(
+            parse_duration("2w").unwrap(),
+            Duration::from_secs(14 * 24 * 3600)
+        );
+        assert!(parse_duration("3y").is_err());
+        assert!(parse_duration("99999999999999w").is_err());
+    }
+
+    #[test]
+    fn parse_preview_size_buckets() {
+        assert_eq!(
+            parse_preview_size("1000x700").unwrap(),
+            PreviewSize::bucketed(1000, 700)
+        );
+        assert!(parse_preview_size("1000").is_err());
+    }
+
+    #[test]
+    fn standard_size_matches_the_help_text() {
+        assert_eq!(
+            standard_preview_size(),
+            PreviewSize {
+                width: 800,
+                height: 768
+            }
+        );
+    }
+}
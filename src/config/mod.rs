@@ -1,5 +1,16 @@
 pub mod parser;
 pub mod resolver;
 
-pub use parser::{Category, Config};
-pub use resolver::{get_config_display_mode, resolve_display_mode};
+pub use parser::{CacheConfig, Category, Config};
+pub use resolver::{
+    get_config_display_mode, get_config_listing_depth, get_config_preview_position,
+    get_config_preview_wrap, resolve_display_mode,
+};
+
+/// Read and parse a TOML config file
+pub fn load(config_path: &str) -> Result<Config, String> {
+    let content = std::fs::read_to_string(config_path)
+        .map_err(|e| format!("Failed to read config file {}: {}", config_path, e))?;
+    toml::from_str(&content)
+        .map_err(|e| format!("Failed to parse config file {}: {}", config_path, e))
+}
@@ -1,21 +1,92 @@
 use serde::{Deserialize, Deserializer};
 use std::collections::HashMap;
 
-use crate::domain::{DisplayMode, SourceMode};
+use crate::cache::CacheKeyMode;
+use crate::constants::{DEFAULT_CACHE_MAX_SIZE_BYTES, DEFAULT_MEM_CACHE_MAX_BYTES};
+use crate::domain::{DisplayMode, PreviewFormat, PreviewPosition, SourceMode};
+use crate::services::preview::provider::ProviderRegistry;
+use crate::utils::parse_size;
 
 #[derive(Debug, Clone, Deserialize, PartialEq)]
 #[serde(deny_unknown_fields)]
 pub struct Category {
     pub display: Option<DisplayMode>,
     pub source: Option<SourceMode>,
+    pub preview: Option<PreviewPosition>,
+    /// Command whose output previews an entry, with `{}` standing for its value
+    pub preview_command: Option<String>,
+    pub preview_wrap: Option<bool>,
+    /// Render text previews as Markdown; by default only `.md` files are
+    pub preview_format: Option<PreviewFormat>,
+    /// Render ANSI colours in command output titles instead of showing the escapes
+    pub ansi: Option<bool>,
+    /// Levels drawn in directory and archive previews
+    pub listing_depth: Option<usize>,
+    /// Folder a `files` source starts in and cannot leave; the home folder by default
+    pub root: Option<String>,
+    /// Leave out what `.gitignore` files exclude in a `files` source
+    pub gitignore: Option<bool>,
     #[serde(default)]
     pub entries: HashMap<String, String>,
 }
 
+/// Global `[cache]` table
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields, default)]
+pub struct CacheConfig {
+    /// Reuse thumbnails from `~/.cache/thumbnails` before decoding. Only `xx-large` ones
+    /// cover the smallest preview size; smaller ones are shown while the decode runs.
+    pub shared_thumbnails: bool,
+    /// Write decoded previews back to `~/.cache/thumbnails`
+    pub write_thumbnails: bool,
+    /// Derive cache file names from file metadata or file contents
+    pub key: CacheKeyMode,
+    /// Disk cache size limit, in bytes or with a unit like "500M"
+    #[serde(deserialize_with = "deserialize_size")]
+    pub max_size: u64,
+    /// Budget for decoded previews and command output kept in memory
+    #[serde(deserialize_with = "deserialize_size")]
+    pub memory_size: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            shared_thumbnails: true,
+            write_thumbnails: false,
+            key: CacheKeyMode::default(),
+            max_size: DEFAULT_CACHE_MAX_SIZE_BYTES,
+            memory_size: DEFAULT_MEM_CACHE_MAX_BYTES,
+        }
+    }
+}
+
+fn deserialize_size<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(u64),
+        Text(String),
+    }
+
+    match Size::deserialize(deserializer)? {
+        Size::Bytes(bytes) => Ok(bytes),
+        Size::Text(text) => parse_size(&text).map_err(serde::de::Error::custom),
+    }
+}
+
 #[derive(Debug)]
 pub struct Config {
     pub display: DisplayMode,
     pub source: SourceMode,
+    pub preview: Option<PreviewPosition>,
+    pub preview_wrap: Option<bool>,
+    pub listing_depth: Option<usize>,
+    pub cache: CacheConfig,
+    pub providers: ProviderRegistry,
     pub categories: HashMap<String, Category>,
 }
 
@@ -23,6 +94,11 @@ pub struct Config {
 struct RawConfig {
     pub display: Option<DisplayMode>,
     pub source: Option<SourceMode>,
+    pub preview: Option<PreviewPosition>,
+    pub preview_wrap: Option<bool>,
+    pub listing_depth: Option<usize>,
+    pub cache: Option<CacheConfig>,
+    pub providers: Option<ProviderRegistry>,
     #[serde(flatten)]
     pub categories: HashMap<String, toml::Value>,
 }
@@ -47,6 +123,11 @@ impl<'de> Deserialize<'de> for Config {
         Ok(Config {
             display,
             source,
+            preview: raw.preview,
+            preview_wrap: raw.preview_wrap,
+            listing_depth: raw.listing_depth,
+            cache: raw.cache.unwrap_or_default(),
+            providers: raw.providers.unwrap_or_default(),
             categories,
         })
     }
@@ -111,6 +192,22 @@ source = "command"
         );
     }
 
+    #[test]
+    fn parse_files_source_without_entries() {
+        let toml_str = r#"
+[files]
+source = "files"
+root = "~/src"
+gitignore = true
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        let files = config.categories.get("files").unwrap();
+        assert_eq!(files.source, Some(SourceMode::Files));
+        assert_eq!(files.root.as_deref(), Some("~/src"));
+        assert_eq!(files.gitignore, Some(true));
+        assert!(files.entries.is_empty());
+    }
+
     #[test]
     fn deny_unknown_fields_reports_typo() {
         let toml_str = r#"
@@ -125,6 +222,86 @@ displey = "picture"
         assert!(msg.contains("displey"), "should mention typo: {msg}");
     }
 
+    #[test]
+    fn cache_table_is_not_a_category() {
+        let toml_str = r#"
+display = "picture"
+
+[cache]
+write_thumbnails = true
+key = "content"
+
+[favorites.entries]
+"cat" = "~/cat.png"
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.categories.len(), 1);
+        assert!(config.cache.shared_thumbnails);
+        assert!(config.cache.write_thumbnails);
+        assert_eq!(config.cache.key, CacheKeyMode::Content);
+    }
+
+    #[test]
+    fn cache_table_defaults_when_missing() {
+        let config: Config = toml::from_str("display = \"text\"").unwrap();
+        assert_eq!(config.cache, CacheConfig::default());
+    }
+
+    #[test]
+    fn cache_max_size_accepts_units_and_bytes() {
+        let config: Config = toml::from_str("[cache]\nmax_size = \"500M\"").unwrap();
+        assert_eq!(config.cache.max_size, 500 * 1024 * 1024);
+        let config: Config = toml::from_str("[cache]\nmax_size = 4096").unwrap();
+        assert_eq!(config.cache.max_size, 4096);
+        assert!(toml::from_str::<Config>("[cache]\nmax_size = \"lots\"").is_err());
+        let config: Config = toml::from_str("[cache]\nmemory_size = \"64M\"").unwrap();
+        assert_eq!(config.cache.memory_size, 64 * 1024 * 1024);
+    }
+
+    #[test]
+    fn providers_table_is_not_a_category() {
+        let toml_str = r#"
+[providers]
+"application/pdf" = "pdftoppm -png -singlefile -scale-to 800 {} -"
+"text/*" = "builtin:text"
+
+[notes]
+display = "text"
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert!(!config.providers.is_empty());
+        assert_eq!(config.categories.len(), 1);
+
+        let err = toml::from_str::<Config>("[providers]\n\"*.md\" = \"builtin:nope\"")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("builtin:nope"), "{err}");
+    }
+
+    #[test]
+    fn parse_preview_pane_settings() {
+        let toml_str = r#"
+preview = "none"
+
+[snippets]
+preview = "bottom"
+preview_command = "bat {}"
+"#;
+        let config: Config = toml::from_str(toml_str).unwrap();
+        assert_eq!(config.preview, Some(PreviewPosition::None));
+        let snippets = config.categories.get("snippets").unwrap();
+        assert_eq!(snippets.preview, Some(PreviewPosition::Bottom));
+        assert_eq!(snippets.preview_command.as_deref(), Some("bat {}"));
+        assert_eq!(snippets.preview_wrap, None);
+        assert_eq!(snippets.preview_format, None);
+        let notes: Config = toml::from_str("[notes]\npreview_format = \"markdown\"").unwrap();
+        assert_eq!(
+            notes.categories["notes"].preview_format,
+            Some(PreviewFormat::Markdown)
+        );
+        assert!(toml::from_str::<Config>("[a]\npreview = \"left\"").is_err());
+    }
+
     #[test]
     fn deny_unknown_source_value() {
         let toml_str = r#"
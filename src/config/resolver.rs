@@ -1,4 +1,4 @@
-use crate::domain::DisplayMode;
+use crate::domain::{DisplayMode, PreviewFormat, PreviewPosition};
 use std::str::FromStr;
 
 /// Unified display mode resolution with priority: command line > category > global > default
@@ -33,6 +33,93 @@ pub fn get_config_display_mode(
     resolve_display_mode(display_arg, &None, &config.display)
 }
 
+/// Preview pane placement with priority: category > global > default.
+/// Pictures always default to a pane; text only when the items want a preview, from a
+/// preview command, Markdown or a source that previews its own entries.
+pub fn resolve_preview_position(
+    category_preview: Option<PreviewPosition>,
+    global_preview: Option<PreviewPosition>,
+    display: &DisplayMode,
+    wants_preview: bool,
+) -> PreviewPosition {
+    category_preview
+        .or(global_preview)
+        .unwrap_or(match display {
+            DisplayMode::Picture => PreviewPosition::Right,
+            DisplayMode::Text if wants_preview => PreviewPosition::Right,
+            DisplayMode::Text => PreviewPosition::None,
+        })
+}
+
+/// Whether a category's items have something to preview in text mode
+fn wants_preview(
+    config: &crate::config::parser::Config,
+    category: &crate::config::parser::Category,
+) -> bool {
+    let has_command = category.preview_command.is_some();
+    let shows_markdown = category.preview_format == Some(PreviewFormat::Markdown);
+    // Clipboard entries, files and dynamic items preview without a command
+    let source_previews = matches!(
+        category.source.as_ref().unwrap_or(&config.source),
+        crate::domain::SourceMode::Dynamic
+            | crate::domain::SourceMode::Clipboard
+            | crate::domain::SourceMode::Files
+    );
+    has_command || shows_markdown || source_previews
+}
+
+pub fn get_config_preview_position(
+    config: &crate::config::parser::Config,
+    category_filter: &Option<String>,
+    display: &DisplayMode,
+) -> PreviewPosition {
+    if let Some(category) = category_filter
+        && let Some(category_config) = config.categories.get(category)
+    {
+        return resolve_preview_position(
+            category_config.preview,
+            config.preview,
+            display,
+            wants_preview(config, category_config),
+        );
+    }
+    resolve_preview_position(
+        None,
+        config.preview,
+        display,
+        config
+            .categories
+            .values()
+            .any(|category| wants_preview(config, category)),
+    )
+}
+
+/// Whether text previews wrap long lines: category > global > wrap
+pub fn get_config_preview_wrap(
+    config: &crate::config::parser::Config,
+    category_filter: &Option<String>,
+) -> bool {
+    category_filter
+        .as_ref()
+        .and_then(|category| config.categories.get(category))
+        .and_then(|category| category.preview_wrap)
+        .or(config.preview_wrap)
+        .unwrap_or(true)
+}
+
+/// Levels shown in directory and archive previews: category > global > default
+pub fn get_config_listing_depth(
+    config: &crate::config::parser::Config,
+    category_filter: &Option<String>,
+) -> usize {
+    category_filter
+        .as_ref()
+        .and_then(|category| config.categories.get(category))
+        .and_then(|category| category.listing_depth)
+        .or(config.listing_depth)
+        .unwrap_or(crate::constants::DEFAULT_LISTING_DEPTH)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -65,6 +152,68 @@ mod tests {
         );
     }
 
+    #[test]
+    fn text_preview_pane_only_with_command() {
+        assert_eq!(
+            resolve_preview_position(None, None, &DisplayMode::Text, false),
+            PreviewPosition::None
+        );
+        assert_eq!(
+            resolve_preview_position(None, None, &DisplayMode::Text, true),
+            PreviewPosition::Right
+        );
+        assert_eq!(
+            resolve_preview_position(None, None, &DisplayMode::Picture, false),
+            PreviewPosition::Right
+        );
+    }
+
+    #[test]
+    fn sources_that_preview_get_a_pane() {
+        let config: crate::config::parser::Config = toml::from_str(
+            "[clip]\nsource = \"clipboard\"\n[notes]\npreview_format = \"markdown\"\n[plain]\n",
+        )
+        .unwrap();
+        let position = |category: &str| {
+            get_config_preview_position(&config, &Some(category.to_string()), &DisplayMode::Text)
+        };
+        assert_eq!(position("clip"), PreviewPosition::Right);
+        assert_eq!(position("notes"), PreviewPosition::Right);
+        assert_eq!(position("plain"), PreviewPosition::None);
+        // Without a filter the pane is there for whichever category wants it
+        assert_eq!(
+            get_config_preview_position(&config, &None, &DisplayMode::Text),
+            PreviewPosition::Right
+        );
+        let plain: crate::config::parser::Config = toml::from_str("[plain]\n").unwrap();
+        assert_eq!(
+            get_config_preview_position(&plain, &None, &DisplayMode::Text),
+            PreviewPosition::None
+        );
+    }
+
+    #[test]
+    fn category_preview_over_global() {
+        assert_eq!(
+            resolve_preview_position(
+                Some(PreviewPosition::Bottom),
+                Some(PreviewPosition::None),
+                &DisplayMode::Text,
+                false
+            ),
+            PreviewPosition::Bottom
+        );
+        assert_eq!(
+            resolve_preview_position(
+                None,
+                Some(PreviewPosition::None),
+                &DisplayMode::Picture,
+                true
+            ),
+            PreviewPosition::None
+        );
+    }
+
     #[test]
     fn resolve_invalid_cli_falls_through() {
         assert_eq!(
@@ -4,19 +4,79 @@ pub const SELECTION_UPDATE_DELAY_MS: u64 = 10;
 pub const INITIAL_PREVIEW_DELAY_MS: u64 = 10;
 pub const IMAGE_PREVIEW_WIDTH: i32 = 800;
 pub const IMAGE_PREVIEW_HEIGHT: i32 = 600;
+pub const PREVIEW_SIZE_BUCKET: i32 = 256;
+pub const MAX_PREVIEW_DECODE_SIZE: i32 = 4096;
+/// Longest side of the decode made when zooming past the preview's resolution
+pub const MAX_FULL_RESOLUTION_SIZE: i32 = 8192;
+pub const ZOOM_STEP: f64 = 1.25;
+pub const MIN_ZOOM: f64 = 0.05;
+pub const MAX_ZOOM: f64 = 16.0;
+pub const PREVIEW_RESIZE_DEBOUNCE_MS: u64 = 150;
+pub const PREFETCH_RADIUS: u32 = 3;
+pub const PREFETCH_NICE: i32 = 10;
 pub const MAX_WINDOW_WIDTH_FRACTION: f64 = 0.45;
+/// Share of the window height given to the list when the preview sits below it
+pub const LIST_HEIGHT_FRACTION: f64 = 0.55;
 pub const DEFAULT_WINDOW_WIDTH: i32 = 1200;
 pub const DEFAULT_WINDOW_HEIGHT: i32 = 800;
+/// Height kept for the title, category and path below a preview
+pub const PREVIEW_DETAILS_HEIGHT: i32 = 100;
 pub const MAX_DECODE_PIXEL_BYTES: u64 = 50 * 1024 * 1024;
+/// Memory a still image may take at full size while a loader scales it down; a 100 MP
+/// photo fits, a 30000×30000 scan does not
+pub const MAX_STILL_DECODE_BYTES: u64 = 512 * 1024 * 1024;
+/// Image decodes still running after this long are abandoned
+pub const DECODE_TIMEOUT_SECS: u64 = 10;
+pub const MAX_ANIMATION_BYTES: u64 = 128 * 1024 * 1024;
+pub const MIN_FRAME_DELAY_MS: u32 = 20;
+pub const DEFAULT_FRAME_DELAY_MS: u64 = 100;
+/// Leading bytes read to identify a file's type
+pub const MIME_SNIFF_BYTES: usize = 512;
+/// Files previewed as text are cut off after this many bytes
+pub const MAX_TEXT_PREVIEW_BYTES: u64 = 256 * 1024;
+/// Longer text previews are shown without syntax colours
+pub const MAX_HIGHLIGHT_BYTES: usize = 512 * 1024;
 pub const MAX_ITEMS: usize = 10_000;
-pub const CACHE_MAX_SIZE_BYTES: u64 = 1024 * 1024 * 1024;
-pub const FFMPEG_THUMB_SCALE: i32 = 800;
+pub const DEFAULT_CACHE_MAX_SIZE_BYTES: u64 = 1024 * 1024 * 1024;
 pub const FFMPEG_THUMB_QUALITY: i32 = 5;
-pub const MEM_CACHE_MAX_SIZE: usize = 16;
+pub const FFMPEG_TIMEOUT_SECS: u64 = 30;
+pub const FFPROBE_TIMEOUT_SECS: u64 = 10;
+/// Videos at least this long get a storyboard of STORYBOARD_GRID × STORYBOARD_GRID frames
+pub const STORYBOARD_MIN_SECS: f64 = 10.0;
+pub const STORYBOARD_GRID: i32 = 3;
+/// ffmpeg colour for audio waveforms
+pub const WAVEFORM_COLOR: &str = "0x62a0ea";
+pub const DYNAMIC_PREVIEW_TIMEOUT_SECS: u64 = 10;
+pub const ARCHIVE_TIMEOUT_SECS: u64 = 10;
+/// Directory and archive previews stop collecting entries here
+pub const MAX_LISTING_ENTRIES: usize = 10_000;
+/// Tree lines drawn in a listing preview; the rest are summarised
+pub const MAX_LISTING_LINES: usize = 500;
+pub const DEFAULT_LISTING_DEPTH: usize = 2;
+pub const PREVIEW_WORKERS: usize = 2;
+pub const DEFAULT_MEM_CACHE_MAX_BYTES: u64 = 256 * 1024 * 1024;
+/// Preview command output is run again once it is this old
+pub const COMMAND_PREVIEW_TTL_SECS: u64 = 30;
 pub const MIN_WINDOW_WIDTH: i32 = 320;
 pub const MIN_WINDOW_HEIGHT: i32 = 240;
 pub const SEARCH_DEBOUNCE_MS: u64 = 80;
 pub const DEFAULT_CLIPBOARD_CMD: &str = "cliphist decode {}";
+pub const CLIPBOARD_TIMEOUT_SECS: u64 = 5;
+/// Longest side of clipboard thumbnails and icons in list rows
+pub const ROW_THUMBNAIL_SIZE: i32 = 48;
 pub const VIDEO_EXTENSIONS: &[&str] = &["mp4", "webm", "mkv", "avi", "mov", "wmv", "flv", "m4v"];
-pub const IMAGE_EXTENSIONS: &[&str] = &["png", "jpeg", "jpg", "gif", "webp", "bmp", "tiff", "tif"];
-pub const GIF_EXTENSION: &str = "gif";
+pub const AUDIO_EXTENSIONS: &[&str] = &[
+    "mp3", "flac", "ogg", "oga", "opus", "m4a", "aac", "wav", "aiff", "wma", "wv", "ape",
+];
+/// Archives listed with bsdtar; compound ones like "tar.gz" are matched against the whole name
+pub const ARCHIVE_EXTENSIONS: &[&str] = &[
+    "zip", "cbz", "tar", "tar.gz", "tgz", "tar.bz2", "tbz2", "tar.xz", "txz", "tar.zst", "tzst",
+    "cbt", "7z", "cb7",
+];
+pub const IMAGE_EXTENSIONS: &[&str] = &[
+    "png", "jpeg", "jpg", "gif", "webp", "bmp", "tiff", "tif", "svg", "svgz", "avif", "heic",
+    "heif", "jxl", "ico", "cr2", "nef", "arw", "dng", "raf", "orf", "rw2",
+];
+/// Camera RAW formats, previewed through the JPEG embedded in them
+pub const RAW_EXTENSIONS: &[&str] = &["cr2", "nef", "arw", "dng", "raf", "orf", "rw2"];
+pub const MARKDOWN_EXTENSIONS: &[&str] = &["md", "markdown"];
@@ -1,4 +1,4 @@
-use super::{DisplayMode, SourceMode};
+use super::{ClipKind, DisplayMode, Launcher, PreviewFormat, SourceMode};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Item {
@@ -8,6 +8,24 @@ pub struct Item {
     pub display: DisplayMode,
     pub source: SourceMode,
     pub preview_template: Option<String>,
+    /// Layout of text previews; `None` picks Markdown for `.md` files
+    pub preview_format: Option<PreviewFormat>,
+    /// Line of interest in the previewed file, e.g. a search match
+    pub line: Option<u32>,
+    /// Title and value as the command printed them, escape sequences included
+    pub colored: Option<ColoredText>,
+    /// Set for clipboard history entries, whose value is the cliphist id
+    pub clip: Option<ClipKind>,
+    /// Set for desktop applications, which are started rather than printed
+    pub launch: Option<Launcher>,
+    /// Icon theme name, or an absolute path to an image, shown in the row
+    pub icon: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColoredText {
+    pub title: String,
+    pub value: String,
 }
 
 impl Item {
@@ -24,6 +42,12 @@ impl Item {
             display,
             source: SourceMode::Config,
             preview_template: None,
+            preview_format: None,
+            line: None,
+            colored: None,
+            clip: None,
+            launch: None,
+            icon: None,
         }
     }
 
@@ -40,6 +64,12 @@ impl Item {
             display,
             source: SourceMode::Command,
             preview_template: None,
+            preview_format: None,
+            line: None,
+            colored: None,
+            clip: None,
+            launch: None,
+            icon: None,
         }
     }
 
@@ -55,9 +85,105 @@ impl Item {
             display: DisplayMode::Text,
             source: SourceMode::Dynamic,
             preview_template,
+            preview_format: None,
+            line: None,
+            colored: None,
+            clip: None,
+            launch: None,
+            icon: None,
+        }
+    }
+
+    /// A clipboard history entry, previewed by decoding it
+    pub fn clipboard(
+        title: impl Into<String>,
+        id: impl Into<String>,
+        category: impl Into<String>,
+        kind: ClipKind,
+    ) -> Self {
+        Self {
+            title: title.into(),
+            value: id.into(),
+            category: category.into(),
+            display: DisplayMode::Text,
+            source: SourceMode::Clipboard,
+            preview_template: Some(crate::constants::DEFAULT_CLIPBOARD_CMD.to_string()),
+            preview_format: None,
+            line: None,
+            colored: None,
+            clip: Some(kind),
+            launch: None,
+            icon: None,
+        }
+    }
+
+    /// A desktop application or one of its actions
+    pub fn application(
+        title: impl Into<String>,
+        value: impl Into<String>,
+        category: impl Into<String>,
+        launcher: Launcher,
+    ) -> Self {
+        Self {
+            title: title.into(),
+            value: value.into(),
+            category: category.into(),
+            display: DisplayMode::Text,
+            source: SourceMode::Applications,
+            preview_template: None,
+            preview_format: None,
+            line: None,
+            colored: None,
+            clip: None,
+            launch: Some(launcher),
+            icon: None,
+        }
+    }
+
+    /// A file or folder in a `files` browser, `path` being absolute
+    pub fn file(
+        title: impl Into<String>,
+        path: impl Into<String>,
+        category: impl Into<String>,
+        display: DisplayMode,
+    ) -> Self {
+        Self {
+            title: title.into(),
+            value: path.into(),
+            category: category.into(),
+            display,
+            source: SourceMode::Files,
+            preview_template: None,
+            preview_format: None,
+            line: None,
+            colored: None,
+            clip: None,
+            launch: None,
+            icon: None,
         }
     }
 
+    pub fn with_icon(mut self, icon: Option<String>) -> Self {
+        self.icon = icon;
+        self
+    }
+
+    /// Preview the item with the output of `template`, `{}` standing for the value
+    pub fn with_preview_template(mut self, template: Option<String>) -> Self {
+        self.preview_template = template;
+        self
+    }
+
+    pub fn with_preview_format(mut self, format: Option<PreviewFormat>) -> Self {
+        self.preview_format = format;
+        self
+    }
+
+    pub fn with_line(mut self, line: Option<u32>) -> Self {
+        self.line = line;
+        self
+    }
+
     pub fn stdin(value: impl Into<String>, display: DisplayMode) -> Self {
         let val = value.into();
         Self {
@@ -67,6 +193,12 @@ impl Item {
             display,
             source: SourceMode::Config,
             preview_template: None,
+            preview_format: None,
+            line: None,
+            colored: None,
+            clip: None,
+            launch: None,
+            icon: None,
         }
     }
 }
@@ -1,6 +1,7 @@
 pub mod item;
 
 use serde::Deserialize;
+use std::path::PathBuf;
 use std::str::FromStr;
 
 #[derive(Debug, Deserialize, Clone, PartialEq, Default)]
@@ -22,6 +23,23 @@ impl FromStr for DisplayMode {
     }
 }
 
+/// Where the preview pane sits relative to the list
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PreviewPosition {
+    Right,
+    Bottom,
+    None,
+}
+
+/// How text previews are laid out
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PreviewFormat {
+    Text,
+    Markdown,
+}
+
 #[derive(Debug, Deserialize, Clone, PartialEq, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum SourceMode {
@@ -29,6 +47,9 @@ pub enum SourceMode {
     Config,
     Command,
     Dynamic,
+    Clipboard,
+    Applications,
+    Files,
 }
 
 impl FromStr for SourceMode {
@@ -38,7 +59,31 @@ impl FromStr for SourceMode {
             "command" => Ok(SourceMode::Command),
             "dynamic" => Ok(SourceMode::Dynamic),
             "config" => Ok(SourceMode::Config),
+            "clipboard" => Ok(SourceMode::Clipboard),
+            "applications" => Ok(SourceMode::Applications),
+            "files" => Ok(SourceMode::Files),
             _ => Err(format!("unknown source mode: {s}")),
         }
     }
 }
+
+/// What a clipboard history entry holds
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClipKind {
+    Text,
+    /// An image, `format` as cliphist names it, e.g. "png"
+    Image {
+        format: String,
+    },
+}
+
+/// How to start an application entry
+#[derive(Debug, Clone, PartialEq)]
+pub struct Launcher {
+    /// Program and arguments, with field codes already expanded
+    pub argv: Vec<String>,
+    /// Run inside a terminal emulator
+    pub terminal: bool,
+    /// Working directory, from the entry's `Path` key
+    pub dir: Option<PathBuf>,
+}
@@ -1,5 +1,6 @@
 mod app;
 mod cache;
+mod cli;
 mod config;
 mod constants;
 mod domain;
@@ -0,0 +1,293 @@
+use std::ops::Range;
+
+pub type Rgb = (u8, u8, u8);
+
+/// A styled byte range of the plain text
+pub type Span = (Range<usize>, Style);
+
+/// Text attributes set by SGR (`ESC [ … m`) sequences
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Style {
+    pub fg: Option<Rgb>,
+    pub bg: Option<Rgb>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+impl Style {
+    fn is_plain(&self) -> bool {
+        *self == Style::default()
+    }
+
+    /// Apply the parameters of one SGR sequence; empty parameters count as 0 (reset)
+    fn apply(&mut self, params: &str) {
+        let mut codes = params
+            .split([';', ':'])
+            .map(|p| p.parse::<u16>().unwrap_or(0));
+        while let Some(code) = codes.next() {
+            match code {
+                0 => *self = Style::default(),
+                1 => self.bold = true,
+                3 => self.italic = true,
+                4 => self.underline = true,
+                22 => self.bold = false,
+                23 => self.italic = false,
+                24 => self.underline = false,
+                30..=37 => self.fg = Some(PALETTE[(code - 30) as usize]),
+                38 => self.fg = extended_color(&mut codes),
+                39 => self.fg = None,
+                40..=47 => self.bg = Some(PALETTE[(code - 40) as usize]),
+                48 => self.bg = extended_color(&mut codes),
+                49 => self.bg = None,
+                90..=97 => self.fg = Some(PALETTE[(code - 90 + 8) as usize]),
+                100..=107 => self.bg = Some(PALETTE[(code - 100 + 8) as usize]),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// The 16 standard colours, as GNOME Terminal draws them
+const PALETTE: [Rgb; 16] = [
+    (0x17, 0x14, 0x21),
+    (0xc0, 0x1c, 0x28),
+    (0x26, 0xa2, 0x69),
+    (0xa2, 0x73, 0x4c),
+    (0x12, 0x48, 0x8b),
+    (0xa3, 0x47, 0xba),
+    (0x2a, 0xa1, 0xb3),
+    (0xd0, 0xcf, 0xcc),
+    (0x5e, 0x5c, 0x64),
+    (0xf6, 0x61, 0x51),
+    (0x33, 0xd1, 0x7a),
+    (0xe9, 0xad, 0x0c),
+    (0x2a, 0x7b, 0xde),
+    (0xc0, 0x61, 0xcb),
+    (0x33, 0xc7, 0xde),
+    (0xff, 0xff, 0xff),
+];
+
+/// `5;n` (256-colour palette) or `2;r;g;b` (truecolor) after a 38 or 48
+fn extended_color(codes: &mut impl Iterator<Item = u16>) -> Option<Rgb> {
+    match codes.next()? {
+        5 => Some(color_256(codes.next()?.min(255) as u8)),
+        2 => {
+            let mut channel = || codes.next().map(|c| c.min(255) as u8);
+            Some((channel()?, channel()?, channel()?))
+        }
+        _ => None,
+    }
+}
+
+fn color_256(index: u8) -> Rgb {
+    const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+    match index {
+        0..=15 => PALETTE[index as usize],
+        16..=231 => {
+            let i = index - 16;
+            (
+                LEVELS[(i / 36) as usize],
+                LEVELS[(i / 6 % 6) as usize],
+                LEVELS[(i % 6) as usize],
+            )
+        }
+        _ => {
+            let gray = 8 + (index - 232) * 10;
+            (gray, gray, gray)
+        }
+    }
+}
+
+/// Split text into its printable characters and the styled byte ranges within them.
+/// Escape sequences other than SGR (cursor movement, OSC hyperlinks) are dropped.
+pub fn parse(text: &str) -> (String, Vec<Span>) {
+    let mut plain = String::with_capacity(text.len());
+    let mut spans: Vec<Span> = Vec::new();
+    let mut style = Style::default();
+    let mut rest = text;
+
+    while let Some(esc) = rest.find('\x1b') {
+        push_styled(&mut plain, &mut spans, &rest[..esc], style);
+        let seq = &rest[esc + 1..];
+        rest = match seq.chars().next() {
+            Some('[') => {
+                // Parameters and intermediates run up to a final byte in @..~
+                let end = seq[1..]
+                    .find(|c: char| ('@'..='~').contains(&c))
+                    .map_or(seq.len(), |i| i + 1);
+                if seq[end..].starts_with('m') {
+                    style.apply(&seq[1..end]);
+                }
+                seq.get(end + 1..).unwrap_or("")
+            }
+            Some(']') => {
+                let bel = seq.find('\x07').map(|i| i + 1);
+                let st = seq.find("\x1b\\").map(|i| i + 2);
+                let end = match (bel, st) {
+                    (Some(a), Some(b)) => a.min(b),
+                    (a, b) => a.or(b).unwrap_or(seq.len()),
+                };
+                &seq[end..]
+            }
+            Some(c) => &seq[c.len_utf8()..],
+            None => "",
+        };
+    }
+    push_styled(&mut plain, &mut spans, rest, style);
+    (plain, spans)
+}
+
+fn push_styled(plain: &mut String, spans: &mut Vec<Span>, text: &str, style: Style) {
+    if text.is_empty() {
+        return;
+    }
+    let start = plain.len();
+    plain.push_str(text);
+    if style.is_plain() {
+        return;
+    }
+    match spans.last_mut() {
+        Some((range, last)) if *last == style && range.end == start => range.end = plain.len(),
+        _ => spans.push((start..plain.len(), style)),
+    }
+}
+
+/// Text with escape sequences removed
+pub fn strip(text: &str) -> String {
+    if text.contains('\x1b') {
+        parse(text).0
+    } else {
+        text.to_string()
+    }
+}
+
+/// Like `parse`, but one entry per line with ranges relative to that line
+pub fn parse_lines(text: &str) -> Vec<(String, Vec<Span>)> {
+    let (plain, spans) = parse(text);
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    for line in plain.lines() {
+        let line_range = offset..offset + line.len();
+        let line_spans = spans
+            .iter()
+            .filter(|(range, _)| range.start < line_range.end && range.end > line_range.start)
+            .map(|(range, style)| {
+                let start = range.start.max(line_range.start) - offset;
+                let end = range.end.min(line_range.end) - offset;
+                (start..end, *style)
+            })
+            .filter(|(range, _)| !range.is_empty())
+            .collect();
+        lines.push((line.to_string(), line_spans));
+        // Skip past the line break, which `lines` may have trimmed as "\r\n"
+        offset = plain[offset..]
+            .find('\n')
+            .map_or(plain.len(), |i| offset + i + 1);
+    }
+    lines
+}
+
+/// Pango markup for a single line of coloured text, such as a list row title
+pub fn to_pango_markup(text: &str) -> String {
+    let (plain, spans) = parse(text);
+    let mut markup = String::with_capacity(plain.len());
+    let mut pos = 0;
+    for (range, style) in spans {
+        markup.push_str(&escape_markup(&plain[pos..range.start]));
+        markup.push_str(&span_open(&style));
+        markup.push_str(&escape_markup(&plain[range.clone()]));
+        markup.push_str("</span>");
+        pos = range.end;
+    }
+    markup.push_str(&escape_markup(&plain[pos..]));
+    markup
+}
+
+fn span_open(style: &Style) -> String {
+    let mut attrs = String::from("<span");
+    if let Some((r, g, b)) = style.fg {
+        attrs.push_str(&format!(" foreground='#{:02x}{:02x}{:02x}'", r, g, b));
+    }
+    if let Some((r, g, b)) = style.bg {
+        attrs.push_str(&format!(" background='#{:02x}{:02x}{:02x}'", r, g, b));
+    }
+    if style.bold {
+        attrs.push_str(" weight='bold'");
+    }
+    if style.italic {
+        attrs.push_str(" style='italic'");
+    }
+    if style.underline {
+        attrs.push_str(" underline='single'");
+    }
+    attrs.push('>');
+    attrs
+}
+
+fn escape_markup(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\'', "&apos;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn basic_colors_and_reset() {
+        let (plain, spans) = parse("a\x1b[1;31mred\x1b[0m b");
+        assert_eq!(plain, "ared b");
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].0, 1..4);
+        assert_eq!(spans[0].1.fg, Some(PALETTE[1]));
+        assert!(spans[0].1.bold);
+    }
+
+    #[test]
+    fn extended_colors() {
+        let (_, spans) = parse("\x1b[38;5;196mx\x1b[48;2;1;2;3my");
+        assert_eq!(spans[0].1.fg, Some((255, 0, 0)));
+        assert_eq!(spans[1].1.bg, Some((1, 2, 3)));
+        assert_eq!(spans[1].1.fg, Some((255, 0, 0)));
+        assert_eq!(color_256(244), (128, 128, 128));
+    }
+
+    #[test]
+    fn attributes_toggle_off() {
+        let (_, spans) = parse("\x1b[3;4mab\x1b[23mc\x1b[24md");
+        assert_eq!(spans[0].0, 0..2);
+        assert!(spans[0].1.italic && spans[0].1.underline);
+        assert_eq!(spans[1].0, 2..3);
+        assert!(!spans[1].1.italic && spans[1].1.underline);
+        assert_eq!(spans.len(), 2);
+    }
+
+    #[test]
+    fn other_sequences_are_dropped() {
+        let text = "\x1b[2K\x1b]8;;https://x.org\x1b\\link\x1b]8;;\x07 done\x1b[m";
+        assert_eq!(strip(text), "link done");
+    }
+
+    #[test]
+    fn styles_continue_across_lines() {
+        let lines = parse_lines("\x1b[32mone\r\ntwo\x1b[0m\nthree");
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].0, "one");
+        assert_eq!(lines[0].1[0].0, 0..3);
+        assert_eq!(lines[1].1[0].0, 0..3);
+        assert!(lines[2].1.is_empty());
+    }
+
+    #[test]
+    fn markup_escapes_text() {
+        assert_eq!(
+            to_pango_markup("\x1b[1m<b>\x1b[0m & co"),
+            "<span weight='bold'>&lt;b&gt;</span> &amp; co"
+        );
+    }
+}
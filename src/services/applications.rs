@@ -0,0 +1,581 @@
+use crate::constants::MAX_ITEMS;
+use crate::domain::Launcher;
+use crate::domain::item::Item;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+
+type Group = HashMap<String, String>;
+
+/// The user's locale and desktop, which decide what entries show and how they are named
+#[derive(Debug, Clone, Default)]
+pub struct Environment {
+    /// `LC_MESSAGES` style locale, e.g. "de_DE.UTF-8"
+    pub locale: Option<String>,
+    /// Names from `XDG_CURRENT_DESKTOP`, e.g. "GNOME"
+    pub desktops: Vec<String>,
+}
+
+impl Environment {
+    pub fn current() -> Self {
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .into_iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty());
+        let desktops = std::env::var("XDG_CURRENT_DESKTOP")
+            .map(|value| split_list(&value, ':'))
+            .unwrap_or_default();
+        Self { locale, desktops }
+    }
+
+    /// Keys to try for a localized value, most specific first:
+    /// lang_COUNTRY@MODIFIER, lang_COUNTRY, lang@MODIFIER, lang
+    fn locale_keys(&self) -> Vec<String> {
+        let Some(locale) = self.locale.as_deref() else {
+            return Vec::new();
+        };
+        let (rest, modifier) = match locale.split_once('@') {
+            Some((rest, modifier)) => (rest, Some(modifier)),
+            None => (locale, None),
+        };
+        let rest = rest.split('.').next().unwrap_or(rest);
+        let (lang, country) = match rest.split_once('_') {
+            Some((lang, country)) => (lang, Some(country)),
+            None => (rest, None),
+        };
+        if lang.is_empty() || lang == "C" || lang == "POSIX" {
+            return Vec::new();
+        }
+        let mut keys = Vec::new();
+        if let (Some(country), Some(modifier)) = (country, modifier) {
+            keys.push(format!("{}_{}@{}", lang, country, modifier));
+        }
+        if let Some(country) = country {
+            keys.push(format!("{}_{}", lang, country));
+        }
+        if let Some(modifier) = modifier {
+            keys.push(format!("{}@{}", lang, modifier));
+        }
+        keys.push(lang.to_string());
+        keys
+    }
+}
+
+/// `applications` folders in XDG order, the user's own first
+pub fn data_dirs() -> Vec<PathBuf> {
+    let data_home = std::env::var_os("XDG_DATA_HOME")
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".local/share")));
+    let data_dirs = std::env::var("XDG_DATA_DIRS")
+        .ok()
+        .filter(|value| !value.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".to_string());
+    data_home
+        .into_iter()
+        .chain(
+            data_dirs
+                .split(':')
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from),
+        )
+        .map(|dir| dir.join("applications"))
+        .collect()
+}
+
+/// Applications and their actions found under `dirs`, sorted by name.
+/// A desktop file ID seen in an earlier folder shadows the same ID in later ones.
+pub fn list(category: &str, dirs: &[PathBuf], env: &Environment) -> Vec<Item> {
+    let mut seen = HashSet::new();
+    let mut apps = Vec::new();
+    for dir in dirs {
+        let walk = walkdir::WalkDir::new(dir)
+            .follow_links(true)
+            .sort_by_file_name();
+        for entry in walk.into_iter().flatten() {
+            let path = entry.path();
+            if !entry.file_type().is_file()
+                || path.extension().and_then(|ext| ext.to_str()) != Some("desktop")
+            {
+                continue;
+            }
+            let Some(id) = desktop_id(dir, path) else {
+                continue;
+            };
+            if !seen.insert(id.clone()) {
+                continue;
+            }
+            let Ok(text) = std::fs::read_to_string(path) else {
+                continue;
+            };
+            apps.extend(parse_desktop_file(&text, &id, path, category, env));
+        }
+    }
+    apps.sort_by_cached_key(|(name, _)| name.to_lowercase());
+
+    apps.into_iter()
+        .flat_map(|(_, items)| items)
+        .take(MAX_ITEMS)
+        .collect()
+}
+
+/// "kde/org.kde.dolphin.desktop" under `dir` has the ID "kde-org.kde.dolphin.desktop"
+fn desktop_id(dir: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(dir).ok()?;
+    let parts: Vec<String> = relative
+        .components()
+        .map(|part| part.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    Some(parts.join("-"))
+}
+
+/// The application's name and its items, the application first and then its actions.
+/// Hidden, non-application and filtered-out entries have no items.
+fn parse_desktop_file(
+    text: &str,
+    id: &str,
+    path: &Path,
+    category: &str,
+    env: &Environment,
+) -> Option<(String, Vec<Item>)> {
+    let groups = parse_groups(text);
+    let entry = groups.get("Desktop Entry")?;
+    if entry.get("Type").map(String::as_str) != Some("Application")
+        || is_true(entry, "Hidden")
+        || is_true(entry, "NoDisplay")
+        || !shown_in(entry, &env.desktops)
+        || entry
+            .get("TryExec")
+            .is_some_and(|program| !is_installed(program))
+    {
+        return None;
+    }
+    let name = localized(entry, "Name", env)?;
+    let icon = entry.get("Icon").filter(|icon| !icon.is_empty()).cloned();
+    let terminal = is_true(entry, "Terminal");
+    let dir = entry
+        .get("Path")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from);
+    let description = localized(entry, "Comment", env)
+        .or_else(|| localized(entry, "GenericName", env))
+        .unwrap_or_else(|| id.to_string());
+
+    let launcher = |exec: &str, icon: &Option<String>| {
+        Some(Launcher {
+            argv: expand_exec(exec, &name, icon.as_deref(), path)?,
+            terminal,
+            dir: dir.clone(),
+        })
+    };
+    let mut items = Vec::new();
+    if let Some(launch) = entry.get("Exec").and_then(|exec| launcher(exec, &icon)) {
+        items.push(
+            Item::application(name.clone(), description.clone(), category, launch)
+                .with_icon(icon.clone()),
+        );
+    }
+    let actions = entry
+        .get("Actions")
+        .map(|actions| split_list(actions, ';'))
+        .unwrap_or_default();
+    for action in actions {
+        let Some(group) = groups.get(&format!("Desktop Action {}", action)) else {
+            continue;
+        };
+        let (Some(action_name), Some(exec)) = (localized(group, "Name", env), group.get("Exec"))
+        else {
+            continue;
+        };
+        let action_icon = group.get("Icon").cloned().or_else(|| icon.clone());
+        if let Some(launch) = launcher(exec, &action_icon) {
+            items.push(
+                Item::application(
+                    format!("{}: {}", name, action_name),
+                    description.clone(),
+                    category,
+                    launch,
+                )
+                .with_icon(action_icon),
+            );
+        }
+    }
+    Some((name, items))
+}
+
+fn parse_groups(text: &str) -> HashMap<String, Group> {
+    let mut groups: HashMap<String, Group> = HashMap::new();
+    let mut current: Option<String> = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            current = Some(name.to_string());
+            continue;
+        }
+        let (Some(group), Some((key, value))) = (&current, line.split_once('=')) else {
+            continue;
+        };
+        // The first occurrence of a key wins
+        groups
+            .entry(group.clone())
+            .or_default()
+            .entry(key.trim().to_string())
+            .or_insert_with(|| unescape(value.trim()));
+    }
+    groups
+}
+
+/// Undo the escapes allowed in desktop file values
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('s') => out.push(' '),
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('\\') => out.push('\\'),
+            // Left for list and Exec parsing, which give these their own meaning
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+fn split_list(value: &str, separator: char) -> Vec<String> {
+    value
+        .split(separator)
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn is_true(group: &Group, key: &str) -> bool {
+    group.get(key).is_some_and(|value| value == "true")
+}
+
+fn localized(group: &Group, key: &str, env: &Environment) -> Option<String> {
+    env.locale_keys()
+        .iter()
+        .find_map(|locale| group.get(&format!("{}[{}]", key, locale)))
+        .or_else(|| group.get(key))
+        .filter(|value| !value.is_empty())
+        .cloned()
+}
+
+/// `OnlyShowIn` and `NotShowIn` against the current desktops
+fn shown_in(entry: &Group, desktops: &[String]) -> bool {
+    let matches = |key: &str| {
+        entry.get(key).map(|value| {
+            split_list(value, ';')
+                .iter()
+                .any(|desktop| desktops.iter().any(|d| d.eq_ignore_ascii_case(desktop)))
+        })
+    };
+    matches("OnlyShowIn").unwrap_or(true) && !matches("NotShowIn").unwrap_or(false)
+}
+
+fn is_installed(program: &str) -> bool {
+    let program = Path::new(program);
+    if program.is_absolute() {
+        return program.is_file();
+    }
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+/// Split an Exec line into arguments and expand its field codes.
+/// Pantry starts applications without files, so file and URL codes are dropped.
+fn expand_exec(exec: &str, name: &str, icon: Option<&str>, path: &Path) -> Option<Vec<String>> {
+    let mut argv = Vec::new();
+    for arg in split_exec(exec)? {
+        match arg.as_str() {
+            "%f" | "%F" | "%u" | "%U" | "%d" | "%D" | "%n" | "%N" | "%v" | "%m" => {}
+            "%i" => {
+                if let Some(icon) = icon {
+                    argv.push("--icon".to_string());
+                    argv.push(icon.to_string());
+                }
+            }
+            _ => argv.push(expand_codes(&arg, name, path)),
+        }
+    }
+    (!argv.is_empty()).then_some(argv)
+}
+
+fn expand_codes(arg: &str, name: &str, path: &Path) -> String {
+    let mut out = String::with_capacity(arg.len());
+    let mut chars = arg.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => out.push('%'),
+            Some('c') => out.push_str(name),
+            Some('k') => out.push_str(&path.to_string_lossy()),
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Arguments separated by spaces; double quotes group them, with `\"`, `` \` ``, `\$`
+/// and `\\` standing for the character itself. `None` for an unterminated quote.
+fn split_exec(exec: &str) -> Option<Vec<String>> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut quoted = false;
+    let mut chars = exec.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_arg = true;
+            }
+            '\\' if quoted => current.push(chars.next()?),
+            ' ' | '\t' if !quoted => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            _ => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if quoted {
+        return None;
+    }
+    if in_arg {
+        args.push(current);
+    }
+    Some(args)
+}
+
+/// Start an application detached from pantry, which exits right after
+pub fn launch(launcher: &Launcher) -> io::Result<()> {
+    use std::os::unix::process::CommandExt;
+
+    let mut argv = launcher.argv.clone();
+    if launcher.terminal {
+        let terminal = std::env::var("TERMINAL")
+            .ok()
+            .filter(|terminal| !terminal.is_empty())
+            .unwrap_or_else(|| "xterm".to_string());
+        argv.splice(0..0, [terminal, "-e".to_string()]);
+    }
+    let (program, args) = argv
+        .split_first()
+        .ok_or_else(|| io::Error::other("Empty Exec line"))?;
+    let mut command = std::process::Command::new(program);
+    if let Some(dir) = &launcher.dir {
+        command.current_dir(dir);
+    }
+    command
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .process_group(0)
+        .spawn()
+        .map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn german() -> Environment {
+        Environment {
+            locale: Some("de_DE.UTF-8".to_string()),
+            desktops: vec!["GNOME".to_string()],
+        }
+    }
+
+    const FIREFOX: &str = "\
+[Desktop Entry]
+Type=Application
+Name=Firefox
+Name[de]=Firefox Webbrowser
+Comment=Browse the Web
+Exec=firefox %u
+Icon=firefox
+Path=/opt/firefox
+Actions=new-window;private;
+
+[Desktop Action new-window]
+Name=New Window
+Name[de]=Neues Fenster
+Exec=firefox --new-window %u
+
+[Desktop Action private]
+Name=Private Window
+Exec=firefox --private-window %u
+";
+
+    #[test]
+    fn applications_and_actions_become_items() {
+        let (name, items) = parse_desktop_file(
+            FIREFOX,
+            "firefox.desktop",
+            Path::new("/a/firefox.desktop"),
+            "apps",
+            &german(),
+        )
+        .unwrap();
+        assert_eq!(name, "Firefox Webbrowser");
+        let titles: Vec<&str> = items.iter().map(|item| item.title.as_str()).collect();
+        assert_eq!(
+            titles,
+            vec![
+                "Firefox Webbrowser",
+                "Firefox Webbrowser: Neues Fenster",
+                "Firefox Webbrowser: Private Window",
+            ]
+        );
+        let launch = items[1].launch.as_ref().unwrap();
+        assert_eq!(launch.argv, vec!["firefox", "--new-window"]);
+        assert_eq!(launch.dir.as_deref(), Some(Path::new("/opt/firefox")));
+        assert_eq!(items[1].icon.as_deref(), Some("firefox"));
+        assert_eq!(items[0].value, "Browse the Web");
+    }
+
+    #[test]
+    fn launch_starts_in_the_entry_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let launcher = Launcher {
+            argv: vec!["sh".to_string(), "-c".to_string(), "pwd > cwd".to_string()],
+            terminal: false,
+            dir: Some(dir.path().to_path_buf()),
+        };
+        launch(&launcher).unwrap();
+        let cwd = dir.path().join("cwd");
+        for _ in 0..100 {
+            if let Ok(text) = std::fs::read_to_string(&cwd)
+                && text.ends_with('\n')
+            {
+                assert_eq!(
+                    Path::new(text.trim_end()).canonicalize().unwrap(),
+                    dir.path().canonicalize().unwrap()
+                );
+                return;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        panic!("the launched command never wrote its working directory");
+    }
+
+    #[test]
+    fn hidden_and_filtered_entries_are_skipped() {
+        let env = german();
+        let entry = |extra: &str| {
+            format!(
+                "[Desktop Entry]\nType=Application\nName=X\nExec=x\n{}\n",
+                extra
+            )
+        };
+        let path = Path::new("/a/x.desktop");
+        let parse = |text: &str| parse_desktop_file(text, "x.desktop", path, "apps", &env);
+        assert!(parse(&entry("")).is_some());
+        assert!(parse(&entry("NoDisplay=true")).is_none());
+        assert!(parse(&entry("Hidden=true")).is_none());
+        assert!(parse(&entry("OnlyShowIn=KDE;")).is_none());
+        assert!(parse(&entry("OnlyShowIn=KDE;GNOME;")).is_some());
+        assert!(parse(&entry("NotShowIn=GNOME;")).is_none());
+        assert!(parse(&entry("TryExec=/nonexistent/pantry-test")).is_none());
+        assert!(parse("[Desktop Entry]\nType=Link\nName=X\nURL=https://x\n").is_none());
+    }
+
+    #[test]
+    fn locale_keys_from_most_specific() {
+        let env = Environment {
+            locale: Some("sr_RS.UTF-8@latin".to_string()),
+            desktops: Vec::new(),
+        };
+        assert_eq!(
+            env.locale_keys(),
+            vec!["sr_RS@latin", "sr_RS", "sr@latin", "sr"]
+        );
+        let c = Environment {
+            locale: Some("C.UTF-8".to_string()),
+            desktops: Vec::new(),
+        };
+        assert!(c.locale_keys().is_empty());
+    }
+
+    #[test]
+    fn exec_lines_are_split_and_expanded() {
+        let path = Path::new("/usr/share/applications/x.desktop");
+        assert_eq!(
+            expand_exec(
+                r#"sh -c "echo \"hi\" \$HOME" %c %F %i 100%%"#,
+                "My App",
+                Some("x"),
+                path
+            )
+            .unwrap(),
+            vec![
+                "sh",
+                "-c",
+                "echo \"hi\" $HOME",
+                "My App",
+                "--icon",
+                "x",
+                "100%"
+            ]
+        );
+        assert_eq!(
+            expand_exec("app --desktop=%k", "A", None, path).unwrap(),
+            vec!["app", "--desktop=/usr/share/applications/x.desktop"]
+        );
+        assert_eq!(expand_exec("app %i", "A", None, path).unwrap(), vec!["app"]);
+        assert_eq!(expand_exec(r#"app "open"#, "A", None, path), None);
+        assert_eq!(unescape(r"a\sb\\c"), r"a b\c");
+    }
+
+    #[test]
+    fn earlier_folders_shadow_later_ones() {
+        let user = tempfile::tempdir().unwrap();
+        let system = tempfile::tempdir().unwrap();
+        let app = |name: &str| {
+            format!(
+                "[Desktop Entry]\nType=Application\nName={}\nExec=app\n",
+                name
+            )
+        };
+        std::fs::write(
+            user.path().join("a.desktop"),
+            "[Desktop Entry]\nHidden=true\n",
+        )
+        .unwrap();
+        std::fs::write(system.path().join("a.desktop"), app("Alpha")).unwrap();
+        std::fs::create_dir(system.path().join("kde")).unwrap();
+        std::fs::write(system.path().join("kde/b.desktop"), app("beta")).unwrap();
+        std::fs::write(system.path().join("c.desktop"), app("Gamma")).unwrap();
+
+        let dirs = vec![user.path().to_path_buf(), system.path().to_path_buf()];
+        let items = list("apps", &dirs, &Environment::default());
+        let titles: Vec<&str> = items.iter().map(|item| item.title.as_str()).collect();
+        assert_eq!(titles, vec!["beta", "Gamma"]);
+        assert_eq!(items[0].value, "kde-b.desktop");
+        assert_eq!(items[0].category, "apps");
+    }
+}
@@ -0,0 +1,213 @@
+use crate::constants::{CLIPBOARD_TIMEOUT_SECS, MAX_ITEMS};
+use crate::domain::ClipKind;
+use crate::domain::item::Item;
+use crate::services::preview::ImageDecoder;
+use crate::services::process::CommandExecutor;
+use crate::utils::escape_shell_arg;
+use std::io;
+
+/// Clipboard history, newest first, as `cliphist list` prints it
+pub fn list(category: &str, executor: &dyn CommandExecutor) -> io::Result<Vec<Item>> {
+    let output = executor
+        .execute_with_timeout("cliphist", &["list"], CLIPBOARD_TIMEOUT_SECS)
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => {
+                io::Error::new(e.kind(), "The clipboard source needs cliphist")
+            }
+            _ => e,
+        })?;
+    if !output.success {
+        return Err(io::Error::other("cliphist list failed"));
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    Ok(text
+        .lines()
+        .filter_map(parse_entry)
+        .take(MAX_ITEMS)
+        .map(|(id, title, kind)| Item::clipboard(title, id, category, kind))
+        .collect())
+}
+
+/// An "id<TAB>preview" line as id, title and kind.
+/// cliphist previews images as "[[ binary data 2 MiB png 1920x1080 ]]".
+fn parse_entry(line: &str) -> Option<(String, String, ClipKind)> {
+    let (id, preview) = line.split_once('\t')?;
+    let id = id.trim();
+    if id.is_empty() || !id.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let binary = preview
+        .trim()
+        .strip_prefix("[[ binary data ")
+        .and_then(|rest| rest.strip_suffix(" ]]"));
+    let Some(binary) = binary else {
+        return Some((id.to_string(), preview.trim().to_string(), ClipKind::Text));
+    };
+    let fields: Vec<&str> = binary.split_whitespace().collect();
+    let (size, format, dimensions) = match fields.as_slice() {
+        [amount, unit, format, dimensions] => {
+            (format!("{} {}", amount, unit), *format, Some(*dimensions))
+        }
+        [amount, unit, format] => (format!("{} {}", amount, unit), *format, None),
+        _ => return Some((id.to_string(), "Binary data".to_string(), ClipKind::Text)),
+    };
+    let title = match dimensions {
+        Some(dimensions) => format!(
+            "{} image, {} ({})",
+            format.to_uppercase(),
+            dimensions.replace('x', "×"),
+            size
+        ),
+        None => format!("{} image ({})", format.to_uppercase(), size),
+    };
+    let kind = ClipKind::Image {
+        format: format.to_lowercase(),
+    };
+    Some((id.to_string(), title, kind))
+}
+
+/// Put an entry back on the clipboard, with wl-copy under Wayland and xclip under X11
+pub fn copy(item: &Item, executor: &dyn CommandExecutor) -> io::Result<()> {
+    use std::io::Write;
+
+    let kind = item.clip.as_ref().unwrap_or(&ClipKind::Text);
+    let wayland = std::env::var_os("WAYLAND_DISPLAY").is_some();
+    // Decoded on its own, as a pipeline would only report how the copy tool exited
+    let decoded = executor
+        .execute_with_timeout("cliphist", &["decode", &item.value], CLIPBOARD_TIMEOUT_SECS)
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => io::Error::new(e.kind(), "Copying needs cliphist"),
+            _ => e,
+        })?;
+    if !decoded.success {
+        return Err(io::Error::other("cliphist decode failed"));
+    }
+    let mut temp_file = tempfile::NamedTempFile::new()?;
+    temp_file.write_all(&decoded.stdout)?;
+    let command = copy_command(&temp_file.path().to_string_lossy(), kind, wayland);
+    let output = executor.execute_with_timeout("sh", &["-c", &command], CLIPBOARD_TIMEOUT_SECS)?;
+    if output.success {
+        Ok(())
+    } else if wayland {
+        Err(io::Error::other("Copying needs wl-copy"))
+    } else {
+        Err(io::Error::other("Copying needs xclip"))
+    }
+}
+
+/// The command copying the decoded entry at `source`
+fn copy_command(source: &str, kind: &ClipKind, wayland: bool) -> String {
+    let target = match (wayland, kind) {
+        (true, _) => "wl-copy".to_string(),
+        (false, ClipKind::Text) => "xclip -selection clipboard".to_string(),
+        (false, ClipKind::Image { format }) => format!(
+            "xclip -selection clipboard -t {}",
+            escape_shell_arg(&format!("image/{}", format))
+        ),
+    };
+    // Both tools keep running to serve the selection, so must not hold our pipes open
+    format!("{} < {} >/dev/null 2>&1", target, escape_shell_arg(source))
+}
+
+/// Remove an entry from the history
+pub fn delete(id: &str, executor: &dyn CommandExecutor) -> io::Result<()> {
+    let command = format!("printf '%s\\n' {} | cliphist delete", escape_shell_arg(id));
+    let output = executor.execute_with_timeout("sh", &["-c", &command], CLIPBOARD_TIMEOUT_SECS)?;
+    if output.success {
+        Ok(())
+    } else {
+        Err(io::Error::other("cliphist delete failed"))
+    }
+}
+
+/// An image entry decoded to fit a `size`×`size` box, for list rows
+pub fn thumbnail(
+    id: &str,
+    size: i32,
+    executor: &dyn CommandExecutor,
+    decoder: &dyn ImageDecoder,
+) -> Option<(Vec<u8>, i32, i32)> {
+    use std::io::Write;
+
+    let output = executor
+        .execute_with_timeout("cliphist", &["decode", id], CLIPBOARD_TIMEOUT_SECS)
+        .ok()?;
+    if !output.success || output.stdout.is_empty() {
+        return None;
+    }
+    let mut temp_file = tempfile::NamedTempFile::new().ok()?;
+    temp_file.write_all(&output.stdout).ok()?;
+    decoder.load_from_path(temp_file.path(), size, size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::SourceMode;
+    use crate::services::process::MockExec;
+
+    #[test]
+    fn lists_text_and_image_entries() {
+        let exec = MockExec::new().push_ok(
+            true,
+            b"12\thello world\n11\t[[ binary data 2 MiB png 1920x1080 ]]\nbad line\n".to_vec(),
+        );
+        let items = list("clip", &exec).unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].title, "hello world");
+        assert_eq!(items[0].value, "12");
+        assert_eq!(items[0].clip, Some(ClipKind::Text));
+        assert_eq!(items[0].source, SourceMode::Clipboard);
+        assert_eq!(items[1].title, "PNG image, 1920×1080 (2 MiB)");
+        assert_eq!(
+            items[1].clip,
+            Some(ClipKind::Image {
+                format: "png".to_string()
+            })
+        );
+        assert_eq!(items[1].category, "clip");
+    }
+
+    #[test]
+    fn missing_cliphist_is_reported() {
+        let exec = MockExec::new().push_err(io::Error::from(io::ErrorKind::NotFound));
+        let err = list("clip", &exec).unwrap_err();
+        assert_eq!(err.to_string(), "The clipboard source needs cliphist");
+    }
+
+    #[test]
+    fn copy_commands_pick_a_tool() {
+        let png = ClipKind::Image {
+            format: "png".to_string(),
+        };
+        assert_eq!(
+            copy_command("/tmp/clip", &ClipKind::Text, true),
+            "wl-copy < '/tmp/clip' >/dev/null 2>&1"
+        );
+        assert_eq!(
+            copy_command("/tmp/clip", &png, false),
+            "xclip -selection clipboard -t 'image/png' < '/tmp/clip' >/dev/null 2>&1"
+        );
+    }
+
+    #[test]
+    fn copy_and_delete_report_failures() {
+        let item = Item::clipboard("hi", "3", "clip", ClipKind::Text);
+        // Responses are taken last first: decode then copy, a failed decode, a failed copy
+        let exec = MockExec::new()
+            .push_ok(false, Vec::new())
+            .push_ok(true, b"hi".to_vec())
+            .push_ok(false, Vec::new())
+            .push_ok(true, Vec::new())
+            .push_ok(true, b"hi".to_vec());
+        assert!(copy(&item, &exec).is_ok());
+        assert_eq!(
+            copy(&item, &exec).unwrap_err().to_string(),
+            "cliphist decode failed"
+        );
+        assert!(copy(&item, &exec).is_err());
+        let exec = MockExec::new().push_ok(false, Vec::new());
+        assert!(delete("3", &exec).is_err());
+        assert!(delete("3", &exec).is_ok());
+    }
+}
@@ -0,0 +1,124 @@
+use crate::cache::key::content_hash;
+use crate::domain::DisplayMode;
+use crate::domain::item::Item;
+use std::collections::HashMap;
+
+/// Keep only picture items whose file contents appear more than once, grouped together
+pub fn group_duplicates(items: Vec<Item>) -> Vec<Item> {
+    let mut by_size: HashMap<u64, Vec<Item>> = HashMap::new();
+    let mut size_order = Vec::new();
+
+    for item in items {
+        if !matches!(item.display, DisplayMode::Picture) {
+            continue;
+        }
+        let path = crate::utils::expand_tilde(&item.value);
+        let Ok(meta) = std::fs::metadata(&path) else {
+            continue;
+        };
+        if !meta.is_file() {
+            continue;
+        }
+        let bucket = by_size.entry(meta.len()).or_default();
+        if bucket.is_empty() {
+            size_order.push(meta.len());
+        }
+        bucket.push(item);
+    }
+
+    // Only files sharing a size can be identical, so most files are never read
+    let mut groups: Vec<Vec<Item>> = Vec::new();
+    for size in size_order {
+        let Some(bucket) = by_size.remove(&size) else {
+            continue;
+        };
+        if bucket.len() < 2 {
+            continue;
+        }
+
+        let mut by_hash: HashMap<String, usize> = HashMap::new();
+        let mut hashed: Vec<Vec<Item>> = Vec::new();
+        for item in bucket {
+            let Some(hash) = content_hash(&crate::utils::expand_tilde(&item.value)) else {
+                continue;
+            };
+            match by_hash.get(&hash) {
+                Some(&idx) => hashed[idx].push(item),
+                None => {
+                    by_hash.insert(hash, hashed.len());
+                    hashed.push(vec![item]);
+                }
+            }
+        }
+        groups.extend(hashed.into_iter().filter(|group| group.len() >= 2));
+    }
+
+    groups
+        .into_iter()
+        .enumerate()
+        .flat_map(|(idx, group)| {
+            group.into_iter().map(move |mut item| {
+                item.title = format!("[{}] {}", idx + 1, item.title);
+                item
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn picture(title: &str, path: &std::path::Path) -> Item {
+        Item::config(title, path.to_string_lossy(), "pics", DisplayMode::Picture)
+    }
+
+    #[test]
+    fn groups_identical_files() {
+        let dir = tempdir().unwrap();
+        let a = dir.path().join("a.png");
+        let b = dir.path().join("b.png");
+        let c = dir.path().join("c.png");
+        fs::write(&a, b"same").unwrap();
+        fs::write(&b, b"diff").unwrap();
+        fs::write(&c, b"same").unwrap();
+
+        let items = vec![picture("a", &a), picture("b", &b), picture("c", &c)];
+        let dups = group_duplicates(items);
+        let titles: Vec<&str> = dups.iter().map(|i| i.title.as_str()).collect();
+        assert_eq!(titles, vec!["[1] a", "[1] c"]);
+    }
+
+    #[test]
+    fn separate_groups_are_numbered() {
+        let dir = tempdir().unwrap();
+        let paths: Vec<_> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|n| dir.path().join(format!("{n}.png")))
+            .collect();
+        fs::write(&paths[0], b"one").unwrap();
+        fs::write(&paths[1], b"two").unwrap();
+        fs::write(&paths[2], b"one").unwrap();
+        fs::write(&paths[3], b"two").unwrap();
+
+        let items = paths
+            .iter()
+            .zip(["a", "b", "c", "d"])
+            .map(|(p, t)| picture(t, p))
+            .collect();
+        let dups = group_duplicates(items);
+        let titles: Vec<&str> = dups.iter().map(|i| i.title.as_str()).collect();
+        assert_eq!(titles, vec!["[1] a", "[1] c", "[2] b", "[2] d"]);
+    }
+
+    #[test]
+    fn text_items_are_ignored() {
+        let items = vec![
+            Item::config("a", "same", "t", DisplayMode::Text),
+            Item::config("b", "same", "t", DisplayMode::Text),
+        ];
+        assert!(group_duplicates(items).is_empty());
+    }
+}
@@ -1,7 +1,9 @@
 use crate::constants::MAX_ITEMS;
 use crate::domain::DisplayMode;
 use crate::domain::item::Item;
+use crate::services::preview::detector;
 use crate::services::process::CommandExecutor;
+use std::path::Path;
 
 /// Process item for display (expand directories, etc.)
 pub fn process_for_display(item: &Item) -> Vec<Item> {
@@ -18,17 +20,7 @@ pub fn process_for_display(item: &Item) -> Vec<Item> {
                 .flatten()
             {
                 let path = entry.path();
-                if path.is_file()
-                    && path
-                        .extension()
-                        .and_then(|s| s.to_str())
-                        .map(|ext| {
-                            let ext_lower = ext.to_lowercase();
-                            crate::constants::IMAGE_EXTENSIONS.contains(&ext_lower.as_str())
-                                || crate::constants::VIDEO_EXTENSIONS.contains(&ext_lower.as_str())
-                        })
-                        .unwrap_or(false)
-                {
+                if path.is_file() && is_media_file(path) {
                     let path_str = path.to_string_lossy();
                     let mut child = item.clone();
                     child.title = format!(
@@ -52,6 +44,25 @@ pub fn process_for_display(item: &Item) -> Vec<Item> {
     }
 }
 
+/// Images, videos and audio by extension, or by their leading bytes when the name doesn't say
+pub(crate) fn is_media_file(path: &Path) -> bool {
+    let known = path
+        .extension()
+        .and_then(|s| s.to_str())
+        .is_some_and(|ext| {
+            let ext_lower = ext.to_lowercase();
+            crate::constants::IMAGE_EXTENSIONS.contains(&ext_lower.as_str())
+                || crate::constants::VIDEO_EXTENSIONS.contains(&ext_lower.as_str())
+                || crate::constants::AUDIO_EXTENSIONS.contains(&ext_lower.as_str())
+        });
+    known
+        || detector::detect_mime(path).is_some_and(|mime| {
+            ["image/", "video/", "audio/"]
+                .iter()
+                .any(|kind| mime.starts_with(kind))
+        })
+}
+
 /// Process dynamic source - execute list command and create items
 pub fn process_dynamic_source(
     list_command: &str,
@@ -76,21 +87,22 @@ pub fn process_dynamic_source(
             continue;
         }
 
+        // id, display text and an optional line number, separated by tabs
         let parts: Vec<&str> = line.split('\t').collect();
         let (id, display_text) = if parts.len() >= 2 {
             (parts[0].trim(), parts[1].trim())
         } else {
             (line, line)
         };
+        let line_number = parts.get(2).and_then(|n| n.trim().parse().ok());
 
         let sanitized_id = id.replace('\0', "");
         let sanitized_display_text = display_text.replace('\0', "");
 
-        items.push(Item::dynamic(
-            sanitized_display_text,
-            sanitized_id,
-            template.clone(),
-        ));
+        items.push(
+            Item::dynamic(sanitized_display_text, sanitized_id, template.clone())
+                .with_line(line_number),
+        );
 
         if items.len() >= MAX_ITEMS {
             break;
@@ -106,6 +118,26 @@ mod tests {
     use crate::domain::SourceMode;
     use crate::services::process::MockExec;
 
+    #[test]
+    fn directories_expand_to_media_by_content() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("scan"), b"\x89PNG\r\n\x1a\n").unwrap();
+        std::fs::write(dir.path().join("icon.svg"), b"<svg></svg>").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), b"hello").unwrap();
+        let item = Item::config(
+            "pics",
+            dir.path().to_string_lossy().to_string(),
+            "",
+            DisplayMode::Picture,
+        );
+        let mut names: Vec<String> = process_for_display(&item)
+            .iter()
+            .map(|child| child.value.rsplit('/').next().unwrap().to_string())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["icon.svg", "scan"]);
+    }
+
     #[test]
     fn dynamic_single_line() {
         let exec = MockExec::new().push_ok(true, b"item1\tValue 1\n".to_vec());
@@ -140,6 +172,15 @@ mod tests {
         assert_eq!(items[0].preview_template.as_deref(), Some("preview {}"));
     }
 
+    #[test]
+    fn dynamic_third_field_is_line() {
+        let exec =
+            MockExec::new().push_ok(true, b"src/main.rs\tmain.rs:12: fn main\t12\n".to_vec());
+        let items = process_dynamic_source("rg -n main", "cat {}", &exec).unwrap();
+        assert_eq!(items[0].value, "src/main.rs");
+        assert_eq!(items[0].line, Some(12));
+    }
+
     #[test]
     fn dynamic_command_failure() {
         let exec = MockExec::new().push_ok(false, Vec::new());
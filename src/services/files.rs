@@ -0,0 +1,224 @@
+use crate::config::{Category, Config};
+use crate::constants::MAX_ITEMS;
+use crate::domain::item::Item;
+use crate::domain::{DisplayMode, SourceMode};
+use crate::services::expansion;
+use crate::services::preview::detector;
+use std::path::{Path, PathBuf};
+
+/// Where a `files` source is, and which entries it shows
+#[derive(Debug, Clone, PartialEq)]
+pub struct Browser {
+    category: String,
+    root: PathBuf,
+    dir: PathBuf,
+    show_hidden: bool,
+    gitignore: bool,
+}
+
+impl Browser {
+    pub fn new(category: &str, root: &Path, gitignore: bool) -> Self {
+        Self {
+            category: category.to_string(),
+            root: root.to_path_buf(),
+            dir: root.to_path_buf(),
+            show_hidden: false,
+            gitignore,
+        }
+    }
+
+    pub fn for_category(name: &str, category: &Category) -> Self {
+        let root = crate::utils::expand_tilde(category.root.as_deref().unwrap_or("~"));
+        Self::new(name, &root, category.gitignore.unwrap_or(false))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Entries of the current folder, folders first, then by name; reads the disk, so
+    /// belongs on a worker
+    pub fn list(&self) -> Vec<Item> {
+        let walk = ignore::WalkBuilder::new(&self.dir)
+            .max_depth(Some(1))
+            .hidden(!self.show_hidden)
+            .ignore(false)
+            .git_ignore(self.gitignore)
+            .git_global(self.gitignore)
+            .git_exclude(self.gitignore)
+            .parents(self.gitignore)
+            .require_git(false)
+            .build();
+        let mut entries: Vec<(bool, String, PathBuf)> = walk
+            .flatten()
+            .filter(|entry| entry.depth() > 0)
+            .map(|entry| {
+                let file_type = entry.file_type();
+                let path = entry.into_path();
+                let name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                // Only links need another stat to tell whether they lead to a folder
+                let is_dir = file_type.is_some_and(|file_type| {
+                    file_type.is_dir() || (file_type.is_symlink() && path.is_dir())
+                });
+                (is_dir, name, path)
+            })
+            .collect();
+        entries.sort_by_cached_key(|(is_dir, name, _)| (!is_dir, name.to_lowercase()));
+        entries.truncate(MAX_ITEMS);
+
+        entries
+            .into_iter()
+            .map(|(is_dir, name, path)| file_item(&self.category, is_dir, &name, &path))
+            .collect()
+    }
+
+    /// Descend into `path` if it is a folder inside the root
+    pub fn enter(&mut self, path: &Path) -> bool {
+        if !path.is_dir() || !path.starts_with(&self.root) {
+            return false;
+        }
+        self.dir = path.to_path_buf();
+        true
+    }
+
+    /// Go to the parent folder, returning the folder left; `None` at the root
+    pub fn up(&mut self) -> Option<PathBuf> {
+        if self.dir == self.root {
+            return None;
+        }
+        let parent = self.dir.parent()?.to_path_buf();
+        Some(std::mem::replace(&mut self.dir, parent))
+    }
+
+    pub fn toggle_hidden(&mut self) {
+        self.show_hidden = !self.show_hidden;
+    }
+}
+
+/// The browser for a run showing a single `files` category, which makes the list interactive
+pub fn browser_for(config: &Config, category_filter: &Option<String>) -> Option<Browser> {
+    let (name, category) = match category_filter {
+        Some(name) => config.categories.get_key_value(name)?,
+        None if config.categories.len() == 1 => config.categories.iter().next()?,
+        None => return None,
+    };
+    let source = category.source.as_ref().unwrap_or(&config.source);
+    (*source == SourceMode::Files).then(|| Browser::for_category(name, category))
+}
+
+/// Media files get the picture preview; folders, archives and text the text one
+fn file_item(category: &str, is_dir: bool, name: &str, path: &Path) -> Item {
+    if is_dir {
+        return Item::file(
+            format!("{}/", name),
+            path.to_string_lossy(),
+            category,
+            DisplayMode::Text,
+        )
+        .with_icon(Some("folder".to_string()));
+    }
+    let media = expansion::is_media_file(path);
+    let display = if media {
+        DisplayMode::Picture
+    } else {
+        DisplayMode::Text
+    };
+    Item::file(name, path.to_string_lossy(), category, display).with_icon(mime_icon(path, media))
+}
+
+/// Generic theme icon for a file's type, guessed from its name. When the name is no help,
+/// or disagrees with `is_media_file`, the same content sniff decides, so the icon matches
+/// the preview.
+fn mime_icon(path: &Path, media: bool) -> Option<String> {
+    let (mut content_type, uncertain) = gio::content_type_guess(Some(path), None);
+    let named_media = gio::content_type_get_mime_type(&content_type).is_some_and(|mime| {
+        ["image/", "video/", "audio/"]
+            .iter()
+            .any(|kind| mime.starts_with(kind))
+    });
+    if (uncertain || named_media != media)
+        && let Some(sniffed) =
+            detector::detect_mime(path).and_then(gio::content_type_from_mime_type)
+    {
+        content_type = sniffed;
+    }
+    gio::content_type_get_generic_icon_name(&content_type).map(|name| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn titles(browser: &Browser) -> Vec<String> {
+        browser.list().into_iter().map(|item| item.title).collect()
+    }
+
+    #[test]
+    fn folders_come_first_and_hidden_files_toggle() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("zeta")).unwrap();
+        std::fs::write(dir.path().join("Alpha.txt"), b"a").unwrap();
+        std::fs::write(dir.path().join("photo.png"), b"\x89PNG\r\n\x1a\n").unwrap();
+        std::fs::write(dir.path().join(".secret"), b"s").unwrap();
+
+        let mut browser = Browser::new("files", dir.path(), false);
+        assert_eq!(titles(&browser), vec!["zeta/", "Alpha.txt", "photo.png"]);
+        let items = browser.list();
+        assert_eq!(items[0].icon.as_deref(), Some("folder"));
+        assert_eq!(items[2].display, DisplayMode::Picture);
+        assert_eq!(items[1].source, SourceMode::Files);
+
+        browser.toggle_hidden();
+        assert_eq!(
+            titles(&browser),
+            vec!["zeta/", ".secret", "Alpha.txt", "photo.png"]
+        );
+    }
+
+    #[test]
+    fn icons_follow_the_sniffed_content() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("scan"), b"\x89PNG\r\n\x1a\n").unwrap();
+
+        let items = Browser::new("files", dir.path(), false).list();
+        assert_eq!(items[0].display, DisplayMode::Picture);
+        assert_eq!(items[0].icon.as_deref(), Some("image-x-generic"));
+    }
+
+    #[test]
+    fn gitignore_is_optional() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(".gitignore"), "*.log\n").unwrap();
+        std::fs::write(dir.path().join("build.log"), b"").unwrap();
+        std::fs::write(dir.path().join("main.rs"), b"").unwrap();
+
+        assert_eq!(
+            titles(&Browser::new("files", dir.path(), true)),
+            vec!["main.rs"]
+        );
+        assert_eq!(
+            titles(&Browser::new("files", dir.path(), false)),
+            vec!["build.log", "main.rs"]
+        );
+    }
+
+    #[test]
+    fn navigation_stays_inside_the_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let sub = dir.path().join("sub");
+        std::fs::create_dir(&sub).unwrap();
+        std::fs::write(dir.path().join("file"), b"").unwrap();
+
+        let mut browser = Browser::new("files", dir.path(), false);
+        assert_eq!(browser.up(), None);
+        assert!(!browser.enter(&dir.path().join("file")));
+        assert!(!browser.enter(dir.path().parent().unwrap()));
+        assert!(browser.enter(&sub));
+        assert_eq!(browser.dir(), sub.as_path());
+        assert_eq!(browser.up(), Some(sub));
+        assert_eq!(browser.dir(), dir.path());
+    }
+}
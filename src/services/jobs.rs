@@ -0,0 +1,136 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+const CANCEL_POLL: Duration = Duration::from_millis(50);
+
+/// Handle a background job polls to learn that its result is no longer wanted
+#[derive(Clone, Default)]
+pub struct CancelToken {
+    task: Option<(Arc<AtomicU64>, u64)>,
+}
+
+impl CancelToken {
+    pub fn never() -> Self {
+        Self::default()
+    }
+
+    /// Cancelled as soon as `active` moves on from `task_id`
+    pub fn for_task(active: Arc<AtomicU64>, task_id: u64) -> Self {
+        Self {
+            task: Some((active, task_id)),
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.task
+            .as_ref()
+            .is_some_and(|(active, id)| active.load(Ordering::Relaxed) != *id)
+    }
+
+    /// How often blocking waits should check `is_cancelled`
+    pub fn poll_interval(&self) -> Duration {
+        CANCEL_POLL
+    }
+}
+
+/// Caps how many preview jobs (decodes, ffmpeg, shell previews) run at once
+#[derive(Clone)]
+pub struct JobLimiter {
+    running: Arc<(Mutex<usize>, Condvar)>,
+    max: usize,
+}
+
+/// A running job's place in the limiter, released on drop
+pub struct JobSlot {
+    limiter: JobLimiter,
+}
+
+impl JobLimiter {
+    pub fn new(max: usize) -> Self {
+        Self {
+            running: Arc::new((Mutex::new(0), Condvar::new())),
+            max: max.max(1),
+        }
+    }
+
+    /// Wait for a free slot, giving up if the job is cancelled in the meantime
+    pub fn acquire(&self, cancel: &CancelToken) -> Option<JobSlot> {
+        let (lock, cvar) = &*self.running;
+        let mut running = lock.lock().ok()?;
+        while *running >= self.max {
+            if cancel.is_cancelled() {
+                return None;
+            }
+            running = cvar.wait_timeout(running, cancel.poll_interval()).ok()?.0;
+        }
+        if cancel.is_cancelled() {
+            return None;
+        }
+        *running += 1;
+        Some(JobSlot {
+            limiter: self.clone(),
+        })
+    }
+}
+
+impl Drop for JobSlot {
+    fn drop(&mut self) {
+        let (lock, cvar) = &*self.limiter.running;
+        if let Ok(mut running) = lock.lock() {
+            *running -= 1;
+        }
+        cvar.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_follows_active_task() {
+        let active = Arc::new(AtomicU64::new(3));
+        let token = CancelToken::for_task(active.clone(), 3);
+        assert!(!token.is_cancelled());
+        active.store(4, Ordering::Relaxed);
+        assert!(token.is_cancelled());
+        assert!(!CancelToken::never().is_cancelled());
+    }
+
+    #[test]
+    fn limiter_caps_concurrent_slots() {
+        let limiter = JobLimiter::new(2);
+        let active = Arc::new(AtomicU64::new(1));
+        let token = CancelToken::for_task(active.clone(), 1);
+
+        let a = limiter.acquire(&token).unwrap();
+        let _b = limiter.acquire(&token).unwrap();
+
+        // A third job waits until it is cancelled
+        let waiter = {
+            let limiter = limiter.clone();
+            let token = token.clone();
+            std::thread::spawn(move || limiter.acquire(&token).is_some())
+        };
+        std::thread::sleep(Duration::from_millis(100));
+        active.store(2, Ordering::Relaxed);
+        assert!(!waiter.join().unwrap());
+
+        drop(a);
+        assert!(limiter.acquire(&CancelToken::never()).is_some());
+    }
+
+    #[test]
+    fn released_slot_wakes_waiter() {
+        let limiter = JobLimiter::new(1);
+        let slot = limiter.acquire(&CancelToken::never()).unwrap();
+        let waiter = {
+            let limiter = limiter.clone();
+            std::thread::spawn(move || limiter.acquire(&CancelToken::never()).is_some())
+        };
+        std::thread::sleep(Duration::from_millis(50));
+        drop(slot);
+        assert!(waiter.join().unwrap());
+    }
+}
@@ -1,4 +1,10 @@
+pub mod ansi;
+pub mod applications;
+pub mod clipboard;
+pub mod duplicates;
 pub mod expansion;
+pub mod files;
+pub mod jobs;
 pub mod pipeline;
 pub mod preview;
 pub mod process;
@@ -1,9 +1,9 @@
 use crate::config::{Category, Config};
 use crate::constants::MAX_ITEMS;
-use crate::domain::item::Item;
+use crate::domain::item::{ColoredText, Item};
 use crate::domain::{DisplayMode, SourceMode};
-use crate::services::expansion;
 use crate::services::process::CommandExecutor;
+use crate::services::{ansi, applications, clipboard, expansion, files};
 
 /// Execute the full pipeline: resolve raw items and expand them for display
 pub fn run(
@@ -81,18 +81,23 @@ fn load_category_items(
     items: &mut Vec<Item>,
     executor: &dyn CommandExecutor,
 ) {
+    let ansi = category_config.ansi.unwrap_or(false);
     match effective_source {
         SourceMode::Config => {
             for (key, value) in &category_config.entries {
                 if items.len() >= MAX_ITEMS {
                     return;
                 }
-                items.push(Item::config(
-                    key.clone(),
-                    value.clone(),
-                    category_name,
-                    effective_display.clone(),
-                ));
+                items.push(
+                    Item::config(
+                        key.clone(),
+                        value.clone(),
+                        category_name,
+                        effective_display.clone(),
+                    )
+                    .with_preview_template(category_config.preview_command.clone())
+                    .with_preview_format(category_config.preview_format),
+                );
             }
         }
         SourceMode::Command => {
@@ -110,12 +115,15 @@ fn load_category_items(
                                 format!("{} [{}]", key, idx + 1)
                             };
 
-                            items.push(Item::command(
+                            let item = Item::command(
                                 title,
                                 line.trim().to_string(),
                                 category_name,
                                 effective_display.clone(),
-                            ));
+                            )
+                            .with_preview_template(category_config.preview_command.clone())
+                            .with_preview_format(category_config.preview_format);
+                            items.push(decode_ansi(item, ansi));
                         }
                     }
                 }
@@ -126,11 +134,38 @@ fn load_category_items(
                 if let Ok(dynamic_items) =
                     expansion::process_dynamic_source(list_cmd, preview_template, executor)
                 {
-                    items.extend(dynamic_items);
+                    items.extend(dynamic_items.into_iter().map(|item| {
+                        decode_ansi(item, ansi).with_preview_format(category_config.preview_format)
+                    }));
                 }
             }
         }
+        SourceMode::Clipboard => match clipboard::list(category_name, executor) {
+            Ok(entries) => items.extend(entries),
+            Err(e) => log::error!("{}", e),
+        },
+        SourceMode::Applications => items.extend(applications::list(
+            category_name,
+            &applications::data_dirs(),
+            &applications::Environment::current(),
+        )),
+        SourceMode::Files => {
+            items.extend(files::Browser::for_category(category_name, category_config).list())
+        }
+    }
+}
+
+/// With `ansi = true`, search and output use the plain text and the row keeps the colours
+fn decode_ansi(mut item: Item, enabled: bool) -> Item {
+    if enabled && (item.title.contains('\x1b') || item.value.contains('\x1b')) {
+        let title = ansi::strip(&item.title);
+        let value = ansi::strip(&item.value);
+        item.colored = Some(ColoredText {
+            title: std::mem::replace(&mut item.title, title),
+            value: std::mem::replace(&mut item.value, value),
+        });
     }
+    item
 }
 
 fn execute_command(
@@ -157,6 +192,14 @@ mod tests {
         Category {
             display: None,
             source: None,
+            preview: None,
+            preview_command: None,
+            preview_wrap: None,
+            preview_format: None,
+            ansi: None,
+            listing_depth: None,
+            root: None,
+            gitignore: None,
             entries: entries
                 .into_iter()
                 .map(|(k, v)| (k.into(), v.into()))
@@ -209,6 +252,59 @@ mod tests {
         assert_eq!(items[1].value, "line2");
     }
 
+    #[test]
+    fn ansi_output_keeps_colours_for_display() {
+        let mut cat = make_category(vec![("ls", "ls --color")]);
+        cat.ansi = Some(true);
+        let mut items = Vec::new();
+        let exec = MockExec::new().push_ok(true, b"\x1b[01;34msrc\x1b[0m\n".to_vec());
+        load_category_items(
+            "test",
+            &cat,
+            DisplayMode::Text,
+            SourceMode::Command,
+            &mut items,
+            &exec,
+        );
+        assert_eq!(items[0].value, "src");
+        let colored = items[0].colored.as_ref().unwrap();
+        assert_eq!(colored.value, "\x1b[01;34msrc\x1b[0m");
+        assert_eq!(colored.title, "ls");
+    }
+
+    #[test]
+    fn escapes_left_alone_without_ansi() {
+        let cat = make_category(vec![("ls", "ls --color")]);
+        let mut items = Vec::new();
+        let exec = MockExec::new().push_ok(true, b"\x1b[1mbold\x1b[0m\n".to_vec());
+        load_category_items(
+            "test",
+            &cat,
+            DisplayMode::Text,
+            SourceMode::Command,
+            &mut items,
+            &exec,
+        );
+        assert_eq!(items[0].value, "\x1b[1mbold\x1b[0m");
+        assert!(items[0].colored.is_none());
+    }
+
+    #[test]
+    fn preview_command_attached_to_items() {
+        let mut cat = make_category(vec![("docs", "https://docs.rs")]);
+        cat.preview_command = Some("curl -s {}".to_string());
+        let mut items = Vec::new();
+        load_category_items(
+            "test",
+            &cat,
+            DisplayMode::Text,
+            SourceMode::Config,
+            &mut items,
+            &MockExec::new(),
+        );
+        assert_eq!(items[0].preview_template.as_deref(), Some("curl -s {}"));
+    }
+
     #[test]
     fn command_source_single_line_uses_key() {
         let mut cat = make_category(vec![("mykey", "echo one")]);
@@ -263,6 +359,24 @@ mod tests {
         assert_eq!(items[0].value, "id");
     }
 
+    #[test]
+    fn clipboard_source_lists_history() {
+        let cat = make_category(vec![]);
+        let mut items = Vec::new();
+        let exec = MockExec::new().push_ok(true, b"2\tsecond\n1\tfirst\n".to_vec());
+        load_category_items(
+            "clip",
+            &cat,
+            DisplayMode::Text,
+            SourceMode::Clipboard,
+            &mut items,
+            &exec,
+        );
+        let values: Vec<&str> = items.iter().map(|i| i.value.as_str()).collect();
+        assert_eq!(values, vec!["2", "1"]);
+        assert_eq!(items[0].category, "clip");
+    }
+
     #[test]
     fn config_source_pushes_all_entries() {
         let keys: Vec<String> = (0..15).map(|i| format!("k{}", i)).collect();
@@ -270,6 +384,14 @@ mod tests {
         let cat = Category {
             display: None,
             source: Some(SourceMode::Config),
+            preview: None,
+            preview_command: None,
+            preview_wrap: None,
+            preview_format: None,
+            ansi: None,
+            listing_depth: None,
+            root: None,
+            gitignore: None,
             entries: entries
                 .into_iter()
                 .map(|(k, v)| (k.into(), v.into()))
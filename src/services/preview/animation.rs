@@ -0,0 +1,197 @@
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{AnimationDecoder, Frames, ImageDecoder as _, RgbaImage};
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::AnimationFrame;
+use crate::constants::{
+    DECODE_TIMEOUT_SECS, DEFAULT_FRAME_DELAY_MS, MAX_ANIMATION_BYTES, MAX_DECODE_PIXEL_BYTES,
+    MIN_FRAME_DELAY_MS,
+};
+use crate::services::jobs::CancelToken;
+
+/// Decode every frame of an animated GIF, APNG or WebP, scaled down to fit the box.
+/// Returns `None` for still images so they take the normal, disk-cached path.
+pub fn decode_animation(
+    path: &Path,
+    max_width: i32,
+    max_height: i32,
+    cancel: &CancelToken,
+) -> Option<(Vec<AnimationFrame>, i32, i32)> {
+    let mime = super::detector::detect_mime(path)?;
+    let reader = BufReader::new(std::fs::File::open(path).ok()?);
+
+    let (canvas, frames): ((u32, u32), Frames) = match mime {
+        "image/gif" => {
+            let decoder = GifDecoder::new(reader).ok()?;
+            (decoder.dimensions(), decoder.into_frames())
+        }
+        "image/png" => {
+            let decoder = PngDecoder::new(reader).ok()?;
+            if !decoder.is_apng().ok()? {
+                return None;
+            }
+            let dimensions = decoder.dimensions();
+            (dimensions, decoder.apng().ok()?.into_frames())
+        }
+        "image/webp" => {
+            let decoder = WebPDecoder::new(reader).ok()?;
+            if !decoder.has_animation() {
+                return None;
+            }
+            (decoder.dimensions(), decoder.into_frames())
+        }
+        _ => return None,
+    };
+
+    if canvas.0 as u64 * canvas.1 as u64 * 4 > MAX_DECODE_PIXEL_BYTES {
+        return None;
+    }
+    let (width, height) = fit_size(canvas, max_width, max_height);
+    collect_frames(frames, width, height, cancel)
+}
+
+fn collect_frames(
+    frames: Frames,
+    width: u32,
+    height: u32,
+    cancel: &CancelToken,
+) -> Option<(Vec<AnimationFrame>, i32, i32)> {
+    let frame_bytes = width as u64 * height as u64 * 4;
+    let mut decoded = Vec::new();
+    let started = Instant::now();
+
+    for frame in frames {
+        if cancel.is_cancelled() {
+            return None;
+        }
+        if started.elapsed() > Duration::from_secs(DECODE_TIMEOUT_SECS) {
+            log::debug!(
+                "Animation truncated to {} frames by the decode timeout",
+                decoded.len()
+            );
+            break;
+        }
+        // A damaged frame ends the animation rather than discarding it
+        let Ok(frame) = frame else {
+            break;
+        };
+        if (decoded.len() as u64 + 1) * frame_bytes > MAX_ANIMATION_BYTES {
+            log::debug!(
+                "Animation truncated to {} frames to stay under the memory cap",
+                decoded.len()
+            );
+            break;
+        }
+        let delay = frame_delay(frame.delay().numer_denom_ms());
+        let buffer = scale(frame.into_buffer(), width, height);
+        decoded.push(AnimationFrame {
+            pixels: Arc::from(buffer.into_raw()),
+            delay,
+        });
+    }
+
+    if decoded.len() < 2 {
+        return None;
+    }
+    Some((decoded, width as i32, height as i32))
+}
+
+/// Browsers treat near-zero delays as "as fast as possible"; play those at 10 fps instead
+fn frame_delay((numer, denom): (u32, u32)) -> Duration {
+    let ms = numer.checked_div(denom).unwrap_or(0);
+    if ms < MIN_FRAME_DELAY_MS {
+        Duration::from_millis(DEFAULT_FRAME_DELAY_MS)
+    } else {
+        Duration::from_millis(ms as u64)
+    }
+}
+
+/// Shrink to fit the box; never enlarge, the picture widget scales up on its own
+fn fit_size((width, height): (u32, u32), max_width: i32, max_height: i32) -> (u32, u32) {
+    let ratio = (max_width as f64 / width as f64)
+        .min(max_height as f64 / height as f64)
+        .min(1.0);
+    (
+        ((width as f64 * ratio).round() as u32).max(1),
+        ((height as f64 * ratio).round() as u32).max(1),
+    )
+}
+
+fn scale(buffer: RgbaImage, width: u32, height: u32) -> RgbaImage {
+    if buffer.dimensions() == (width, height) {
+        buffer
+    } else {
+        image::imageops::resize(
+            &buffer,
+            width,
+            height,
+            image::imageops::FilterType::Triangle,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::gif::{GifEncoder, Repeat};
+    use image::{Delay, Frame, Rgba};
+    use tempfile::tempdir;
+
+    fn write_gif(path: &Path, frames: usize, delay_ms: u32) {
+        let file = std::fs::File::create(path).unwrap();
+        let mut encoder = GifEncoder::new(file);
+        encoder.set_repeat(Repeat::Infinite).unwrap();
+        for i in 0..frames {
+            let buffer = RgbaImage::from_pixel(20, 10, Rgba([i as u8 * 40, 0, 0, 255]));
+            let delay = Delay::from_numer_denom_ms(delay_ms, 1);
+            encoder
+                .encode_frame(Frame::from_parts(buffer, 0, 0, delay))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn decodes_all_gif_frames_with_timing() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("a.gif");
+        write_gif(&path, 3, 70);
+
+        let (frames, w, h) = decode_animation(&path, 800, 600, &CancelToken::never()).unwrap();
+        assert_eq!((w, h), (20, 10));
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].delay, Duration::from_millis(70));
+        assert_eq!(frames[0].pixels.len(), 20 * 10 * 4);
+    }
+
+    #[test]
+    fn still_gif_is_not_an_animation() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("still.gif");
+        write_gif(&path, 1, 0);
+        assert!(decode_animation(&path, 800, 600, &CancelToken::never()).is_none());
+    }
+
+    #[test]
+    fn frames_are_scaled_to_the_box() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("a.gif");
+        write_gif(&path, 2, 50);
+        let (frames, w, h) = decode_animation(&path, 10, 10, &CancelToken::never()).unwrap();
+        assert_eq!((w, h), (10, 5));
+        assert_eq!(frames[1].pixels.len(), 10 * 5 * 4);
+    }
+
+    #[test]
+    fn zero_delay_plays_at_default_rate() {
+        assert_eq!(
+            frame_delay((0, 1)),
+            Duration::from_millis(DEFAULT_FRAME_DELAY_MS)
+        );
+        assert_eq!(frame_delay((40, 1)), Duration::from_millis(40));
+    }
+}
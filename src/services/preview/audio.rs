@@ -0,0 +1,270 @@
+use crate::cache::CacheAdapter;
+use crate::constants::{FFMPEG_TIMEOUT_SECS, FFPROBE_TIMEOUT_SECS, WAVEFORM_COLOR};
+use crate::services::jobs::CancelToken;
+use crate::services::process::CommandExecutor;
+use std::path::Path;
+
+use super::decoder::ImageDecoder;
+use super::video::format_duration;
+use super::{PreviewPayload, PreviewSize};
+
+type Frame = (Vec<u8>, i32, i32);
+
+pub fn is_audio(path: &Path) -> bool {
+    match path.extension().and_then(|s| s.to_str()) {
+        Some(ext) => {
+            let ext_lower = ext.to_lowercase();
+            crate::constants::AUDIO_EXTENSIONS.contains(&ext_lower.as_str())
+        }
+        None => super::detector::detect_mime(path).is_some_and(|mime| mime.starts_with("audio/")),
+    }
+}
+
+/// Tags and stream facts ffprobe reports for a track
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AudioInfo {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub date: Option<String>,
+    pub track: Option<String>,
+    pub genre: Option<String>,
+    pub duration: Option<f64>,
+    pub codec: Option<String>,
+}
+
+impl AudioInfo {
+    /// Label/value pairs for the preview details
+    pub fn details(&self) -> Vec<(String, String)> {
+        let tags = [
+            ("Title", &self.title),
+            ("Artist", &self.artist),
+            ("Album", &self.album),
+            ("Date", &self.date),
+            ("Track", &self.track),
+            ("Genre", &self.genre),
+        ];
+        let mut details: Vec<(String, String)> = tags
+            .into_iter()
+            .filter_map(|(label, value)| Some((label.to_string(), value.clone()?)))
+            .collect();
+        if let Some(duration) = self.duration {
+            details.push(("Duration".to_string(), format_duration(duration)));
+        }
+        if let Some(codec) = &self.codec {
+            details.push(("Codec".to_string(), codec.clone()));
+        }
+        details
+    }
+}
+
+pub fn probe(
+    audio_path: &Path,
+    executor: &dyn CommandExecutor,
+    cancel: &CancelToken,
+) -> Option<AudioInfo> {
+    let audio_str = audio_path.to_string_lossy();
+    // Ogg and Opus keep their comments on the stream, ID3 and FLAC on the container
+    let args = [
+        "-v",
+        "error",
+        "-select_streams",
+        "a:0",
+        "-show_entries",
+        "format=duration:format_tags:stream=codec_name:stream_tags",
+        "-of",
+        "default=noprint_wrappers=1",
+        &audio_str,
+    ];
+    let output = executor
+        .execute_cancellable("ffprobe", &args, FFPROBE_TIMEOUT_SECS, cancel)
+        .ok()?;
+    if !output.success {
+        return None;
+    }
+    Some(parse_probe_output(&String::from_utf8_lossy(&output.stdout)))
+}
+
+fn parse_probe_output(output: &str) -> AudioInfo {
+    let mut info = AudioInfo::default();
+    for line in output.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        if value.is_empty() || value == "N/A" {
+            continue;
+        }
+        // Vorbis comments are usually upper case, ID3 frames come through lower case
+        let key = key.trim().to_lowercase();
+        let slot = match key.strip_prefix("tag:").unwrap_or(&key) {
+            "title" => &mut info.title,
+            "artist" => &mut info.artist,
+            "album" => &mut info.album,
+            "date" | "year" => &mut info.date,
+            "track" | "tracknumber" => &mut info.track,
+            "genre" => &mut info.genre,
+            "codec_name" => &mut info.codec,
+            "duration" => {
+                info.duration = value.parse().ok().filter(|d: &f64| *d > 0.0);
+                continue;
+            }
+            _ => continue,
+        };
+        if slot.is_none() {
+            *slot = Some(value.to_string());
+        }
+    }
+    info
+}
+
+/// Embedded cover art above a waveform of the track, either alone if the other is missing
+pub fn generate_preview(
+    audio_path: &Path,
+    cache_path: &Path,
+    size: PreviewSize,
+    cache: &dyn CacheAdapter,
+    decoder: &dyn ImageDecoder,
+    executor: &dyn CommandExecutor,
+    cancel: &CancelToken,
+) -> PreviewPayload {
+    let work_dir = match tempfile::tempdir() {
+        Ok(dir) => dir,
+        Err(e) => return PreviewPayload::Error(format!("Tempdir error: {}", e)),
+    };
+    let wave_h = size.height / 4;
+    let cover_box = (size.width, size.height - wave_h);
+    let scale = format!(
+        "scale={}:{}:force_original_aspect_ratio=decrease",
+        cover_box.0, cover_box.1
+    );
+    let cover = render(
+        audio_path,
+        &work_dir.path().join("cover.png"),
+        &["-an", "-frames:v", "1", "-vf", &scale],
+        cover_box,
+        decoder,
+        executor,
+        cancel,
+    );
+    let waves = format!(
+        "showwavespic=s={}x{}:colors={}",
+        size.width, wave_h, WAVEFORM_COLOR
+    );
+    let waveform = render(
+        audio_path,
+        &work_dir.path().join("waveform.png"),
+        &["-filter_complex", &waves, "-frames:v", "1"],
+        (size.width, wave_h),
+        decoder,
+        executor,
+        cancel,
+    );
+
+    match stack(cover, waveform) {
+        Some((raw_data, w, h)) => {
+            let _ = cache.save_raw_cache(cache_path, audio_path, &raw_data, w, h);
+            PreviewPayload::Image {
+                bytes: std::sync::Arc::new(raw_data),
+                width: w,
+                height: h,
+            }
+        }
+        None if cancel.is_cancelled() => super::cancelled(),
+        None => PreviewPayload::Error("FFmpeg execution failed".to_string()),
+    }
+}
+
+/// Run ffmpeg on the track with `filters` and decode the single image it writes
+fn render(
+    audio_path: &Path,
+    out: &Path,
+    filters: &[&str],
+    (max_width, max_height): (i32, i32),
+    decoder: &dyn ImageDecoder,
+    executor: &dyn CommandExecutor,
+    cancel: &CancelToken,
+) -> Option<Frame> {
+    if cancel.is_cancelled() {
+        return None;
+    }
+    let audio_str = audio_path.to_string_lossy();
+    let out_str = out.to_string_lossy();
+    let mut args = vec!["-y", "-v", "error", "-i", &audio_str];
+    args.extend_from_slice(filters);
+    args.push(&out_str);
+
+    let output = executor
+        .execute_cancellable("ffmpeg", &args, FFMPEG_TIMEOUT_SECS, cancel)
+        .ok()?;
+    if !output.success {
+        return None;
+    }
+    let image = decoder.load_cancellable(out, max_width, max_height, cancel);
+    let _ = std::fs::remove_file(out);
+    image
+}
+
+/// Place `top` above `bottom`, each centred across the wider of the two, on transparency
+fn stack(top: Option<Frame>, bottom: Option<Frame>) -> Option<Frame> {
+    let parts: Vec<Frame> = top.into_iter().chain(bottom).collect();
+    let width = parts.iter().map(|(_, w, _)| *w as usize).max()?;
+    let height: usize = parts.iter().map(|(_, _, h)| *h as usize).sum();
+    let mut canvas = vec![0u8; width * height * 4];
+
+    let mut y0 = 0;
+    for (pixels, w, h) in &parts {
+        let (w, h) = (*w as usize, *h as usize);
+        let stride = w * 4;
+        let x0 = (width - w) / 2;
+        for row in 0..h {
+            let dst = ((y0 + row) * width + x0) * 4;
+            canvas[dst..dst + stride].copy_from_slice(&pixels[row * stride..(row + 1) * stride]);
+        }
+        y0 += h;
+    }
+    Some((canvas, width as i32, height as i32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tags_in_any_case() {
+        let out = "codec_name=flac\nTAG:TITLE=Roygbiv\nTAG:ARTIST=Boards of Canada\n\
+                   TAG:TRACKNUMBER=11\nduration=151.2\nTAG:album=Music Has the Right\n";
+        let info = parse_probe_output(out);
+        assert_eq!(
+            info.details(),
+            vec![
+                ("Title".to_string(), "Roygbiv".to_string()),
+                ("Artist".to_string(), "Boards of Canada".to_string()),
+                ("Album".to_string(), "Music Has the Right".to_string()),
+                ("Track".to_string(), "11".to_string()),
+                ("Duration".to_string(), "2:31".to_string()),
+                ("Codec".to_string(), "flac".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn stream_tags_win_over_later_duplicates() {
+        let info = parse_probe_output("TAG:title=Stream\nTAG:title=Format\nduration=N/A\n");
+        assert_eq!(info.title.as_deref(), Some("Stream"));
+        assert_eq!(info.duration, None);
+    }
+
+    #[test]
+    fn stacks_cover_over_waveform() {
+        let cover = (vec![255u8; 2 * 2 * 4], 2, 2);
+        let wave = (vec![128u8; 16], 4, 1);
+        let (canvas, w, h) = stack(Some(cover), Some(wave)).unwrap();
+        assert_eq!((w, h), (4, 3));
+        let pixel = |x: usize, y: usize| &canvas[(y * 4 + x) * 4..(y * 4 + x) * 4 + 4];
+        assert_eq!(pixel(0, 0), &[0, 0, 0, 0]);
+        assert_eq!(pixel(1, 0), &[255, 255, 255, 255]);
+        assert_eq!(pixel(3, 2), &[128, 128, 128, 128]);
+        assert_eq!(stack(None, None), None);
+    }
+}
@@ -1,6 +1,17 @@
-use gdk_pixbuf::Pixbuf;
+use gdk_pixbuf::prelude::*;
+use gdk_pixbuf::{Pixbuf, PixbufLoader};
 use image::ImageReader;
+use std::io::{Cursor, Read};
 use std::path::Path;
+use std::sync::OnceLock;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use super::{AnimationFrame, detector, metadata, raw};
+use crate::constants::{DECODE_TIMEOUT_SECS, MAX_DECODE_PIXEL_BYTES, MAX_STILL_DECODE_BYTES};
+use crate::services::jobs::CancelToken;
+
+const DECODE_CHUNK: usize = 64 * 1024;
 
 pub trait ImageDecoder: Send + Sync {
     fn load_from_path(
@@ -9,6 +20,42 @@ pub trait ImageDecoder: Send + Sync {
         max_width: i32,
         max_height: i32,
     ) -> Option<(Vec<u8>, i32, i32)>;
+
+    /// Decoders that can stop part-way override this; the default only checks up front
+    fn load_cancellable(
+        &self,
+        path: &Path,
+        max_width: i32,
+        max_height: i32,
+        cancel: &CancelToken,
+    ) -> Option<(Vec<u8>, i32, i32)> {
+        if cancel.is_cancelled() {
+            return None;
+        }
+        self.load_from_path(path, max_width, max_height)
+    }
+
+    /// Upright width and height from the file header, without decoding pixels
+    fn dimensions(&self, _path: &Path) -> Option<(i32, i32)> {
+        None
+    }
+
+    /// Why an image cannot be decoded: no installed loader handles its format,
+    /// or its header declares more pixels than a preview may decode
+    fn decode_error(&self, _path: &Path) -> Option<String> {
+        None
+    }
+
+    /// All frames of an animated image, or `None` for still images
+    fn load_animation(
+        &self,
+        _path: &Path,
+        _max_width: i32,
+        _max_height: i32,
+        _cancel: &CancelToken,
+    ) -> Option<(Vec<AnimationFrame>, i32, i32)> {
+        None
+    }
 }
 
 #[derive(Clone)]
@@ -21,15 +68,100 @@ impl ImageDecoder for GdkPixbufDecoder {
         max_width: i32,
         max_height: i32,
     ) -> Option<(Vec<u8>, i32, i32)> {
-        if path
-            .extension()
-            .and_then(|s| s.to_str())
-            .is_some_and(|ext| ext.eq_ignore_ascii_case(crate::constants::GIF_EXTENSION))
-        {
-            load_gif_first_frame(path, max_width, max_height)
+        self.load_cancellable(path, max_width, max_height, &CancelToken::never())
+    }
+
+    fn load_cancellable(
+        &self,
+        path: &Path,
+        max_width: i32,
+        max_height: i32,
+        cancel: &CancelToken,
+    ) -> Option<(Vec<u8>, i32, i32)> {
+        if cancel.is_cancelled() {
+            return None;
+        }
+        let path = path.to_path_buf();
+        let worker_cancel = cancel.clone();
+        let decode = move || {
+            // Judged by content, so a GIF saved as .jpg still takes the `image` path
+            if detector::detect_mime(&path) == Some("image/gif") {
+                load_gif_first_frame(&path, max_width, max_height)
+            } else {
+                load_image_data_raw(&path, max_width, max_height, &worker_cancel)
+            }
+        };
+        with_deadline(Duration::from_secs(DECODE_TIMEOUT_SECS), cancel, decode)
+    }
+
+    fn dimensions(&self, path: &Path) -> Option<(i32, i32)> {
+        let (width, height) = if raw::is_raw(path) {
+            raw::jpeg_size(&raw::embedded_preview(path)?)?
         } else {
-            load_image_data_raw(path, max_width, max_height)
+            let (_, width, height) = Pixbuf::file_info(path)?;
+            (width, height)
+        };
+        let orientation = metadata::decode_orientation(path);
+        if metadata::swaps_axes(orientation) {
+            Some((height, width))
+        } else {
+            Some((width, height))
+        }
+    }
+
+    fn decode_error(&self, path: &Path) -> Option<String> {
+        if raw::is_raw(path) {
+            return Some("No embedded preview in this RAW file".to_string());
         }
+        let mime = detector::detect_mime(path)?;
+        if !mime.starts_with("image/") || mime == "image/gif" {
+            return None;
+        }
+        if !pixbuf_mime_types().iter().any(|m| m == mime) {
+            return Some(missing_loader_message(mime));
+        }
+        let (width, height) = oversized(path)?;
+        Some(format!("Image too large to preview ({}×{})", width, height))
+    }
+
+    fn load_animation(
+        &self,
+        path: &Path,
+        max_width: i32,
+        max_height: i32,
+        cancel: &CancelToken,
+    ) -> Option<(Vec<AnimationFrame>, i32, i32)> {
+        super::animation::decode_animation(path, max_width, max_height, cancel)
+    }
+}
+
+/// MIME types of the gdk-pixbuf loaders installed on this system
+fn pixbuf_mime_types() -> &'static [String] {
+    static TYPES: OnceLock<Vec<String>> = OnceLock::new();
+    TYPES.get_or_init(|| {
+        Pixbuf::formats()
+            .iter()
+            .flat_map(|format| format.mime_types())
+            .map(|mime| mime.to_string())
+            .collect()
+    })
+}
+
+/// Name the package that provides a loader, for formats that ship one separately
+fn missing_loader_message(mime: &str) -> String {
+    let provider = match mime {
+        "image/svg+xml" => Some("librsvg"),
+        "image/avif" => Some("libavif or libheif"),
+        "image/heic" => Some("libheif"),
+        "image/jxl" => Some("libjxl"),
+        _ => None,
+    };
+    match provider {
+        Some(provider) => format!(
+            "No image loader for {}; install the gdk-pixbuf loader from {}",
+            mime, provider
+        ),
+        None => format!("No image loader for {}", mime),
     }
 }
 
@@ -42,7 +174,7 @@ fn load_gif_first_frame(
     let (orig_w, orig_h) = reader.into_dimensions().ok()?;
     let pixel_bytes = orig_w as u64 * orig_h as u64 * 4;
 
-    if pixel_bytes > crate::constants::MAX_DECODE_PIXEL_BYTES {
+    if pixel_bytes > MAX_DECODE_PIXEL_BYTES {
         return None;
     }
 
@@ -79,53 +211,278 @@ fn load_gif_first_frame(
     Some((rgba.into_raw(), width as i32, height as i32))
 }
 
+/// Run a decode on its own thread and stop waiting once `cancel` fires or `timeout` passes.
+/// SVG, HEIF, AVIF and JPEG XL loaders do all their work when closed, out of reach of the
+/// chunk loop's checks, so a stuck one is left to finish unseen.
+fn with_deadline<T: Send + 'static>(
+    timeout: Duration,
+    cancel: &CancelToken,
+    decode: impl FnOnce() -> Option<T> + Send + 'static,
+) -> Option<T> {
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let _ = tx.send(decode());
+    });
+
+    let deadline = Instant::now() + timeout;
+    loop {
+        match rx.recv_timeout(cancel.poll_interval()) {
+            Ok(result) => return result,
+            Err(RecvTimeoutError::Disconnected) => return None,
+            Err(RecvTimeoutError::Timeout) if cancel.is_cancelled() => return None,
+            Err(RecvTimeoutError::Timeout) if Instant::now() >= deadline => {
+                log::debug!("Decode timed out after {} seconds", timeout.as_secs());
+                return None;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+    }
+}
+
+/// Feed the file to a loader in chunks so a cancelled decode stops early. Camera RAW files
+/// are read through the JPEG preview embedded in them.
+/// Pixels come back turned upright according to the file's EXIF orientation, where the
+/// loader doesn't already do that.
 fn load_image_data_raw(
     path: &Path,
     max_width: i32,
     max_height: i32,
+    cancel: &CancelToken,
 ) -> Option<(Vec<u8>, i32, i32)> {
-    let pixbuf = Pixbuf::from_file_at_scale(path, max_width, max_height, true).ok()?;
+    let orientation = metadata::decode_orientation(path);
+    // The box applies to the upright image, which is sideways in the file for 5–8
+    let (max_width, max_height) = if metadata::swaps_axes(orientation) {
+        (max_height, max_width)
+    } else {
+        (max_width, max_height)
+    };
+    let mut file: Box<dyn Read> = if raw::is_raw(path) {
+        Box::new(Cursor::new(raw::embedded_preview(path)?))
+    } else if let Some((width, height)) = oversized(path) {
+        log::debug!(
+            "Skipping {}: {}×{} exceeds the decode limit",
+            path.display(),
+            width,
+            height
+        );
+        return None;
+    } else {
+        Box::new(std::fs::File::open(path).ok()?)
+    };
+    let loader = PixbufLoader::new();
+    loader.connect_size_prepared(move |loader, width, height| {
+        let (w, h) = fit_within(width, height, max_width, max_height);
+        loader.set_size(w, h);
+    });
+
+    let mut buf = vec![0u8; DECODE_CHUNK];
+    let started = Instant::now();
+    loop {
+        if cancel.is_cancelled() {
+            let _ = loader.close();
+            return None;
+        }
+        // `with_deadline` has given up on it by now, so stop feeding it
+        if started.elapsed() > Duration::from_secs(DECODE_TIMEOUT_SECS) {
+            log::debug!("Decoding {} timed out", path.display());
+            let _ = loader.close();
+            return None;
+        }
+        let n = match file.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(_) => {
+                let _ = loader.close();
+                return None;
+            }
+        };
+        if loader.write(&buf[..n]).is_err() {
+            let _ = loader.close();
+            return None;
+        }
+    }
+    loader.close().ok()?;
+    let pixbuf = loader.pixbuf()?;
+    let (bytes, width, height) = pixbuf_to_rgba(&pixbuf);
+    if orientation == 1 {
+        return Some((bytes, width, height));
+    }
+    Some(metadata::orient_rgba(&bytes, width, height, orientation))
+}
+
+/// Header dimensions of a file whose decode would exceed `MAX_STILL_DECODE_BYTES`
+fn oversized(path: &Path) -> Option<(i32, i32)> {
+    let (format, width, height) = Pixbuf::file_info(path)?;
+    let name = format.name().unwrap_or_default();
+    let bytes = decode_bytes(&name, format.is_scalable(), width, height);
+    (bytes > MAX_STILL_DECODE_BYTES).then_some((width, height))
+}
+
+/// Memory a loader needs for an image before it is scaled into the preview box.
+/// Vector formats render straight at the box size and JPEG decodes down to an
+/// eighth per side; everything else is decoded whole first.
+fn decode_bytes(format: &str, scalable: bool, width: i32, height: i32) -> u64 {
+    let full = width.max(0) as u64 * height.max(0) as u64 * 4;
+    match format {
+        _ if scalable => 0,
+        "jpeg" => full / 64,
+        _ => full,
+    }
+}
+
+/// Scale to fit the box keeping the aspect ratio, as `Pixbuf::from_file_at_scale` does
+fn fit_within(width: i32, height: i32, max_width: i32, max_height: i32) -> (i32, i32) {
+    if width <= 0 || height <= 0 {
+        return (width, height);
+    }
+    if height as f64 * max_width as f64 > width as f64 * max_height as f64 {
+        (
+            (0.5 + width as f64 * max_height as f64 / height as f64) as i32,
+            max_height,
+        )
+    } else {
+        (
+            max_width,
+            (0.5 + height as f64 * max_width as f64 / width as f64) as i32,
+        )
+    }
+}
+
+/// Convert a pixbuf of any channel layout into tightly packed RGBA, a row at a time
+pub fn pixbuf_to_rgba(pixbuf: &Pixbuf) -> (Vec<u8>, i32, i32) {
     let width = pixbuf.width();
     let height = pixbuf.height();
+    let n_channels = pixbuf.n_channels() as usize;
     let has_alpha = pixbuf.has_alpha();
     let rowstride = pixbuf.rowstride() as usize;
+    // The last row may stop short of the rowstride, so only the pixels are sliced
+    let row_len = width as usize * n_channels;
 
     let pixel_bytes = pixbuf.read_pixel_bytes();
     let pixels: &[u8] = pixel_bytes.as_ref();
 
-    let mut rgba_data = Vec::with_capacity((width * height * 4) as usize);
-    let n_channels = pixbuf.n_channels() as usize;
-
+    let mut rgba = Vec::with_capacity(width as usize * height as usize * 4);
     for y in 0..height as usize {
-        let row_start = y * rowstride;
-        for x in 0..width as usize {
-            let pixel_start = row_start + x * n_channels;
-            if pixel_start + n_channels > pixels.len() {
-                break;
-            }
+        let start = y * rowstride;
+        let Some(row) = pixels.get(start..start + row_len) else {
+            break;
+        };
+        push_rgba_row(&mut rgba, row, n_channels, has_alpha);
+    }
+    (rgba, width, height)
+}
 
-            match n_channels {
-                1 => {
-                    let v = pixels[pixel_start];
-                    rgba_data.extend_from_slice(&[v, v, v, 255]);
-                }
-                2 => {
-                    let v = pixels[pixel_start];
-                    let a = pixels[pixel_start + 1];
-                    rgba_data.extend_from_slice(&[v, v, v, a]);
-                }
-                _ => {
-                    rgba_data.extend_from_slice(&pixels[pixel_start..pixel_start + 3]);
-                    let a = if has_alpha && n_channels >= 4 {
-                        pixels[pixel_start + 3]
-                    } else {
-                        255
-                    };
-                    rgba_data.push(a);
-                }
-            }
-        }
+/// Append one row of 1 to 4 channel pixels as RGBA; RGBA rows are copied whole
+fn push_rgba_row(out: &mut Vec<u8>, row: &[u8], n_channels: usize, has_alpha: bool) {
+    match n_channels {
+        4 if has_alpha => out.extend_from_slice(row),
+        1 => row
+            .iter()
+            .for_each(|&v| out.extend_from_slice(&[v, v, v, 255])),
+        2 => row
+            .chunks_exact(2)
+            .for_each(|p| out.extend_from_slice(&[p[0], p[0], p[0], p[1]])),
+        _ => row
+            .chunks_exact(n_channels)
+            .for_each(|p| out.extend_from_slice(&[p[0], p[1], p[2], 255])),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slow_decodes_are_abandoned() {
+        let timeout = Duration::from_millis(100);
+        let never = CancelToken::never();
+        assert_eq!(with_deadline(timeout, &never, || Some(7)), Some(7));
+
+        let started = Instant::now();
+        let slow = || {
+            std::thread::sleep(Duration::from_secs(2));
+            Some(7)
+        };
+        assert_eq!(with_deadline(timeout, &never, slow), None);
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        let cancelled = CancelToken::for_task(std::sync::Arc::new(1.into()), 0);
+        assert_eq!(
+            with_deadline(Duration::from_secs(5), &cancelled, slow),
+            None
+        );
+    }
+
+    #[test]
+    fn fit_within_keeps_aspect_ratio() {
+        assert_eq!(fit_within(4000, 2000, 800, 600), (800, 400));
+        assert_eq!(fit_within(1000, 2000, 800, 600), (300, 600));
     }
 
-    Some((rgba_data, width, height))
+    #[test]
+    fn fit_within_scales_small_images_up() {
+        assert_eq!(fit_within(100, 50, 800, 600), (800, 400));
+    }
+
+    #[test]
+    fn decode_budget_by_format() {
+        assert_eq!(decode_bytes("png", false, 30_000, 30_000), 3_600_000_000);
+        assert_eq!(decode_bytes("jpeg", false, 8000, 8000), 4_000_000);
+        assert_eq!(decode_bytes("svg", true, 30_000, 30_000), 0);
+    }
+
+    /// A PNG that is only a signature and an IHDR chunk declaring `width`×`height`
+    fn png_header(width: u32, height: u32) -> Vec<u8> {
+        let mut ihdr = b"IHDR".to_vec();
+        ihdr.extend_from_slice(&width.to_be_bytes());
+        ihdr.extend_from_slice(&height.to_be_bytes());
+        ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png.extend_from_slice(&13u32.to_be_bytes());
+        png.extend_from_slice(&ihdr);
+        png.extend_from_slice(&crc32fast::hash(&ihdr).to_be_bytes());
+        png
+    }
+
+    #[test]
+    fn large_photos_pass_the_header_check() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("photo.png");
+        std::fs::write(&path, png_header(5120, 2880)).unwrap();
+        assert_eq!(oversized(&path), None);
+        std::fs::write(&path, png_header(30_000, 30_000)).unwrap();
+        assert_eq!(oversized(&path), Some((30_000, 30_000)));
+    }
+
+    #[test]
+    fn rows_widen_to_rgba() {
+        let mut out = Vec::new();
+        push_rgba_row(&mut out, &[1, 2, 3, 4, 5, 6], 3, false);
+        push_rgba_row(&mut out, &[7, 8], 2, true);
+        push_rgba_row(&mut out, &[9, 10, 11, 12], 4, true);
+        assert_eq!(out, [1, 2, 3, 255, 4, 5, 6, 255, 7, 7, 7, 8, 9, 10, 11, 12]);
+    }
+
+    #[test]
+    fn missing_loaders_name_their_package() {
+        assert_eq!(
+            missing_loader_message("image/jxl"),
+            "No image loader for image/jxl; install the gdk-pixbuf loader from libjxl"
+        );
+        assert_eq!(
+            missing_loader_message("image/x-icon"),
+            "No image loader for image/x-icon"
+        );
+    }
+
+    #[test]
+    fn cancelled_decode_returns_none() {
+        let active = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(2));
+        let cancel = CancelToken::for_task(active, 1);
+        assert!(
+            GdkPixbufDecoder
+                .load_cancellable(Path::new("/nonexistent.png"), 800, 600, &cancel)
+                .is_none()
+        );
+    }
 }
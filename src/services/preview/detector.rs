@@ -1,6 +1,122 @@
+use std::io::Read;
+use std::path::Path;
+
+use crate::constants::MIME_SNIFF_BYTES;
+
+/// Control bytes other than common whitespace and escape sequences mean binary data
 pub fn is_binary(data: &[u8]) -> bool {
     data.iter()
-        .any(|&b| b == 0 || (b < 32 && b != b'\n' && b != b'\t'))
+        .any(|&b| b == 0 || (b < 32 && !matches!(b, b'\n' | b'\t' | b'\r' | 0x0c | 0x1b)))
+}
+
+/// MIME type of a file, judged by its leading bytes rather than its name
+pub fn detect_mime(path: &Path) -> Option<&'static str> {
+    let file = std::fs::File::open(path).ok()?;
+    let mut head = Vec::with_capacity(MIME_SNIFF_BYTES);
+    file.take(MIME_SNIFF_BYTES as u64)
+        .read_to_end(&mut head)
+        .ok()?;
+    Some(sniff_mime(&head))
+}
+
+/// Identify data from its magic bytes, falling back to plain text or octet-stream
+pub fn sniff_mime(head: &[u8]) -> &'static str {
+    const MAGIC: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"FUJIFILMCCD-RAW", "image/x-fuji-raf"),
+        (b"IIRO", "image/x-olympus-orf"),
+        (b"IIRS", "image/x-olympus-orf"),
+        (b"MMOR", "image/x-olympus-orf"),
+        (b"IIU\x00", "image/x-panasonic-rw2"),
+        (b"II*\x00", "image/tiff"),
+        (b"MM\x00*", "image/tiff"),
+        (b"\x00\x00\x01\x00", "image/x-icon"),
+        (b"\xff\x0a", "image/jxl"),
+        (b"\x00\x00\x00\x0cJXL ", "image/jxl"),
+        (b"%PDF-", "application/pdf"),
+        (b"%!PS", "application/postscript"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"BZh", "application/x-bzip2"),
+        (b"\xfd7zXZ\x00", "application/x-xz"),
+        (b"\x28\xb5\x2f\xfd", "application/zstd"),
+        (b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+        (b"Rar!\x1a\x07", "application/vnd.rar"),
+        (b"\x7fELF", "application/x-executable"),
+        (b"SQLite format 3\x00", "application/vnd.sqlite3"),
+        (b"OggS", "audio/ogg"),
+        (b"fLaC", "audio/flac"),
+        (b"ID3", "audio/mpeg"),
+        (b"\xff\xfb", "audio/mpeg"),
+        (b"\xff\xf3", "audio/mpeg"),
+        (b"FLV", "video/x-flv"),
+    ];
+
+    if let Some(mime) = sniff_container(head) {
+        return mime;
+    }
+    // Canon's CR2 is a TIFF that names itself after the header
+    if head.starts_with(b"II*\x00") && head.get(8..10) == Some(b"CR") {
+        return "image/x-canon-cr2";
+    }
+    if let Some((_, mime)) = MAGIC.iter().find(|(magic, _)| head.starts_with(magic)) {
+        return mime;
+    }
+    if head.get(257..262) == Some(b"ustar") {
+        return "application/x-tar";
+    }
+    if is_binary(head) {
+        // Too short a signature to trust on text that happens to start with "BM"
+        return if head.starts_with(b"BM") {
+            "image/bmp"
+        } else {
+            "application/octet-stream"
+        };
+    }
+    if is_svg(head) {
+        "image/svg+xml"
+    } else {
+        "text/plain"
+    }
+}
+
+/// RIFF, ISO base media and Matroska files share a header, the format is named inside it
+fn sniff_container(head: &[u8]) -> Option<&'static str> {
+    if head.starts_with(b"RIFF") {
+        return match head.get(8..12)? {
+            b"WEBP" => Some("image/webp"),
+            b"AVI " => Some("video/x-msvideo"),
+            b"WAVE" => Some("audio/wav"),
+            _ => None,
+        };
+    }
+    if head.get(4..8) == Some(b"ftyp") {
+        return Some(match head.get(8..12)? {
+            b"avif" | b"avis" => "image/avif",
+            b"heic" | b"heix" | b"mif1" | b"msf1" => "image/heic",
+            b"qt  " => "video/quicktime",
+            b"M4A " => "audio/mp4",
+            _ => "video/mp4",
+        });
+    }
+    if head.starts_with(b"\x1a\x45\xdf\xa3") {
+        let is_webm = head.windows(4).any(|w| w == b"webm");
+        return Some(if is_webm {
+            "video/webm"
+        } else {
+            "video/x-matroska"
+        });
+    }
+    None
+}
+
+fn is_svg(head: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(head);
+    let text = text.trim_start_matches('\u{feff}').trim_start();
+    (text.starts_with("<svg") || text.starts_with("<?xml")) && text.contains("<svg")
 }
 
 #[cfg(test)]
@@ -21,4 +137,41 @@ mod tests {
     fn printable_with_newlines_not_binary() {
         assert!(!is_binary(b"line1\nline2\nline3\n"));
     }
+
+    #[test]
+    fn crlf_and_colors_not_binary() {
+        assert!(!is_binary(b"\x1b[1mbold\x1b[0m\r\n"));
+    }
+
+    #[test]
+    fn sniffs_common_formats() {
+        assert_eq!(sniff_mime(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), "image/png");
+        assert_eq!(sniff_mime(b"%PDF-1.7\n%\xe2\xe3"), "application/pdf");
+        assert_eq!(sniff_mime(b"RIFF\0\0\0\0WEBPVP8 "), "image/webp");
+        assert_eq!(sniff_mime(b"\0\0\0\x20ftypisom\0\0\x02\0"), "video/mp4");
+        assert_eq!(sniff_mime(b"\0\0\0\x1cftypavif"), "image/avif");
+        assert_eq!(sniff_mime(b"II*\0\x10\0\0\0CR\x02\0"), "image/x-canon-cr2");
+        assert_eq!(sniff_mime(b"II*\0\x08\0\0\0"), "image/tiff");
+        assert_eq!(sniff_mime(b"FUJIFILMCCD-RAW 0201"), "image/x-fuji-raf");
+    }
+
+    #[test]
+    fn falls_back_to_text_or_octet_stream() {
+        assert_eq!(sniff_mime(b"fn main() {}\n"), "text/plain");
+        assert_eq!(sniff_mime(b""), "text/plain");
+        assert_eq!(sniff_mime(&[0x13, 0x37, 0x00]), "application/octet-stream");
+    }
+
+    #[test]
+    fn svg_is_an_image() {
+        let svg = b"<?xml version=\"1.0\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\"/>";
+        assert_eq!(sniff_mime(svg), "image/svg+xml");
+    }
+
+    #[test]
+    fn tar_detected_past_the_header() {
+        let mut tar = vec![b'a'; 512];
+        tar[257..262].copy_from_slice(b"ustar");
+        assert_eq!(sniff_mime(&tar), "application/x-tar");
+    }
 }
@@ -0,0 +1,630 @@
+use std::ops::Range;
+use std::path::Path;
+
+/// What a highlighted span of source is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Keyword,
+    String,
+    Comment,
+    Number,
+}
+
+/// Just enough of a language's lexical rules to colour it
+pub struct Syntax {
+    pub name: &'static str,
+    extensions: &'static [&'static str],
+    file_names: &'static [&'static str],
+    interpreters: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+    keywords: &'static [&'static str],
+    case_insensitive: bool,
+}
+
+const C_KEYWORDS: &[&str] = &[
+    "auto",
+    "break",
+    "case",
+    "char",
+    "class",
+    "const",
+    "continue",
+    "default",
+    "delete",
+    "do",
+    "double",
+    "else",
+    "enum",
+    "extern",
+    "false",
+    "float",
+    "for",
+    "goto",
+    "if",
+    "include",
+    "inline",
+    "int",
+    "long",
+    "namespace",
+    "new",
+    "nullptr",
+    "private",
+    "protected",
+    "public",
+    "register",
+    "return",
+    "short",
+    "signed",
+    "sizeof",
+    "static",
+    "struct",
+    "switch",
+    "template",
+    "this",
+    "true",
+    "typedef",
+    "union",
+    "unsigned",
+    "using",
+    "virtual",
+    "void",
+    "volatile",
+    "while",
+];
+
+const JAVA_KEYWORDS: &[&str] = &[
+    "abstract",
+    "boolean",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "data",
+    "default",
+    "do",
+    "else",
+    "enum",
+    "extends",
+    "false",
+    "final",
+    "finally",
+    "for",
+    "fun",
+    "if",
+    "implements",
+    "import",
+    "in",
+    "instanceof",
+    "interface",
+    "is",
+    "new",
+    "null",
+    "object",
+    "override",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "return",
+    "static",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "throws",
+    "true",
+    "try",
+    "val",
+    "var",
+    "void",
+    "when",
+    "while",
+];
+
+const SYNTAXES: &[Syntax] = &[
+    Syntax {
+        name: "rust",
+        extensions: &["rs"],
+        file_names: &[],
+        interpreters: &[],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        // `'` also starts lifetimes, so char literals are left plain
+        quotes: &['"'],
+        keywords: &[
+            "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+            "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod",
+            "move", "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super",
+            "trait", "true", "type", "unsafe", "use", "where", "while",
+        ],
+        case_insensitive: false,
+    },
+    Syntax {
+        name: "c",
+        extensions: &["c", "h", "cc", "cpp", "cxx", "hpp", "hh"],
+        file_names: &[],
+        interpreters: &[],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''],
+        keywords: C_KEYWORDS,
+        case_insensitive: false,
+    },
+    Syntax {
+        name: "go",
+        extensions: &["go"],
+        file_names: &[],
+        interpreters: &[],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\'', '`'],
+        keywords: &[
+            "break",
+            "case",
+            "chan",
+            "const",
+            "continue",
+            "default",
+            "defer",
+            "else",
+            "false",
+            "fallthrough",
+            "for",
+            "func",
+            "go",
+            "goto",
+            "if",
+            "import",
+            "interface",
+            "map",
+            "nil",
+            "package",
+            "range",
+            "return",
+            "select",
+            "struct",
+            "switch",
+            "true",
+            "type",
+            "var",
+        ],
+        case_insensitive: false,
+    },
+    Syntax {
+        name: "javascript",
+        extensions: &["js", "mjs", "cjs", "jsx", "ts", "tsx"],
+        file_names: &[],
+        interpreters: &["node", "deno", "bun"],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\'', '`'],
+        keywords: &[
+            "async",
+            "await",
+            "break",
+            "case",
+            "catch",
+            "class",
+            "const",
+            "continue",
+            "default",
+            "delete",
+            "do",
+            "else",
+            "export",
+            "extends",
+            "false",
+            "finally",
+            "for",
+            "from",
+            "function",
+            "if",
+            "import",
+            "in",
+            "instanceof",
+            "interface",
+            "let",
+            "new",
+            "null",
+            "of",
+            "return",
+            "static",
+            "super",
+            "switch",
+            "this",
+            "throw",
+            "true",
+            "try",
+            "type",
+            "typeof",
+            "undefined",
+            "var",
+            "void",
+            "while",
+            "yield",
+        ],
+        case_insensitive: false,
+    },
+    Syntax {
+        name: "java",
+        extensions: &["java", "kt", "kts"],
+        file_names: &[],
+        interpreters: &[],
+        line_comments: &["//"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''],
+        keywords: JAVA_KEYWORDS,
+        case_insensitive: false,
+    },
+    Syntax {
+        name: "python",
+        extensions: &["py", "pyw", "pyi"],
+        file_names: &[],
+        interpreters: &["python", "python2", "python3"],
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+        keywords: &[
+            "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class",
+            "continue", "def", "del", "elif", "else", "except", "finally", "for", "from", "global",
+            "if", "import", "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise",
+            "return", "self", "try", "while", "with", "yield",
+        ],
+        case_insensitive: false,
+    },
+    Syntax {
+        name: "ruby",
+        extensions: &["rb"],
+        file_names: &["Gemfile", "Rakefile"],
+        interpreters: &["ruby"],
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+        keywords: &[
+            "begin", "class", "def", "do", "else", "elsif", "end", "ensure", "false", "if",
+            "module", "nil", "puts", "require", "rescue", "return", "self", "then", "true",
+            "unless", "until", "when", "while", "yield",
+        ],
+        case_insensitive: false,
+    },
+    Syntax {
+        name: "lua",
+        extensions: &["lua"],
+        file_names: &[],
+        interpreters: &["lua", "luajit"],
+        line_comments: &["--"],
+        block_comment: Some(("--[[", "]]")),
+        quotes: &['"', '\''],
+        keywords: &[
+            "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "if", "in",
+            "local", "nil", "not", "or", "repeat", "return", "then", "true", "until", "while",
+        ],
+        case_insensitive: false,
+    },
+    Syntax {
+        name: "shell",
+        extensions: &["sh", "bash", "zsh", "fish", "ksh"],
+        file_names: &[".bashrc", ".bash_profile", ".zshrc", ".profile", "PKGBUILD"],
+        interpreters: &["sh", "bash", "zsh", "dash", "ksh", "fish"],
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+        keywords: &[
+            "case", "do", "done", "echo", "elif", "else", "esac", "exit", "export", "fi", "for",
+            "function", "if", "in", "local", "return", "set", "then", "until", "while",
+        ],
+        case_insensitive: false,
+    },
+    Syntax {
+        name: "make",
+        extensions: &["mk"],
+        file_names: &["Makefile", "makefile", "GNUmakefile"],
+        interpreters: &[],
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &[],
+        keywords: &[
+            "define", "else", "endef", "endif", "ifdef", "ifeq", "ifndef", "include",
+        ],
+        case_insensitive: false,
+    },
+    Syntax {
+        name: "sql",
+        extensions: &["sql"],
+        file_names: &[],
+        interpreters: &[],
+        line_comments: &["--"],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['\''],
+        keywords: &[
+            "and", "as", "asc", "by", "create", "delete", "desc", "distinct", "drop", "from",
+            "group", "having", "in", "index", "insert", "into", "is", "join", "left", "limit",
+            "not", "null", "on", "or", "order", "primary", "select", "set", "table", "update",
+            "values", "where",
+        ],
+        case_insensitive: true,
+    },
+    Syntax {
+        name: "css",
+        extensions: &["css", "scss"],
+        file_names: &[],
+        interpreters: &[],
+        line_comments: &[],
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"', '\''],
+        keywords: &["important", "inherit", "initial", "none", "unset"],
+        case_insensitive: false,
+    },
+    Syntax {
+        name: "toml",
+        extensions: &["toml", "ini", "conf", "cfg"],
+        file_names: &[],
+        interpreters: &[],
+        line_comments: &["#", ";"],
+        block_comment: None,
+        quotes: &['"', '\''],
+        keywords: &["false", "true"],
+        case_insensitive: false,
+    },
+    Syntax {
+        name: "yaml",
+        extensions: &["yml", "yaml"],
+        file_names: &[],
+        interpreters: &[],
+        line_comments: &["#"],
+        block_comment: None,
+        quotes: &['"', '\''],
+        keywords: &["false", "no", "null", "true", "yes"],
+        case_insensitive: false,
+    },
+    Syntax {
+        name: "json",
+        extensions: &["json", "jsonc"],
+        file_names: &[],
+        interpreters: &[],
+        line_comments: &[],
+        block_comment: None,
+        quotes: &['"'],
+        keywords: &["false", "null", "true"],
+        case_insensitive: false,
+    },
+];
+
+/// Pick a syntax from the file's extension or name, then from a `#!` line
+pub fn detect(path: Option<&Path>, text: &str) -> Option<&'static Syntax> {
+    if let Some(path) = path {
+        let name = path.file_name().map(|n| n.to_string_lossy());
+        let ext = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase());
+        let by_path = SYNTAXES.iter().find(|syntax| {
+            name.as_deref()
+                .is_some_and(|n| syntax.file_names.contains(&n))
+                || ext
+                    .as_deref()
+                    .is_some_and(|e| syntax.extensions.contains(&e))
+        });
+        if by_path.is_some() {
+            return by_path;
+        }
+    }
+    let interpreter = shebang_interpreter(text.lines().next()?)?;
+    SYNTAXES
+        .iter()
+        .find(|syntax| syntax.interpreters.contains(&interpreter))
+}
+
+/// `python3` from `#!/usr/bin/python3` or `#!/usr/bin/env -S python3 -u`
+fn shebang_interpreter(first_line: &str) -> Option<&str> {
+    let mut words = first_line.strip_prefix("#!")?.split_whitespace();
+    let program = words.next()?.rsplit('/').next()?;
+    if program == "env" {
+        words.find(|word| !word.starts_with('-'))
+    } else {
+        Some(program)
+    }
+}
+
+/// Spans to colour on each line, as byte ranges within that line.
+/// Block comments and strings carry over to the following lines.
+pub fn highlight_lines(text: &str, syntax: &Syntax) -> Vec<Vec<(Range<usize>, TokenKind)>> {
+    let mut open: Option<Open> = None;
+    text.lines()
+        .map(|line| highlight_line(line, syntax, &mut open))
+        .collect()
+}
+
+#[derive(Clone, Copy)]
+enum Open {
+    Comment,
+    String(char),
+}
+
+fn highlight_line(
+    line: &str,
+    syntax: &Syntax,
+    open: &mut Option<Open>,
+) -> Vec<(Range<usize>, TokenKind)> {
+    let mut spans = Vec::new();
+    let mut pos = 0;
+
+    while pos < line.len() {
+        let rest = &line[pos..];
+
+        if let Some(state) = *open {
+            let (end, kind) = match state {
+                Open::Comment => {
+                    let close = syntax.block_comment.map_or("", |(_, close)| close);
+                    (
+                        rest.find(close).map(|i| i + close.len()),
+                        TokenKind::Comment,
+                    )
+                }
+                Open::String(quote) => (string_end(rest, quote), TokenKind::String),
+            };
+            let end = match end {
+                Some(end) => {
+                    *open = None;
+                    pos + end
+                }
+                None => line.len(),
+            };
+            spans.push((pos..end, kind));
+            pos = end;
+            continue;
+        }
+
+        if syntax.line_comments.iter().any(|c| rest.starts_with(c))
+            && !syntax
+                .block_comment
+                .is_some_and(|(start, _)| rest.starts_with(start))
+        {
+            spans.push((pos..line.len(), TokenKind::Comment));
+            break;
+        }
+        if let Some((start, _)) = syntax.block_comment
+            && rest.starts_with(start)
+        {
+            *open = Some(Open::Comment);
+            spans.push((pos..pos + start.len(), TokenKind::Comment));
+            pos += start.len();
+            continue;
+        }
+
+        let c = rest.chars().next().unwrap_or_default();
+        if syntax.quotes.contains(&c) {
+            *open = Some(Open::String(c));
+            spans.push((pos..pos + 1, TokenKind::String));
+            pos += 1;
+            continue;
+        }
+
+        if c.is_ascii_digit() {
+            let len = word_len(rest, true);
+            spans.push((pos..pos + len, TokenKind::Number));
+            pos += len;
+        } else if c.is_alphabetic() || c == '_' {
+            let len = word_len(rest, false);
+            let word = &rest[..len];
+            let is_keyword = if syntax.case_insensitive {
+                syntax.keywords.iter().any(|k| k.eq_ignore_ascii_case(word))
+            } else {
+                syntax.keywords.contains(&word)
+            };
+            if is_keyword {
+                spans.push((pos..pos + len, TokenKind::Keyword));
+            }
+            pos += len;
+        } else {
+            pos += c.len_utf8();
+        }
+    }
+    merge_adjacent(spans)
+}
+
+/// Where a string that started before `rest` closes, skipping escaped quotes
+fn string_end(rest: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in rest.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            c if c == quote => return Some(i + c.len_utf8()),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Identifiers stop at a `.`, numbers such as `1.5e3` run through it
+fn word_len(rest: &str, number: bool) -> usize {
+    rest.find(|c: char| !(c.is_alphanumeric() || c == '_' || (number && c == '.')))
+        .unwrap_or(rest.len())
+}
+
+/// An opening quote and the string body come out as separate spans; join them
+fn merge_adjacent(spans: Vec<(Range<usize>, TokenKind)>) -> Vec<(Range<usize>, TokenKind)> {
+    let mut merged: Vec<(Range<usize>, TokenKind)> = Vec::with_capacity(spans.len());
+    for (range, kind) in spans {
+        match merged.last_mut() {
+            Some((last, last_kind)) if *last_kind == kind && last.end == range.start => {
+                last.end = range.end;
+            }
+            _ => merged.push((range, kind)),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rust() -> &'static Syntax {
+        detect(Some(Path::new("main.rs")), "").unwrap()
+    }
+
+    #[test]
+    fn detects_by_extension_name_and_shebang() {
+        assert_eq!(rust().name, "rust");
+        assert_eq!(
+            detect(Some(Path::new("/src/Makefile")), "").unwrap().name,
+            "make"
+        );
+        assert_eq!(
+            detect(None, "#!/usr/bin/env -S python3 -u\nprint(1)")
+                .unwrap()
+                .name,
+            "python"
+        );
+        assert_eq!(detect(None, "#!/bin/bash\n").unwrap().name, "shell");
+        assert!(detect(Some(Path::new("notes.txt")), "hello").is_none());
+    }
+
+    #[test]
+    fn colours_keywords_strings_numbers_and_comments() {
+        let lines = highlight_lines("let s = \"a\\\"b\"; // 42\nfn x() -> u8 { 7 }", rust());
+        assert_eq!(
+            lines[0],
+            vec![
+                (0..3, TokenKind::Keyword),
+                (8..14, TokenKind::String),
+                (16..21, TokenKind::Comment),
+            ]
+        );
+        assert_eq!(
+            lines[1],
+            vec![(0..2, TokenKind::Keyword), (15..16, TokenKind::Number)]
+        );
+    }
+
+    #[test]
+    fn block_comments_span_lines() {
+        let lines = highlight_lines("a /* one\ntwo */ if", rust());
+        assert_eq!(lines[0], vec![(2..8, TokenKind::Comment)]);
+        assert_eq!(
+            lines[1],
+            vec![(0..6, TokenKind::Comment), (7..9, TokenKind::Keyword)]
+        );
+    }
+
+    #[test]
+    fn identifiers_containing_keywords_are_plain() {
+        let lines = highlight_lines("letter format self.x", rust());
+        assert_eq!(lines[0], vec![(14..18, TokenKind::Keyword)]);
+    }
+
+    #[test]
+    fn sql_keywords_ignore_case() {
+        let sql = detect(Some(Path::new("q.sql")), "").unwrap();
+        let lines = highlight_lines("SELECT 1", sql);
+        assert_eq!(
+            lines[0],
+            vec![(0..6, TokenKind::Keyword), (7..8, TokenKind::Number)]
+        );
+    }
+}
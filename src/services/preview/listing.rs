@@ -0,0 +1,490 @@
+use crate::constants::{
+    ARCHIVE_EXTENSIONS, ARCHIVE_TIMEOUT_SECS, IMAGE_EXTENSIONS, MAX_LISTING_ENTRIES,
+    MAX_LISTING_LINES,
+};
+use crate::services::jobs::CancelToken;
+use crate::services::process::CommandExecutor;
+use crate::utils::format_size;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// One file or folder, its path relative to the listed root and `/`-separated
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub path: String,
+    pub size: u64,
+    pub is_dir: bool,
+}
+
+/// A listing and whether it stopped at `MAX_LISTING_ENTRIES`
+pub type Listing = (Vec<Entry>, bool);
+
+pub fn is_archive(path: &Path) -> bool {
+    if has_archive_name(path) {
+        return true;
+    }
+    path.extension().is_none()
+        && super::detector::detect_mime(path).is_some_and(|mime| {
+            matches!(
+                mime,
+                "application/zip" | "application/x-tar" | "application/x-7z-compressed"
+            )
+        })
+}
+
+fn has_archive_name(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    ARCHIVE_EXTENSIONS.iter().any(|ext| {
+        name.strip_suffix(ext)
+            .is_some_and(|rest| rest.ends_with('.'))
+    })
+}
+
+/// Whether `is_archive` could hold for a file, judging by its name alone
+pub fn may_be_archive(path: &Path) -> bool {
+    has_archive_name(path) || path.extension().is_none()
+}
+
+/// Whether an item's value names something shown as a tree rather than as text
+pub fn is_listable(path: &Path) -> bool {
+    path.is_dir() || (path.is_file() && is_archive(path))
+}
+
+/// Everything below `root`, however deep, so folder sizes cover their whole subtree;
+/// `render_tree` decides how many levels are drawn
+pub fn list_directory(root: &Path, cancel: &CancelToken) -> Listing {
+    let mut entries = Vec::new();
+    let walk = walkdir::WalkDir::new(root).min_depth(1).sort_by_file_name();
+    for entry in walk.into_iter().flatten() {
+        if cancel.is_cancelled() || entries.len() >= MAX_LISTING_ENTRIES {
+            return (entries, true);
+        }
+        let Ok(relative) = entry.path().strip_prefix(root) else {
+            continue;
+        };
+        let is_dir = entry.file_type().is_dir();
+        entries.push(Entry {
+            path: relative.to_string_lossy().replace('\\', "/"),
+            size: if is_dir {
+                0
+            } else {
+                entry.metadata().map(|meta| meta.len()).unwrap_or(0)
+            },
+            is_dir,
+        });
+    }
+    (entries, false)
+}
+
+/// Contents of a zip, tarball or 7z archive, as `bsdtar` lists them
+pub fn list_archive(
+    archive: &Path,
+    executor: &dyn CommandExecutor,
+    cancel: &CancelToken,
+) -> Result<Listing, String> {
+    let archive_str = archive.to_string_lossy();
+    let output = executor
+        .execute_cancellable(
+            "bsdtar",
+            &["-tvf", &archive_str],
+            ARCHIVE_TIMEOUT_SECS,
+            cancel,
+        )
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => {
+                "Listing archives needs bsdtar (libarchive-tools)".to_string()
+            }
+            _ => format!("bsdtar failed: {}", e),
+        })?;
+    if !output.success {
+        return Err("bsdtar could not read the archive".to_string());
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut entries: Vec<Entry> = text.lines().filter_map(parse_bsdtar_line).collect();
+    // bsdtar prints archive order; `render_tree` needs the walk order `list_directory` gives
+    entries.sort_by(|a, b| a.path.split('/').cmp(b.path.split('/')));
+    let truncated = entries.len() > MAX_LISTING_ENTRIES;
+    entries.truncate(MAX_LISTING_ENTRIES);
+    Ok((entries, truncated))
+}
+
+/// An `ls -l` style line: mode, links, owner, group, size, three date fields, name
+fn parse_bsdtar_line(line: &str) -> Option<Entry> {
+    let mut rest = line;
+    let mut fields = Vec::with_capacity(8);
+    for _ in 0..8 {
+        rest = rest.trim_start();
+        let end = rest.find(char::is_whitespace)?;
+        fields.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    // A single space separates the date from the name, which may itself start with spaces
+    let name = rest.strip_prefix(' ')?;
+    let mode = fields[0];
+    let name = if mode.starts_with('l') {
+        name.split(" -> ").next().unwrap_or(name)
+    } else {
+        name
+    };
+    let path = name.trim_end_matches('/').trim_start_matches("./");
+    if path.is_empty() || path == "." {
+        return None;
+    }
+    Some(Entry {
+        path: path.to_string(),
+        size: fields[4].parse().unwrap_or(0),
+        is_dir: mode.starts_with('d'),
+    })
+}
+
+/// The image a comic or wallpaper pack would open on: the first by name
+pub fn first_image(entries: &[Entry]) -> Option<&str> {
+    entries
+        .iter()
+        .filter(|entry| !entry.is_dir)
+        .map(|entry| entry.path.as_str())
+        .filter(|path| {
+            Path::new(path)
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        })
+        .min()
+}
+
+/// Bytes of one archive member
+pub fn extract(
+    archive: &Path,
+    member: &str,
+    executor: &dyn CommandExecutor,
+    cancel: &CancelToken,
+) -> Option<Vec<u8>> {
+    let archive_str = archive.to_string_lossy();
+    let pattern = escape_pattern(member);
+    let output = executor
+        .execute_cancellable(
+            "bsdtar",
+            &["-xOf", &archive_str, "--", &pattern],
+            ARCHIVE_TIMEOUT_SECS,
+            cancel,
+        )
+        .ok()?;
+    (output.success && !output.stdout.is_empty()).then_some(output.stdout)
+}
+
+/// bsdtar matches member arguments as patterns, so a name like `[Group] 001.jpg` needs escaping
+fn escape_pattern(member: &str) -> String {
+    let mut pattern = String::with_capacity(member.len());
+    for c in member.chars() {
+        if matches!(c, '\\' | '*' | '?' | '[') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern
+}
+
+#[derive(Default)]
+struct Node {
+    children: BTreeMap<String, Node>,
+    size: u64,
+    is_dir: bool,
+    /// A folder the listing stopped inside, whose total would be too small
+    partial: bool,
+}
+
+impl Node {
+    fn build((entries, truncated): &Listing) -> Node {
+        let mut root = Node {
+            is_dir: true,
+            ..Node::default()
+        };
+        for entry in entries {
+            let mut node = &mut root;
+            for part in entry.path.split('/').filter(|part| !part.is_empty()) {
+                // Archives often name files without listing their folders first
+                node.is_dir = true;
+                node = node.children.entry(part.to_string()).or_default();
+            }
+            node.is_dir |= entry.is_dir;
+            node.size = entry.size;
+        }
+        // Entries come in walk order, so only the folders around the last one were cut short
+        root.partial = *truncated;
+        if *truncated && let Some(last) = entries.last() {
+            let mut node = &mut root;
+            for part in last.path.split('/').filter(|part| !part.is_empty()) {
+                let Some(child) = node.children.get_mut(part) else {
+                    break;
+                };
+                child.partial = child.is_dir;
+                node = child;
+            }
+        }
+        root
+    }
+
+    fn total_size(&self) -> u64 {
+        if self.is_dir {
+            self.children.values().map(Node::total_size).sum()
+        } else {
+            self.size
+        }
+    }
+
+    /// Files and folders below this node
+    fn counts(&self) -> (usize, usize) {
+        self.children
+            .values()
+            .fold((0, 0), |(files, folders), child| {
+                let (f, d) = child.counts();
+                if child.is_dir {
+                    (files + f, folders + d + 1)
+                } else {
+                    (files + f + 1, folders + d)
+                }
+            })
+    }
+}
+
+/// A tree drawn with box characters, folders first, under a count of everything listed.
+/// Levels past `depth` are folded into their folder's line.
+pub fn render_tree(listing: &Listing, depth: usize) -> String {
+    let root = Node::build(listing);
+    let (files, folders) = root.counts();
+    let mut out = format!(
+        "{} {}, {} {}, {}{}\n\n",
+        files,
+        if files == 1 { "file" } else { "files" },
+        folders,
+        if folders == 1 { "folder" } else { "folders" },
+        format_size(root.total_size()),
+        if root.partial {
+            " (listing cut short)"
+        } else {
+            ""
+        },
+    );
+    let mut lines = 0;
+    let mut hidden = 0;
+    write_children(&root, "", depth.max(1), &mut out, &mut lines, &mut hidden);
+    if hidden > 0 {
+        out.push_str(&format!("… {} more\n", hidden));
+    }
+    out
+}
+
+fn write_children(
+    node: &Node,
+    prefix: &str,
+    depth: usize,
+    out: &mut String,
+    lines: &mut usize,
+    hidden: &mut usize,
+) {
+    let mut children: Vec<(&String, &Node)> = node.children.iter().collect();
+    children.sort_by_key(|(_, child)| !child.is_dir);
+    let count = children.len();
+
+    for (i, (name, child)) in children.into_iter().enumerate() {
+        if *lines >= MAX_LISTING_LINES {
+            *hidden += 1;
+            continue;
+        }
+        *lines += 1;
+        let last = i + 1 == count;
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        out.push_str(&format!("{}{}{}", prefix, branch, name));
+        if !child.is_dir {
+            out.push_str(&format!("  {}", format_size(child.size)));
+        } else if child.children.is_empty() || child.partial {
+            out.push('/');
+        } else {
+            out.push_str(&format!("/  {}", format_size(child.total_size())));
+        }
+        out.push('\n');
+        if child.is_dir && depth > 1 {
+            write_children(
+                child,
+                &format!("{}{}", prefix, indent),
+                depth - 1,
+                out,
+                lines,
+                hidden,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::process::{CommandOutput, MockExec};
+    use std::io;
+    use std::sync::Mutex;
+
+    fn file(path: &str, size: u64) -> Entry {
+        Entry {
+            path: path.to_string(),
+            size,
+            is_dir: false,
+        }
+    }
+
+    #[test]
+    fn parses_bsdtar_lines() {
+        let entry =
+            parse_bsdtar_line("-rw-r--r--  0 user   group    1234 Jan  1  2024 pages/001 a.jpg")
+                .unwrap();
+        assert_eq!(entry, file("pages/001 a.jpg", 1234));
+        let dir =
+            parse_bsdtar_line("drwxr-xr-x  0 0      0           0 Mar 14 09:30 pages/").unwrap();
+        assert!(dir.is_dir);
+        assert_eq!(dir.path, "pages");
+        assert_eq!(parse_bsdtar_line("garbage"), None);
+    }
+
+    #[test]
+    fn renders_nested_tree_with_totals() {
+        let entries = vec![
+            file("readme.txt", 10),
+            file("img/b.png", 2048),
+            file("img/a.png", 1024),
+        ];
+        let tree = render_tree(&(entries, false), 2);
+        let expected = format!(
+            "3 files, 1 folder, {}\n\n├── img/  {}\n│   ├── a.png  {}\n│   └── b.png  {}\n└── readme.txt  {}\n",
+            format_size(3082),
+            format_size(3072),
+            format_size(1024),
+            format_size(2048),
+            format_size(10),
+        );
+        assert_eq!(tree, expected);
+    }
+
+    #[test]
+    fn depth_folds_deeper_levels() {
+        let entries = vec![file("a/b/c.txt", 5)];
+        let tree = render_tree(&(entries, false), 1);
+        assert_eq!(tree, "1 file, 2 folders, 5 B\n\n└── a/  5 B\n");
+    }
+
+    #[test]
+    fn folders_cut_short_have_no_size() {
+        let entries = vec![file("a/x", 1), file("b/y", 2)];
+        let tree = render_tree(&(entries, true), 1);
+        assert_eq!(
+            tree,
+            "2 files, 2 folders, 3 B (listing cut short)\n\n├── a/  1 B\n└── b/\n"
+        );
+    }
+
+    #[test]
+    fn archive_listings_are_cut_short_in_walk_order() {
+        // `a/z` comes last in the archive but sorts into `a/`, which is then whole
+        let line = |path: &str| format!("-rw-r--r--  0 u g 1 Jan  1  2024 {}\n", path);
+        let mut text = line("a/x");
+        for i in 0..MAX_LISTING_ENTRIES - 1 {
+            text.push_str(&line(&format!("b/{:05}", i)));
+        }
+        text.push_str(&line("a/z"));
+        let exec = MockExec::new().push_ok(true, text.into_bytes());
+        let listing = list_archive(Path::new("/x/pack.zip"), &exec, &CancelToken::never()).unwrap();
+        assert!(listing.1);
+        assert!(render_tree(&listing, 1).ends_with("├── a/  2 B\n└── b/\n"));
+    }
+
+    /// Records the arguments of every command it is asked to run
+    #[derive(Default)]
+    struct RecordingExec {
+        args: Mutex<Vec<Vec<String>>>,
+    }
+
+    impl CommandExecutor for RecordingExec {
+        fn execute(&self, _program: &str, args: &[&str]) -> io::Result<CommandOutput> {
+            self.args
+                .lock()
+                .unwrap()
+                .push(args.iter().map(|arg| arg.to_string()).collect());
+            Ok(CommandOutput {
+                success: true,
+                stdout: b"bytes".to_vec(),
+            })
+        }
+
+        fn execute_cancellable(
+            &self,
+            program: &str,
+            args: &[&str],
+            _timeout_secs: u64,
+            _cancel: &CancelToken,
+        ) -> io::Result<CommandOutput> {
+            self.execute(program, args)
+        }
+    }
+
+    #[test]
+    fn extract_escapes_pattern_characters() {
+        let exec = RecordingExec::default();
+        let bytes = extract(
+            Path::new("/x/pack.zip"),
+            "[Group] 001*?\\.jpg",
+            &exec,
+            &CancelToken::never(),
+        );
+        assert_eq!(bytes.as_deref(), Some(&b"bytes"[..]));
+        assert_eq!(
+            exec.args.into_inner().unwrap(),
+            vec![vec![
+                "-xOf".to_string(),
+                "/x/pack.zip".to_string(),
+                "--".to_string(),
+                "\\[Group] 001\\*\\?\\\\.jpg".to_string(),
+            ]]
+        );
+    }
+
+    #[test]
+    fn cover_is_first_image_by_name() {
+        let entries = vec![
+            file("notes.txt", 1),
+            file("p/010.jpg", 1),
+            file("p/002.PNG", 1),
+        ];
+        assert_eq!(first_image(&entries), Some("p/002.PNG"));
+        assert_eq!(first_image(&entries[..1]), None);
+    }
+
+    #[test]
+    fn archives_by_name() {
+        assert!(is_archive(Path::new("/x/pack.tar.gz")));
+        assert!(is_archive(Path::new("/x/Comic.CBZ")));
+        assert!(!is_archive(Path::new("/x/notes.gz.txt")));
+        assert!(!is_archive(Path::new("/x/tar")));
+    }
+
+    #[test]
+    fn directories_list_the_whole_subtree() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("a/b")).unwrap();
+        std::fs::write(dir.path().join("a/one.txt"), b"1").unwrap();
+        std::fs::write(dir.path().join("a/b/deep.txt"), b"22").unwrap();
+        let listing = list_directory(dir.path(), &CancelToken::never());
+        assert!(!listing.1);
+        let paths: Vec<&str> = listing.0.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, vec!["a", "a/b", "a/b/deep.txt", "a/one.txt"]);
+        // Sizes below the drawn depth still count towards the folder
+        assert_eq!(
+            render_tree(&listing, 1),
+            "2 files, 2 folders, 3 B\n\n└── a/  3 B\n"
+        );
+    }
+}
@@ -0,0 +1,243 @@
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+use std::ops::Range;
+
+/// How a stretch of rendered Markdown is styled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Element {
+    Heading(u8),
+    Emphasis,
+    Strong,
+    Strikethrough,
+    Code,
+    CodeBlock,
+    Quote,
+    Link,
+    /// List bullets, numbers and rules
+    Marker,
+}
+
+/// Markdown laid out as plain text plus the elements covering it
+#[derive(Debug, Default, PartialEq)]
+pub struct Document {
+    pub text: String,
+    /// Ranges in characters, the way text buffers count offsets
+    pub spans: Vec<(Range<usize>, Element)>,
+}
+
+const RULE: &str = "────────────────";
+
+pub fn render(source: &str) -> Document {
+    let options = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let mut renderer = Renderer::default();
+    for event in Parser::new_ext(source, options) {
+        match event {
+            Event::Start(tag) => renderer.start(tag),
+            Event::End(tag) => renderer.end(tag),
+            Event::Text(text) => renderer.push(&text),
+            Event::Code(code) => {
+                let start = renderer.len;
+                renderer.push(&code);
+                renderer
+                    .doc
+                    .spans
+                    .push((start..renderer.len, Element::Code));
+            }
+            Event::SoftBreak => renderer.push(" "),
+            Event::HardBreak => renderer.push("\n"),
+            Event::Rule => {
+                renderer.block();
+                renderer.push_marker(RULE);
+            }
+            Event::TaskListMarker(done) => renderer.push(if done { "☑ " } else { "☐ " }),
+            _ => {}
+        }
+    }
+    renderer.finish()
+}
+
+#[derive(Default)]
+struct Renderer {
+    doc: Document,
+    /// Length of `doc.text` in characters
+    len: usize,
+    open: Vec<(usize, Element)>,
+    /// Next number of each enclosing list, `None` for bullets
+    lists: Vec<Option<u64>>,
+    /// Right after a list marker, where the item's first block must not break the line
+    at_marker: bool,
+}
+
+impl Renderer {
+    fn start(&mut self, tag: Tag) {
+        match tag {
+            Tag::Paragraph => self.block(),
+            Tag::Heading { level, .. } => {
+                self.block();
+                self.open(Element::Heading(level as u8));
+            }
+            Tag::BlockQuote(_) => {
+                self.block();
+                self.open(Element::Quote);
+            }
+            Tag::CodeBlock(_) => {
+                self.block();
+                self.open(Element::CodeBlock);
+            }
+            Tag::List(first) => {
+                if self.lists.is_empty() {
+                    self.block();
+                } else {
+                    self.newline();
+                }
+                self.lists.push(first);
+            }
+            Tag::Item => {
+                self.newline();
+                let depth = self.lists.len().saturating_sub(1);
+                self.push(&"  ".repeat(depth));
+                let marker = match self.lists.last_mut() {
+                    Some(Some(n)) => {
+                        *n += 1;
+                        format!("{}. ", *n - 1)
+                    }
+                    _ => "• ".to_string(),
+                };
+                self.push_marker(&marker);
+                self.at_marker = true;
+            }
+            Tag::Emphasis => self.open(Element::Emphasis),
+            Tag::Strong => self.open(Element::Strong),
+            Tag::Strikethrough => self.open(Element::Strikethrough),
+            Tag::Link { .. } => self.open(Element::Link),
+            _ => {}
+        }
+    }
+
+    fn end(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::Heading(_)
+            | TagEnd::BlockQuote(_)
+            | TagEnd::CodeBlock
+            | TagEnd::Emphasis
+            | TagEnd::Strong
+            | TagEnd::Strikethrough
+            | TagEnd::Link => {
+                if let Some((start, element)) = self.open.pop() {
+                    self.doc.spans.push((start..self.len, element));
+                }
+            }
+            TagEnd::List(_) => {
+                self.lists.pop();
+            }
+            _ => {}
+        }
+    }
+
+    fn open(&mut self, element: Element) {
+        self.open.push((self.len, element));
+    }
+
+    fn push(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        self.doc.text.push_str(text);
+        self.len += text.chars().count();
+        self.at_marker = false;
+    }
+
+    fn push_marker(&mut self, marker: &str) {
+        let start = self.len;
+        self.push(marker);
+        self.doc.spans.push((start..self.len, Element::Marker));
+    }
+
+    fn newline(&mut self) {
+        if !self.doc.text.is_empty() && !self.doc.text.ends_with('\n') {
+            self.push("\n");
+        }
+    }
+
+    /// Separate blocks by a blank line, or by a line break inside lists
+    fn block(&mut self) {
+        if self.doc.text.is_empty() || self.at_marker {
+            return;
+        }
+        let gap = if self.lists.is_empty() { "\n\n" } else { "\n" };
+        while !self.doc.text.ends_with(gap) {
+            self.push("\n");
+        }
+    }
+
+    fn finish(mut self) -> Document {
+        let trimmed = self.doc.text.trim_end_matches('\n').len();
+        self.len -= self.doc.text.len() - trimmed;
+        self.doc.text.truncate(trimmed);
+        for (range, _) in &mut self.doc.spans {
+            range.end = range.end.min(self.len);
+        }
+        self.doc.spans.retain(|(range, _)| !range.is_empty());
+        self.doc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spanned(doc: &Document, element: Element) -> Vec<String> {
+        doc.spans
+            .iter()
+            .filter(|(_, e)| *e == element)
+            .map(|(range, _)| {
+                doc.text
+                    .chars()
+                    .skip(range.start)
+                    .take(range.len())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn headings_and_paragraphs() {
+        let doc = render("# Title\n\nSome *soft*\nwrapped **text**.\n");
+        assert_eq!(doc.text, "Title\n\nSome soft wrapped text.");
+        assert_eq!(spanned(&doc, Element::Heading(1)), vec!["Title"]);
+        assert_eq!(spanned(&doc, Element::Emphasis), vec!["soft"]);
+        assert_eq!(spanned(&doc, Element::Strong), vec!["text"]);
+    }
+
+    #[test]
+    fn lists_are_numbered_and_nested() {
+        let doc = render("3. one\n4. two\n   - inner\n");
+        assert_eq!(doc.text, "3. one\n4. two\n  • inner");
+        assert_eq!(spanned(&doc, Element::Marker), vec!["3. ", "4. ", "• "]);
+    }
+
+    #[test]
+    fn code_blocks_keep_their_lines() {
+        let doc = render("Run:\n\n```sh\nmake\nmake install\n```\n\nDone `now`");
+        assert_eq!(doc.text, "Run:\n\nmake\nmake install\n\nDone now");
+        assert_eq!(
+            spanned(&doc, Element::CodeBlock),
+            vec!["make\nmake install\n"]
+        );
+        assert_eq!(spanned(&doc, Element::Code), vec!["now"]);
+    }
+
+    #[test]
+    fn links_and_quotes() {
+        let doc = render("> See [the docs](https://example.org) ~~later~~\n");
+        assert_eq!(doc.text, "See the docs later");
+        assert_eq!(spanned(&doc, Element::Link), vec!["the docs"]);
+        assert_eq!(spanned(&doc, Element::Strikethrough), vec!["later"]);
+        assert_eq!(spanned(&doc, Element::Quote), vec!["See the docs later"]);
+    }
+
+    #[test]
+    fn offsets_count_characters() {
+        let doc = render("naïve **café**");
+        assert_eq!(doc.spans, vec![(6..10, Element::Strong)]);
+    }
+}
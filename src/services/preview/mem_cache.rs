@@ -1,117 +1,349 @@
-use std::collections::{HashMap, VecDeque};
-use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use super::PreviewPayload;
+use super::{PreviewPayload, PreviewSize};
 
-#[derive(Clone)]
-pub struct MemoryCache {
-    entries: Arc<Mutex<HashMap<PathBuf, PreviewPayload>>>,
-    order: Arc<Mutex<VecDeque<PathBuf>>>,
-    max_size: usize,
+/// What a cached payload was produced from
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MemKey {
+    /// Decoded image, identified by its disk cache path (which includes the decode size)
+    File(PathBuf),
+    /// Output of a preview command for one item, kept for `COMMAND_PREVIEW_TTL_SECS`
+    /// since nothing tells us when it changes
+    Command { command: String, size: PreviewSize },
+    /// Output of a `[providers]` command, tied to the file's size and mtime
+    Provider {
+        command: String,
+        size: PreviewSize,
+        source: Option<(u64, u64)>,
+    },
+    /// Tree and cover of an archive, tied to the archive's size and mtime
+    Archive {
+        path: PathBuf,
+        size: PreviewSize,
+        source: Option<(u64, u64)>,
+    },
 }
 
-impl MemoryCache {
-    pub fn new(max_size: usize) -> Self {
+const NIL: usize = usize::MAX;
+
+struct Node {
+    key: MemKey,
+    payload: PreviewPayload,
+    cost: usize,
+    expires: Option<Instant>,
+    prev: usize,
+    next: usize,
+}
+
+/// LRU list threaded through a slab, so lookups, promotion and eviction are O(1)
+struct Lru {
+    map: HashMap<MemKey, usize>,
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    /// Most recently used
+    head: usize,
+    /// Least recently used
+    tail: usize,
+    used: usize,
+}
+
+impl Lru {
+    fn new() -> Self {
         Self {
-            entries: Arc::new(Mutex::new(HashMap::new())),
-            order: Arc::new(Mutex::new(VecDeque::new())),
-            max_size,
+            map: HashMap::new(),
+            nodes: Vec::new(),
+            free: Vec::new(),
+            head: NIL,
+            tail: NIL,
+            used: 0,
         }
     }
 
-    pub fn get(&self, path: &Path) -> Option<PreviewPayload> {
-        let entries = self.entries.lock().ok()?;
-        let mut order = self.order.lock().ok()?;
+    fn unlink(&mut self, idx: usize) {
+        let (prev, next) = (self.nodes[idx].prev, self.nodes[idx].next);
+        match prev {
+            NIL => self.head = next,
+            p => self.nodes[p].next = next,
+        }
+        match next {
+            NIL => self.tail = prev,
+            n => self.nodes[n].prev = prev,
+        }
+    }
 
-        if let Some(payload) = entries.get(path) {
-            if let Some(pos) = order.iter().position(|p| p == path) {
-                order.remove(pos);
-            }
-            order.push_back(path.to_path_buf());
-            return Some(payload.clone());
+    fn push_front(&mut self, idx: usize) {
+        self.nodes[idx].prev = NIL;
+        self.nodes[idx].next = self.head;
+        if self.head != NIL {
+            self.nodes[self.head].prev = idx;
+        }
+        self.head = idx;
+        if self.tail == NIL {
+            self.tail = idx;
         }
-        None
     }
 
-    pub fn insert(&self, path: PathBuf, payload: PreviewPayload) {
-        let mut entries = match self.entries.lock() {
-            Ok(guard) => guard,
-            Err(_) => return,
+    fn remove(&mut self, idx: usize) {
+        self.unlink(idx);
+        let key = self.nodes[idx].key.clone();
+        self.map.remove(&key);
+        self.used -= self.nodes[idx].cost;
+        // Drop the payload now rather than when the slot is reused
+        self.nodes[idx].payload = PreviewPayload::Text(String::new());
+        self.free.push(idx);
+    }
+
+    fn get(&mut self, key: &MemKey) -> Option<PreviewPayload> {
+        let idx = *self.map.get(key)?;
+        if self.nodes[idx]
+            .expires
+            .is_some_and(|expires| Instant::now() >= expires)
+        {
+            self.remove(idx);
+            return None;
+        }
+        if self.head != idx {
+            self.unlink(idx);
+            self.push_front(idx);
+        }
+        Some(self.nodes[idx].payload.clone())
+    }
+
+    fn insert(
+        &mut self,
+        key: MemKey,
+        payload: PreviewPayload,
+        cost: usize,
+        expires: Option<Instant>,
+        budget: usize,
+    ) {
+        if let Some(&idx) = self.map.get(&key) {
+            self.remove(idx);
+        }
+        if cost > budget {
+            return;
+        }
+        while self.used + cost > budget && self.tail != NIL {
+            self.remove(self.tail);
+        }
+
+        let node = Node {
+            key: key.clone(),
+            payload,
+            cost,
+            expires,
+            prev: NIL,
+            next: NIL,
         };
-        let mut order = match self.order.lock() {
-            Ok(guard) => guard,
-            Err(_) => return,
+        let idx = match self.free.pop() {
+            Some(idx) => {
+                self.nodes[idx] = node;
+                idx
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
         };
+        self.push_front(idx);
+        self.map.insert(key, idx);
+        self.used += cost;
+    }
+}
 
-        if entries.insert(path.clone(), payload).is_some() {
-            if let Some(pos) = order.iter().position(|p| p == &path) {
-                order.remove(pos);
-            }
-            order.push_back(path);
-            return;
+/// In-memory preview cache bounded by the bytes its payloads hold
+#[derive(Clone)]
+pub struct MemoryCache {
+    inner: Arc<Mutex<Lru>>,
+    max_bytes: usize,
+    command_ttl: Duration,
+}
+
+impl MemoryCache {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Lru::new())),
+            max_bytes,
+            command_ttl: Duration::from_secs(crate::constants::COMMAND_PREVIEW_TTL_SECS),
         }
+    }
 
-        order.push_back(path.clone());
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
 
-        if order.len() > self.max_size
-            && let Some(oldest) = order.pop_front()
-        {
-            entries.remove(&oldest);
+    pub fn get(&self, key: &MemKey) -> Option<PreviewPayload> {
+        self.inner.lock().ok()?.get(key)
+    }
+
+    pub fn insert(&self, key: MemKey, payload: PreviewPayload) {
+        let cost = payload_cost(&key, &payload);
+        let expires =
+            matches!(key, MemKey::Command { .. }).then(|| Instant::now() + self.command_ttl);
+        if let Ok(mut lru) = self.inner.lock() {
+            lru.insert(key, payload, cost, expires, self.max_bytes);
         }
     }
+
+    #[cfg(test)]
+    fn with_command_ttl(mut self, ttl: Duration) -> Self {
+        self.command_ttl = ttl;
+        self
+    }
+
+    #[cfg(test)]
+    fn used_bytes(&self) -> usize {
+        self.inner.lock().map(|lru| lru.used).unwrap_or(0)
+    }
+}
+
+fn payload_cost(key: &MemKey, payload: &PreviewPayload) -> usize {
+    let key_len = match key {
+        MemKey::File(path) | MemKey::Archive { path, .. } => path.as_os_str().len(),
+        MemKey::Command { command, .. } | MemKey::Provider { command, .. } => command.len(),
+    };
+    let payload_len = match payload {
+        PreviewPayload::Text(text)
+        | PreviewPayload::Markdown(text)
+        | PreviewPayload::Error(text) => text.len(),
+        PreviewPayload::Image { bytes, .. } => bytes.len(),
+        PreviewPayload::Animation { frames, .. } => frames.iter().map(|f| f.pixels.len()).sum(),
+        PreviewPayload::Listing { tree, cover } => {
+            tree.len() + cover.as_ref().map_or(0, |(bytes, _, _)| bytes.len())
+        }
+    };
+    key_len + payload_len
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn key(s: &str) -> MemKey {
+        MemKey::File(PathBuf::from(s))
+    }
+
     fn payload(s: &str) -> PreviewPayload {
         PreviewPayload::Text(s.to_string())
     }
 
+    /// Single-letter key plus a payload of `len` bytes
+    fn sized(len: usize) -> PreviewPayload {
+        PreviewPayload::Text("x".repeat(len))
+    }
+
     #[test]
     fn get_returns_inserted() {
-        let cache = MemoryCache::new(4);
-        cache.insert(PathBuf::from("a"), payload("A"));
-        assert!(matches!(cache.get(Path::new("a")), Some(PreviewPayload::Text(ref s)) if s == "A"));
+        let cache = MemoryCache::new(1024);
+        cache.insert(key("a"), payload("A"));
+        assert!(matches!(cache.get(&key("a")), Some(PreviewPayload::Text(ref s)) if s == "A"));
     }
 
     #[test]
     fn get_miss_returns_none() {
-        let cache = MemoryCache::new(4);
-        assert!(cache.get(Path::new("nope")).is_none());
+        let cache = MemoryCache::new(1024);
+        assert!(cache.get(&key("nope")).is_none());
     }
 
     #[test]
     fn lru_evicts_oldest() {
-        let cache = MemoryCache::new(2);
-        cache.insert(PathBuf::from("a"), payload("A"));
-        cache.insert(PathBuf::from("b"), payload("B"));
-        cache.insert(PathBuf::from("c"), payload("C")); // evicts "a"
-        assert!(cache.get(Path::new("a")).is_none());
-        assert!(cache.get(Path::new("b")).is_some());
-        assert!(cache.get(Path::new("c")).is_some());
+        let cache = MemoryCache::new(20);
+        cache.insert(key("a"), sized(9));
+        cache.insert(key("b"), sized(9));
+        cache.insert(key("c"), sized(9)); // evicts "a"
+        assert!(cache.get(&key("a")).is_none());
+        assert!(cache.get(&key("b")).is_some());
+        assert!(cache.get(&key("c")).is_some());
     }
 
     #[test]
     fn get_refreshes_recency() {
-        let cache = MemoryCache::new(2);
-        cache.insert(PathBuf::from("a"), payload("A"));
-        cache.insert(PathBuf::from("b"), payload("B"));
-        cache.get(Path::new("a")); // refresh "a" → now "b" is oldest
-        cache.insert(PathBuf::from("c"), payload("C")); // evicts "b"
-        assert!(cache.get(Path::new("a")).is_some());
-        assert!(cache.get(Path::new("b")).is_none());
+        let cache = MemoryCache::new(20);
+        cache.insert(key("a"), sized(9));
+        cache.insert(key("b"), sized(9));
+        cache.get(&key("a")); // refresh "a" → now "b" is oldest
+        cache.insert(key("c"), sized(9)); // evicts "b"
+        assert!(cache.get(&key("a")).is_some());
+        assert!(cache.get(&key("b")).is_none());
     }
 
     #[test]
     fn insert_overwrites_existing() {
-        let cache = MemoryCache::new(2);
-        cache.insert(PathBuf::from("a"), payload("A"));
-        cache.insert(PathBuf::from("a"), payload("A2"));
-        assert!(
-            matches!(cache.get(Path::new("a")), Some(PreviewPayload::Text(ref s)) if s == "A2")
-        );
+        let cache = MemoryCache::new(1024);
+        cache.insert(key("a"), payload("A"));
+        cache.insert(key("a"), payload("A2"));
+        assert!(matches!(cache.get(&key("a")), Some(PreviewPayload::Text(ref s)) if s == "A2"));
+        assert_eq!(cache.used_bytes(), 3);
+    }
+
+    #[test]
+    fn one_large_payload_evicts_several_small() {
+        let cache = MemoryCache::new(30);
+        for name in ["a", "b", "c"] {
+            cache.insert(key(name), sized(9));
+        }
+        cache.insert(key("d"), sized(19));
+        assert!(cache.get(&key("a")).is_none());
+        assert!(cache.get(&key("b")).is_none());
+        assert!(cache.get(&key("c")).is_some());
+        assert!(cache.get(&key("d")).is_some());
+        assert_eq!(cache.used_bytes(), 30);
+    }
+
+    #[test]
+    fn payload_over_budget_is_not_stored() {
+        let cache = MemoryCache::new(10);
+        cache.insert(key("a"), sized(5));
+        cache.insert(key("b"), sized(50));
+        assert!(cache.get(&key("b")).is_none());
+        assert!(cache.get(&key("a")).is_some());
+    }
+
+    #[test]
+    fn command_keys_include_size() {
+        let cache = MemoryCache::new(1024);
+        let small = MemKey::Command {
+            command: "cliphist decode '1'".to_string(),
+            size: PreviewSize::default(),
+        };
+        let large = MemKey::Command {
+            command: "cliphist decode '1'".to_string(),
+            size: PreviewSize::bucketed(2000, 2000),
+        };
+        cache.insert(small.clone(), payload("text"));
+        assert!(cache.get(&small).is_some());
+        assert!(cache.get(&large).is_none());
+    }
+
+    #[test]
+    fn command_output_expires() {
+        let command = MemKey::Command {
+            command: "date".to_string(),
+            size: PreviewSize::default(),
+        };
+        let cache = MemoryCache::new(1024).with_command_ttl(Duration::ZERO);
+        cache.insert(command.clone(), payload("now"));
+        cache.insert(key("a"), payload("A"));
+        assert!(cache.get(&command).is_none());
+        assert!(cache.get(&key("a")).is_some());
+        assert_eq!(cache.used_bytes(), 2);
+
+        let cache = MemoryCache::new(1024);
+        cache.insert(command.clone(), payload("now"));
+        assert!(cache.get(&command).is_some());
+    }
+
+    #[test]
+    fn slots_are_reused_after_eviction() {
+        let cache = MemoryCache::new(20);
+        for i in 0..100 {
+            cache.insert(key(&format!("{}", i % 10)), sized(8));
+        }
+        let lru = cache.inner.lock().unwrap();
+        assert_eq!(lru.nodes.len(), 2);
+        assert_eq!(lru.map.len(), 2);
     }
 }
@@ -0,0 +1,316 @@
+use exif::{Exif, In, Tag, Value};
+use std::path::Path;
+
+use super::detector;
+
+/// Label/value pairs for an image file: its size, format, dates and EXIF fields.
+/// `dimensions` are the upright width and height, as the decoder reports them.
+pub fn image_details(path: &Path, dimensions: Option<(i32, i32)>) -> Vec<(String, String)> {
+    let exif = read_exif(path);
+    let mut details = Vec::new();
+
+    if let Some((width, height)) = dimensions {
+        details.push(("Dimensions".to_string(), format!("{}×{}", width, height)));
+    }
+    if let Ok(meta) = std::fs::metadata(path) {
+        details.push((
+            "File size".to_string(),
+            crate::utils::format_size(meta.len()),
+        ));
+        if let Some(format) = detector::detect_mime(path).and_then(format_name) {
+            details.push(("Format".to_string(), format));
+        }
+        if let Some(modified) = meta.modified().ok().and_then(format_time) {
+            details.push(("Modified".to_string(), modified));
+        }
+    }
+    if let Some(exif) = &exif {
+        details.extend(exif_details(exif));
+    }
+    details
+}
+
+/// EXIF orientation (1–8) of an image file, if it has one
+pub fn exif_orientation(path: &Path) -> Option<u32> {
+    orientation_of(&read_exif(path)?)
+}
+
+/// EXIF orientation the decoded pixels still need turning by. Only JPEG, TIFF, PNG and WebP
+/// loaders, and RAW previews, leave pixels as stored; HEIF, AVIF and JPEG XL decoders apply
+/// the container's own rotation, which already matches the EXIF one.
+pub fn decode_orientation(path: &Path) -> u32 {
+    match detector::detect_mime(path) {
+        Some("image/jpeg" | "image/tiff" | "image/png" | "image/webp") => {
+            exif_orientation(path).unwrap_or(1)
+        }
+        Some(mime) if super::raw::is_raw_mime(mime) => exif_orientation(path).unwrap_or(1),
+        _ => 1,
+    }
+}
+
+/// Orientations 5–8 turn the image a quarter, so width and height trade places
+pub fn swaps_axes(orientation: u32) -> bool {
+    (5..=8).contains(&orientation)
+}
+
+/// Apply an EXIF orientation to RGBA pixels so they display upright
+pub fn orient_rgba(bytes: &[u8], width: i32, height: i32, orientation: u32) -> (Vec<u8>, i32, i32) {
+    let (w, h) = (width as usize, height as usize);
+    let (out_w, out_h) = if swaps_axes(orientation) {
+        (h, w)
+    } else {
+        (w, h)
+    };
+    let mut out = vec![0u8; w * h * 4];
+
+    for y in 0..h {
+        for x in 0..w {
+            let (nx, ny) = match orientation {
+                2 => (w - 1 - x, y),
+                3 => (w - 1 - x, h - 1 - y),
+                4 => (x, h - 1 - y),
+                5 => (y, x),
+                6 => (h - 1 - y, x),
+                7 => (h - 1 - y, w - 1 - x),
+                8 => (y, w - 1 - x),
+                _ => (x, y),
+            };
+            let src = (y * w + x) * 4;
+            let dst = (ny * out_w + nx) * 4;
+            out[dst..dst + 4].copy_from_slice(&bytes[src..src + 4]);
+        }
+    }
+    (out, out_w as i32, out_h as i32)
+}
+
+fn read_exif(path: &Path) -> Option<Exif> {
+    let file = std::fs::File::open(path).ok()?;
+    exif::Reader::new()
+        .read_from_container(&mut std::io::BufReader::new(file))
+        .ok()
+}
+
+fn orientation_of(exif: &Exif) -> Option<u32> {
+    exif.get_field(Tag::Orientation, In::PRIMARY)?
+        .value
+        .get_uint(0)
+        .filter(|o| (1..=8).contains(o))
+}
+
+fn exif_details(exif: &Exif) -> Vec<(String, String)> {
+    let mut details = Vec::new();
+    let make = ascii_field(exif, Tag::Make);
+    let model = ascii_field(exif, Tag::Model);
+    let camera = match (make, model) {
+        // Models usually repeat the make, as in "Canon" / "Canon EOS R5"
+        (Some(make), Some(model)) if model.starts_with(&make) => Some(model),
+        (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+        (make, model) => make.or(model),
+    };
+    if let Some(camera) = camera {
+        details.push(("Camera".to_string(), camera));
+    }
+    let taken = [Tag::DateTimeOriginal, Tag::DateTime]
+        .into_iter()
+        .find_map(|tag| exif.get_field(tag, In::PRIMARY));
+    if let Some(taken) = taken {
+        details.push(("Taken".to_string(), taken.display_value().to_string()));
+    }
+    if let Some(label) = orientation_of(exif).and_then(orientation_label) {
+        details.push(("Orientation".to_string(), label.to_string()));
+    }
+    if let (Some(lat), Some(lon)) = (
+        gps_coordinate(exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, "S"),
+        gps_coordinate(exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, "W"),
+    ) {
+        details.push(("GPS".to_string(), format!("{:.5}, {:.5}", lat, lon)));
+    }
+    details
+}
+
+fn ascii_field(exif: &Exif, tag: Tag) -> Option<String> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Ascii(parts) => {
+            let text = String::from_utf8_lossy(parts.first()?);
+            let text = text.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+            (!text.is_empty()).then(|| text.to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Degrees, minutes and seconds as signed decimal degrees
+fn gps_coordinate(exif: &Exif, tag: Tag, ref_tag: Tag, negative_ref: &str) -> Option<f64> {
+    let Value::Rational(parts) = &exif.get_field(tag, In::PRIMARY)?.value else {
+        return None;
+    };
+    let degrees = parts
+        .iter()
+        .zip([1.0, 60.0, 3600.0])
+        .map(|(part, divisor)| part.to_f64() / divisor)
+        .sum::<f64>();
+    let negative = ascii_field(exif, ref_tag).is_some_and(|r| r == negative_ref);
+    degrees
+        .is_finite()
+        .then_some(if negative { -degrees } else { degrees })
+}
+
+fn orientation_label(orientation: u32) -> Option<&'static str> {
+    match orientation {
+        2 => Some("Mirrored"),
+        3 => Some("Rotated 180°"),
+        4 => Some("Mirrored vertically"),
+        5 => Some("Mirrored, rotated 90° counter-clockwise"),
+        6 => Some("Rotated 90° clockwise"),
+        7 => Some("Mirrored, rotated 90° clockwise"),
+        8 => Some("Rotated 90° counter-clockwise"),
+        _ => None,
+    }
+}
+
+/// "image/jpeg" as "JPEG", "image/svg+xml" as "SVG"
+fn format_name(mime: &str) -> Option<String> {
+    let (kind, subtype) = mime.split_once('/')?;
+    if kind != "image" {
+        return None;
+    }
+    let subtype = subtype.trim_start_matches("x-");
+    let subtype = subtype.split('+').next().unwrap_or(subtype);
+    Some(subtype.to_uppercase())
+}
+
+fn format_time(time: std::time::SystemTime) -> Option<String> {
+    let secs = time.duration_since(std::time::UNIX_EPOCH).ok()?.as_secs();
+    let local = glib::DateTime::from_unix_local(secs as i64).ok()?;
+    local.format("%Y-%m-%d %H:%M").ok().map(|s| s.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exif::{Field, Rational};
+
+    fn exif_from(fields: &[Field]) -> Exif {
+        let mut writer = exif::experimental::Writer::new();
+        for field in fields {
+            writer.push_field(field);
+        }
+        let mut buf = std::io::Cursor::new(Vec::new());
+        writer.write(&mut buf, false).unwrap();
+        exif::Reader::new().read_raw(buf.into_inner()).unwrap()
+    }
+
+    fn ascii(tag: Tag, text: &str) -> Field {
+        Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![text.as_bytes().to_vec()]),
+        }
+    }
+
+    fn dms(tag: Tag, d: u32, m: u32, s: u32) -> Field {
+        let r = |n| Rational { num: n, denom: 1 };
+        Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Rational(vec![r(d), r(m), r(s)]),
+        }
+    }
+
+    #[test]
+    fn camera_date_orientation_and_gps() {
+        let exif = exif_from(&[
+            ascii(Tag::Make, "Canon"),
+            ascii(Tag::Model, "Canon EOS R5"),
+            ascii(Tag::DateTimeOriginal, "2024:05:01 12:34:56"),
+            Field {
+                tag: Tag::Orientation,
+                ifd_num: In::PRIMARY,
+                value: Value::Short(vec![6]),
+            },
+            dms(Tag::GPSLatitude, 48, 51, 30),
+            ascii(Tag::GPSLatitudeRef, "N"),
+            dms(Tag::GPSLongitude, 2, 17, 40),
+            ascii(Tag::GPSLongitudeRef, "W"),
+        ]);
+        let details = exif_details(&exif);
+        let get = |label: &str| {
+            details
+                .iter()
+                .find(|(l, _)| l == label)
+                .map(|(_, v)| v.as_str())
+        };
+        assert_eq!(get("Camera"), Some("Canon EOS R5"));
+        assert_eq!(get("Taken"), Some("2024-05-01 12:34:56"));
+        assert_eq!(get("Orientation"), Some("Rotated 90° clockwise"));
+        assert_eq!(get("GPS"), Some("48.85833, -2.29444"));
+    }
+
+    #[test]
+    fn orientation_transforms() {
+        // 2×1 image: red, green
+        let px = [255, 0, 0, 255, 0, 255, 0, 255];
+        let (red, green) = (&px[..4], &px[4..]);
+        let (out, w, h) = orient_rgba(&px, 2, 1, 6);
+        assert_eq!((w, h), (1, 2));
+        assert_eq!((&out[..4], &out[4..]), (red, green));
+        let (out, w, h) = orient_rgba(&px, 2, 1, 8);
+        assert_eq!((w, h), (1, 2));
+        assert_eq!((&out[..4], &out[4..]), (green, red));
+        let (out, _, _) = orient_rgba(&px, 2, 1, 2);
+        assert_eq!((&out[..4], &out[4..]), (green, red));
+        assert_eq!(orient_rgba(&px, 2, 1, 1).0, px);
+    }
+
+    #[test]
+    fn image_formats_named_from_mime() {
+        assert_eq!(format_name("image/jpeg").as_deref(), Some("JPEG"));
+        assert_eq!(format_name("image/svg+xml").as_deref(), Some("SVG"));
+        assert_eq!(format_name("image/x-icon").as_deref(), Some("ICON"));
+        assert_eq!(format_name("text/plain"), None);
+    }
+
+    /// A JPEG holding nothing but an EXIF block with the given orientation
+    fn jpeg_with_orientation(orientation: u8) -> Vec<u8> {
+        let mut tiff = b"II*\0\x08\0\0\0\x01\0".to_vec();
+        tiff.extend_from_slice(&[0x12, 0x01, 3, 0, 1, 0, 0, 0, orientation, 0, 0, 0]);
+        tiff.extend_from_slice(&[0, 0, 0, 0]);
+        let mut app1 = b"Exif\0\0".to_vec();
+        app1.extend_from_slice(&tiff);
+        let mut out = vec![0xff, 0xd8, 0xff, 0xe1];
+        out.extend_from_slice(&(app1.len() as u16 + 2).to_be_bytes());
+        out.extend_from_slice(&app1);
+        out.extend_from_slice(&[0xff, 0xd9]);
+        out
+    }
+
+    #[test]
+    fn only_unrotated_formats_are_oriented() {
+        let dir = tempfile::tempdir().unwrap();
+        let jpeg = dir.path().join("photo.jpg");
+        std::fs::write(&jpeg, jpeg_with_orientation(6)).unwrap();
+        assert_eq!(exif_orientation(&jpeg), Some(6));
+        assert_eq!(decode_orientation(&jpeg), 6);
+
+        // libheif turns HEIC and AVIF upright itself
+        let heic = dir.path().join("photo.heic");
+        let mut bytes = b"\0\0\0\x18ftypheic\0\0\0\0mif1heic".to_vec();
+        bytes.extend_from_slice(&jpeg_with_orientation(6));
+        std::fs::write(&heic, bytes).unwrap();
+        assert_eq!(decode_orientation(&heic), 1);
+    }
+
+    #[test]
+    fn plain_files_have_no_exif() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("img.png");
+        std::fs::write(&path, b"\x89PNG\r\n\x1a\n").unwrap();
+        assert_eq!(exif_orientation(&path), None);
+        let details = image_details(&path, Some((640, 480)));
+        assert_eq!(
+            details[0],
+            ("Dimensions".to_string(), "640×480".to_string())
+        );
+        assert!(details.contains(&("Format".to_string(), "PNG".to_string())));
+    }
+}
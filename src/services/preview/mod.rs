@@ -1,37 +1,119 @@
+pub mod animation;
+pub mod audio;
 pub mod decoder;
 pub mod detector;
+pub mod highlight;
+pub mod listing;
+pub mod markdown;
 pub mod mem_cache;
+pub mod metadata;
+pub mod provider;
+pub mod raw;
 pub mod video;
 
-use crate::cache::{CacheAdapter, CacheManager};
+use crate::cache::{CacheAdapter, CacheManager, FreedesktopThumbnails};
+use crate::config::CacheConfig;
 use crate::domain::item::Item;
+use crate::services::jobs::CancelToken;
 use crate::services::process::CommandExecutor;
 use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 
 pub use decoder::{GdkPixbufDecoder, ImageDecoder};
-use mem_cache::MemoryCache;
+use mem_cache::{MemKey, MemoryCache};
+use provider::{Provider, ProviderRegistry};
 
 #[derive(Debug, Clone)]
 pub enum PreviewPayload {
     Text(String),
+    /// Markdown source, laid out by the preview pane
+    Markdown(String),
     Image {
         bytes: Arc<Vec<u8>>,
         width: i32,
         height: i32,
     },
+    /// Frames share one size and are played in a loop
+    Animation {
+        frames: Arc<Vec<AnimationFrame>>,
+        width: i32,
+        height: i32,
+    },
+    /// Tree of a directory or archive, with an archive's first image as its cover
+    Listing {
+        tree: String,
+        cover: Option<(Arc<Vec<u8>>, i32, i32)>,
+    },
     Error(String),
 }
 
+#[derive(Debug, Clone)]
+pub struct AnimationFrame {
+    /// Tightly packed RGBA
+    pub pixels: Arc<[u8]>,
+    /// How long the frame stays on screen
+    pub delay: Duration,
+}
+
+/// Bounding box (in device pixels) that images are decoded into
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PreviewSize {
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Default for PreviewSize {
+    fn default() -> Self {
+        Self {
+            width: crate::constants::IMAGE_PREVIEW_WIDTH,
+            height: crate::constants::IMAGE_PREVIEW_HEIGHT,
+        }
+    }
+}
+
+impl PreviewSize {
+    /// Round a pane allocation up to the next bucket so small resizes reuse cached decodes.
+    /// Panes no larger than the default decode at the default size.
+    pub fn bucketed(width: i32, height: i32) -> Self {
+        let bucket = crate::constants::PREVIEW_SIZE_BUCKET;
+        let max = crate::constants::MAX_PREVIEW_DECODE_SIZE;
+        let round_up = |v: i32, min: i32| {
+            if v <= min {
+                return min;
+            }
+            ((v + bucket - 1) / bucket * bucket).min(max)
+        };
+        let default = Self::default();
+        Self {
+            width: round_up(width, default.width),
+            height: round_up(height, default.height),
+        }
+    }
+
+    pub fn covers(&self, other: &PreviewSize) -> bool {
+        self.width >= other.width && self.height >= other.height
+    }
+}
+
 pub type ProdPreviewService =
     PreviewService<CacheManager, crate::services::process::ShellExec, GdkPixbufDecoder>;
 
-pub fn create_prod_preview_service() -> ProdPreviewService {
-    ProdPreviewService::new(
-        CacheManager::new(),
+pub fn create_prod_preview_service(cache_config: &CacheConfig) -> ProdPreviewService {
+    let service = ProdPreviewService::new(
+        CacheManager::new(cache_config),
         crate::services::process::ShellExec,
         GdkPixbufDecoder,
     )
+    .with_memory_budget(cache_config.memory_size);
+    if cache_config.shared_thumbnails || cache_config.write_thumbnails {
+        service.with_shared_cache(Arc::new(FreedesktopThumbnails::new(
+            cache_config.shared_thumbnails,
+            cache_config.write_thumbnails,
+        )))
+    } else {
+        service
+    }
 }
 
 #[derive(Clone)]
@@ -44,6 +126,10 @@ pub struct PreviewService<
     executor: E,
     decoder: D,
     mem_cache: MemoryCache,
+    shared: Option<Arc<dyn CacheAdapter>>,
+    providers: Arc<ProviderRegistry>,
+    listing_depth: usize,
+    video_probes: Arc<video::ProbeCache>,
 }
 
 impl<C: CacheAdapter + Clone, E: CommandExecutor + Clone, D: ImageDecoder + Clone>
@@ -54,17 +140,97 @@ impl<C: CacheAdapter + Clone, E: CommandExecutor + Clone, D: ImageDecoder + Clon
             cache,
             executor,
             decoder,
-            mem_cache: MemoryCache::new(crate::constants::MEM_CACHE_MAX_SIZE),
+            mem_cache: MemoryCache::new(crate::constants::DEFAULT_MEM_CACHE_MAX_BYTES as usize),
+            shared: None,
+            providers: Arc::default(),
+            listing_depth: crate::constants::DEFAULT_LISTING_DEPTH,
+            video_probes: Arc::default(),
+        }
+    }
+
+    /// Bound the in-memory payload cache to `bytes`
+    pub fn with_memory_budget(mut self, bytes: u64) -> Self {
+        self.mem_cache = MemoryCache::new(usize::try_from(bytes).unwrap_or(usize::MAX));
+        self
+    }
+
+    /// Preview files through the `[providers]` rules before falling back to the display mode
+    pub fn with_providers(mut self, providers: ProviderRegistry) -> Self {
+        self.providers = Arc::new(providers);
+        self
+    }
+
+    /// Levels of a directory or archive drawn in its tree preview
+    pub fn with_listing_depth(mut self, depth: usize) -> Self {
+        self.listing_depth = depth;
+        self
+    }
+
+    /// Consult a cache owned by other applications before decoding
+    pub fn with_shared_cache(mut self, shared: Arc<dyn CacheAdapter>) -> Self {
+        self.shared = Some(shared);
+        self
+    }
+
+    /// A valid shared thumbnail and whether it fills the requested box on one axis; only
+    /// those that do can stand in for a decode
+    fn load_shared_cache(&self, path: &Path, size: PreviewSize) -> Option<(PreviewPayload, bool)> {
+        let shared = self.shared.as_ref()?;
+        let shared_path = shared.get_cache_path("", path, size.width, size.height);
+        if !shared.is_cache_valid(&shared_path, path) {
+            return None;
         }
+        let (bytes, w, h) = shared.load_raw_cache(&shared_path)?;
+        let fills = w >= size.width || h >= size.height;
+        let payload = PreviewPayload::Image {
+            bytes: Arc::new(bytes),
+            width: w,
+            height: h,
+        };
+        Some((payload, fills))
     }
 
-    fn load_valid_cache(&self, category: &str, path: &Path) -> Option<PreviewPayload> {
+    /// A shared thumbnail too small to be the preview, shown while the image is decoded.
+    /// File managers mostly write the smaller flavors, so this is often all there is.
+    pub fn resolve_first_paint(&self, item: &Item, size: PreviewSize) -> Option<PreviewPayload> {
+        let path = crate::utils::expand_tilde(&item.value);
+        if !self.shows_image(item) || video::is_video(&path) || audio::is_audio(&path) {
+            return None;
+        }
+        match self.load_shared_cache(&path, size)? {
+            (payload, false) => Some(payload),
+            (_, true) => None,
+        }
+    }
+
+    fn save_shared_cache(&self, path: &Path, size: PreviewSize, payload: &PreviewPayload) {
+        let (
+            Some(shared),
+            PreviewPayload::Image {
+                bytes,
+                width,
+                height,
+            },
+        ) = (self.shared.as_ref(), payload)
+        else {
+            return;
+        };
+        let shared_path = shared.get_cache_path("", path, size.width, size.height);
+        if let Err(e) = shared.save_raw_cache(&shared_path, path, bytes, *width, *height) {
+            log::debug!(
+                "Failed to write shared thumbnail for {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+
+    fn load_valid_cache(&self, cache_path: &Path, path: &Path) -> Option<PreviewPayload> {
         if !path.exists() || !path.is_file() {
             return None;
         }
-        let cache_path = self.cache.get_cache_path(category, path);
-        if self.cache.is_cache_valid(&cache_path, path)
-            && let Some((bytes, w, h)) = self.cache.load_raw_cache(&cache_path)
+        if self.cache.is_cache_valid(cache_path, path)
+            && let Some((bytes, w, h)) = self.cache.load_raw_cache(cache_path)
         {
             return Some(PreviewPayload::Image {
                 bytes: Arc::new(bytes),
@@ -75,147 +241,547 @@ impl<C: CacheAdapter + Clone, E: CommandExecutor + Clone, D: ImageDecoder + Clon
         None
     }
 
-    pub fn try_cache(&self, item: &Item) -> Option<PreviewPayload> {
-        if item.preview_template.is_some()
-            || matches!(item.source, crate::domain::SourceMode::Dynamic)
-        {
+    /// The `[providers]` rule for a plain item naming an existing file
+    fn provider_for(&self, item: &Item) -> Option<&Provider> {
+        if self.providers.is_empty() || is_command_preview(item) {
             return None;
         }
-        if !matches!(item.display, crate::domain::DisplayMode::Picture) {
+        let path = crate::utils::expand_tilde(&item.value);
+        if !path.is_file() {
+            return None;
+        }
+        self.providers.lookup(&path)
+    }
+
+    /// Whether the item is previewed as an image file, by display mode or provider
+    fn shows_image(&self, item: &Item) -> bool {
+        match self.provider_for(item) {
+            Some(provider) => *provider == Provider::Image,
+            None => {
+                !is_command_preview(item)
+                    && matches!(item.display, crate::domain::DisplayMode::Picture)
+            }
+        }
+    }
+
+    pub fn try_cache(&self, item: &Item, size: PreviewSize) -> Option<PreviewPayload> {
+        // Plain text needs no work, so it is shown without a loading placeholder
+        if !is_command_preview(item)
+            && matches!(item.display, crate::domain::DisplayMode::Text)
+            && self.provider_for(item).is_none()
+        {
+            // Markdown files, directories and archives are read on a worker, which also
+            // sniffs extensionless files; only values that look like paths are checked
+            if item.value.starts_with('/') || item.value.starts_with('~') {
+                let path = crate::utils::expand_tilde(&item.value);
+                if path.is_dir()
+                    || (path.is_file() && (is_markdown(item) || listing::may_be_archive(&path)))
+                {
+                    return None;
+                }
+            }
+            return Some(as_markdown(item, PreviewPayload::Text(item.value.clone())));
+        }
+        if !self.shows_image(item) {
             return None;
         }
         let expanded_path = crate::utils::expand_tilde(&item.value);
-        if video::is_video(&expanded_path) {
+        if video::is_video(&expanded_path) || audio::is_audio(&expanded_path) {
             return None;
         }
 
-        if let Some(payload) = self.mem_cache.get(&expanded_path) {
+        let cache_path =
+            self.cache
+                .fast_cache_path(&item.category, &expanded_path, size.width, size.height)?;
+        let mem_key = MemKey::File(cache_path.clone());
+
+        if let Some(payload) = self.mem_cache.get(&mem_key) {
             return Some(payload);
         }
 
-        if let Some(payload) = self.load_valid_cache(&item.category, &expanded_path) {
-            self.mem_cache.insert(expanded_path, payload.clone());
+        if let Some(payload) = self.load_valid_cache(&cache_path, &expanded_path) {
+            self.mem_cache.insert(mem_key, payload.clone());
             return Some(payload);
         }
         None
     }
 
-    pub fn resolve_payload(&self, item: &Item) -> PreviewPayload {
-        if item.preview_template.is_some()
-            || matches!(item.source, crate::domain::SourceMode::Dynamic)
-        {
-            return self.resolve_dynamic(item);
+    /// Fill the memory and disk caches for an item that may be selected soon.
+    /// Only plain image files are prefetched; commands, ffmpeg and ffprobe are never run
+    /// speculatively, so videos and audio wait until they are selected.
+    pub fn prefetch(&self, item: &Item, size: PreviewSize, cancel: &CancelToken) {
+        let path = crate::utils::expand_tilde(&item.value);
+        if !self.shows_image(item) || video::is_video(&path) || audio::is_audio(&path) {
+            return;
+        }
+        let _ = self.resolve_image(item, size, cancel);
+    }
+
+    /// Whether the item is an image file that `resolve_full_resolution` can decode
+    pub fn has_full_resolution(&self, item: &Item) -> bool {
+        let path = crate::utils::expand_tilde(&item.value);
+        self.shows_image(item)
+            && path.is_file()
+            && !video::is_video(&path)
+            && !audio::is_audio(&path)
+    }
+
+    /// Decode an image at its own size, up to `MAX_FULL_RESOLUTION_SIZE`, for zooming in.
+    /// Kept in memory only, and only when it takes at most a quarter of the memory budget,
+    /// so one zoomed photo can't push out every preview; the disk caches hold preview-sized
+    /// decodes.
+    pub fn resolve_full_resolution(
+        &self,
+        item: &Item,
+        cancel: &CancelToken,
+    ) -> Option<PreviewPayload> {
+        if !self.has_full_resolution(item) {
+            return None;
+        }
+        let path = crate::utils::expand_tilde(&item.value);
+        let (width, height) = self.decoder.dimensions(&path)?;
+        let limit = crate::constants::MAX_FULL_RESOLUTION_SIZE;
+        let size = PreviewSize {
+            width: width.min(limit),
+            height: height.min(limit),
+        };
+        let mem_key =
+            MemKey::File(
+                self.cache
+                    .get_cache_path(&item.category, &path, size.width, size.height),
+            );
+        if let Some(payload) = self.mem_cache.get(&mem_key) {
+            return Some(payload);
+        }
+
+        let (bytes, w, h) =
+            self.decoder
+                .load_cancellable(&path, size.width, size.height, cancel)?;
+        let cacheable = bytes.len() <= self.mem_cache.max_bytes() / 4;
+        let payload = PreviewPayload::Image {
+            bytes: Arc::new(bytes),
+            width: w,
+            height: h,
+        };
+        if cacheable {
+            self.mem_cache.insert(mem_key, payload.clone());
+        }
+        Some(payload)
+    }
+
+    /// Whether `media_details` has anything to add for this item
+    pub fn has_media_details(&self, item: &Item) -> bool {
+        self.shows_image(item) && crate::utils::expand_tilde(&item.value).is_file()
+    }
+
+    /// Label/value pairs describing the media behind an item: a video's duration,
+    /// a track's tags, or an image's dimensions and EXIF fields
+    pub fn media_details(&self, item: &Item, cancel: &CancelToken) -> Vec<(String, String)> {
+        if !self.has_media_details(item) {
+            return Vec::new();
+        }
+        let path = crate::utils::expand_tilde(&item.value);
+        if video::is_video(&path) {
+            return self
+                .video_probes
+                .probe(&path, &self.executor, cancel)
+                .map(|info| info.details())
+                .unwrap_or_default();
+        }
+        if audio::is_audio(&path) {
+            return audio::probe(&path, &self.executor, cancel)
+                .map(|info| info.details())
+                .unwrap_or_default();
+        }
+        metadata::image_details(&path, self.decoder.dimensions(&path))
+    }
+
+    pub fn resolve_payload(&self, item: &Item, size: PreviewSize) -> PreviewPayload {
+        self.resolve_cancellable(item, size, &CancelToken::never())
+    }
+
+    /// Resolve a payload, abandoning commands and decodes once `cancel` fires
+    pub fn resolve_cancellable(
+        &self,
+        item: &Item,
+        size: PreviewSize,
+        cancel: &CancelToken,
+    ) -> PreviewPayload {
+        if is_command_preview(item) {
+            return as_requested_markdown(item, self.resolve_dynamic(item, size, cancel));
+        }
+
+        if let Some(provider) = self.provider_for(item) {
+            let path = crate::utils::expand_tilde(&item.value);
+            return match provider {
+                Provider::Image => self.resolve_image(item, size, cancel),
+                Provider::Text => as_markdown(item, read_text_preview(&path)),
+                Provider::Command(template) => {
+                    let command = template.replace(
+                        "{}",
+                        &crate::utils::escape_shell_arg(&path.to_string_lossy()),
+                    );
+                    let mem_key = MemKey::Provider {
+                        command: command.clone(),
+                        size,
+                        source: crate::cache::format::source_fingerprint(&path),
+                    };
+                    let output = self.run_preview_command(item, &command, mem_key, size, cancel);
+                    as_requested_markdown(item, output)
+                }
+            };
+        }
+
+        let path = crate::utils::expand_tilde(&item.value);
+        if listing::is_listable(&path) {
+            return self.resolve_listing(&path, size, cancel);
         }
 
         match item.display {
-            crate::domain::DisplayMode::Text => PreviewPayload::Text(item.value.clone()),
-            crate::domain::DisplayMode::Picture => self.resolve_image(item),
+            crate::domain::DisplayMode::Text => {
+                if is_markdown(item) && path.is_file() {
+                    as_markdown(item, read_text_preview(&path))
+                } else {
+                    as_markdown(item, PreviewPayload::Text(item.value.clone()))
+                }
+            }
+            crate::domain::DisplayMode::Picture => self.resolve_image(item, size, cancel),
         }
     }
 
-    fn resolve_image(&self, item: &Item) -> PreviewPayload {
+    fn resolve_image(
+        &self,
+        item: &Item,
+        size: PreviewSize,
+        cancel: &CancelToken,
+    ) -> PreviewPayload {
         let expanded_path = crate::utils::expand_tilde(&item.value);
         if !expanded_path.exists() || !expanded_path.is_file() {
             return PreviewPayload::Text(item.value.clone());
         }
 
-        if let Some(payload) = self.mem_cache.get(&expanded_path) {
+        let cache_path =
+            self.cache
+                .get_cache_path(&item.category, &expanded_path, size.width, size.height);
+        let mem_key = MemKey::File(cache_path.clone());
+
+        if let Some(payload) = self.mem_cache.get(&mem_key) {
             return payload;
         }
 
-        if let Some(payload) = self.load_valid_cache(&item.category, &expanded_path) {
-            self.mem_cache
-                .insert(expanded_path.clone(), payload.clone());
+        if let Some(payload) = self.load_valid_cache(&cache_path, &expanded_path) {
+            self.mem_cache.insert(mem_key, payload.clone());
+            return payload;
+        }
+
+        if cancel.is_cancelled() {
+            return cancelled();
+        }
+
+        // Animations skip the disk and shared caches, which only hold a single frame
+        if let Some((frames, width, height)) =
+            self.decoder
+                .load_animation(&expanded_path, size.width, size.height, cancel)
+        {
+            let payload = PreviewPayload::Animation {
+                frames: Arc::new(frames),
+                width,
+                height,
+            };
+            self.mem_cache.insert(mem_key, payload.clone());
             return payload;
         }
 
-        let cache_path = self.cache.get_cache_path(&item.category, &expanded_path);
+        // Shared thumbnails of videos are a single frame, not our storyboard,
+        // and those of audio files lack the waveform
+        let is_video = video::is_video(&expanded_path);
+        let is_audio = audio::is_audio(&expanded_path);
+        let rendered = is_video || is_audio;
+        if !rendered && let Some((payload, true)) = self.load_shared_cache(&expanded_path, size) {
+            self.mem_cache.insert(mem_key, payload.clone());
+            return payload;
+        }
 
-        let payload = if video::is_video(&expanded_path) {
-            video::generate_thumbnail(
+        let payload = if is_video {
+            let info = self
+                .video_probes
+                .probe(&expanded_path, &self.executor, cancel)
+                .unwrap_or_default();
+            let payload = video::generate_thumbnail(
+                &expanded_path,
+                &info,
+                size,
+                &self.decoder,
+                &self.executor,
+                cancel,
+            );
+            if let PreviewPayload::Image {
+                bytes,
+                width,
+                height,
+            } = &payload
+            {
+                let _ =
+                    self.cache
+                        .save_raw_cache(&cache_path, &expanded_path, bytes, *width, *height);
+            }
+            payload
+        } else if is_audio {
+            audio::generate_preview(
                 &expanded_path,
                 &cache_path,
+                size,
                 &self.cache,
                 &self.decoder,
                 &self.executor,
+                cancel,
             )
-        } else {
-            let max_w = crate::constants::IMAGE_PREVIEW_WIDTH;
-            let max_h = crate::constants::IMAGE_PREVIEW_HEIGHT;
-
-            if let Some((bytes, w, h)) = self.decoder.load_from_path(&expanded_path, max_w, max_h) {
-                let _ = self.cache.save_raw_cache(&cache_path, &bytes, w, h);
-                PreviewPayload::Image {
-                    bytes: Arc::new(bytes),
-                    width: w,
-                    height: h,
-                }
-            } else {
-                PreviewPayload::Error("Failed to decode image".to_string())
+        } else if let Some((bytes, w, h)) =
+            self.decoder
+                .load_cancellable(&expanded_path, size.width, size.height, cancel)
+        {
+            let _ = self
+                .cache
+                .save_raw_cache(&cache_path, &expanded_path, &bytes, w, h);
+            PreviewPayload::Image {
+                bytes: Arc::new(bytes),
+                width: w,
+                height: h,
             }
+        } else if cancel.is_cancelled() {
+            cancelled()
+        } else if let Some(reason) = self.decoder.decode_error(&expanded_path) {
+            PreviewPayload::Error(reason)
+        } else {
+            PreviewPayload::Error("Failed to decode image".to_string())
         };
 
         if let PreviewPayload::Image { .. } = &payload {
-            self.mem_cache.insert(expanded_path, payload.clone());
+            if !rendered {
+                self.save_shared_cache(&expanded_path, size, &payload);
+            }
+            self.mem_cache.insert(mem_key, payload.clone());
         }
 
         payload
     }
 
-    fn resolve_dynamic(&self, item: &Item) -> PreviewPayload {
-        let safe_value = crate::utils::escape_shell_arg(&item.value);
+    fn resolve_listing(
+        &self,
+        path: &Path,
+        size: PreviewSize,
+        cancel: &CancelToken,
+    ) -> PreviewPayload {
+        let depth = self.listing_depth;
+        if path.is_dir() {
+            let listing = listing::list_directory(path, cancel);
+            if cancel.is_cancelled() {
+                return cancelled();
+            }
+            return PreviewPayload::Listing {
+                tree: listing::render_tree(&listing, depth),
+                cover: None,
+            };
+        }
+
+        let mem_key = MemKey::Archive {
+            path: path.to_path_buf(),
+            size,
+            source: crate::cache::format::source_fingerprint(path),
+        };
+        if let Some(payload) = self.mem_cache.get(&mem_key) {
+            return payload;
+        }
+        let listing = match listing::list_archive(path, &self.executor, cancel) {
+            Ok(listing) => listing,
+            Err(_) if cancel.is_cancelled() => return cancelled(),
+            Err(reason) => return PreviewPayload::Error(reason),
+        };
+        let cover = listing::first_image(&listing.0)
+            .and_then(|member| self.decode_archive_member(path, member, size, cancel));
+        if cancel.is_cancelled() {
+            return cancelled();
+        }
+        let payload = PreviewPayload::Listing {
+            tree: listing::render_tree(&listing, depth),
+            cover,
+        };
+        self.mem_cache.insert(mem_key, payload.clone());
+        payload
+    }
+
+    /// Decode an image inside an archive into the top half of the preview
+    fn decode_archive_member(
+        &self,
+        archive: &Path,
+        member: &str,
+        size: PreviewSize,
+        cancel: &CancelToken,
+    ) -> Option<(Arc<Vec<u8>>, i32, i32)> {
+        use std::io::Write;
+
+        let bytes = listing::extract(archive, member, &self.executor, cancel)?;
+        let mut temp_file = tempfile::NamedTempFile::new().ok()?;
+        temp_file.write_all(&bytes).ok()?;
+        let (pixels, w, h) =
+            self.decoder
+                .load_cancellable(temp_file.path(), size.width, size.height / 2, cancel)?;
+        Some((Arc::new(pixels), w, h))
+    }
+
+    fn resolve_dynamic(
+        &self,
+        item: &Item,
+        size: PreviewSize,
+        cancel: &CancelToken,
+    ) -> PreviewPayload {
+        // Values like "~/notes/todo.md" name files, which the shell won't expand once quoted
+        let value = crate::utils::expand_tilde(&item.value);
+        let safe_value = crate::utils::escape_shell_arg(&value.to_string_lossy());
+        let line = item.line.unwrap_or(1).to_string();
 
         let preview_cmd = if let Some(ref template) = item.preview_template {
-            template.replace("{}", &safe_value)
+            template.replace("{line}", &line).replace("{}", &safe_value)
         } else {
             crate::constants::DEFAULT_CLIPBOARD_CMD.replace("{}", &safe_value)
         };
 
-        match self.executor.execute("sh", &["-c", &preview_cmd]) {
-            Ok(output) if output.success => {
-                if detector::is_binary(&output.stdout) {
-                    use std::io::Write;
-                    use tempfile::NamedTempFile;
+        let mem_key = MemKey::Command {
+            command: preview_cmd.clone(),
+            size,
+        };
+        self.run_preview_command(item, &preview_cmd, mem_key, size, cancel)
+    }
+
+    /// Run a preview command, showing text output as text and binary output as an image
+    fn run_preview_command(
+        &self,
+        item: &Item,
+        preview_cmd: &str,
+        mem_key: MemKey,
+        size: PreviewSize,
+        cancel: &CancelToken,
+    ) -> PreviewPayload {
+        if let Some(payload) = self.mem_cache.get(&mem_key) {
+            return payload;
+        }
 
-                    let mut temp_file = match NamedTempFile::new() {
-                        Ok(tf) => tf,
-                        Err(e) => {
-                            return PreviewPayload::Error(format!("Tempfile error: {}", e));
-                        }
-                    };
+        let timeout = crate::constants::DYNAMIC_PREVIEW_TIMEOUT_SECS;
+        let payload =
+            match self
+                .executor
+                .execute_cancellable("sh", &["-c", preview_cmd], timeout, cancel)
+            {
+                Ok(output) if output.success => {
+                    if detector::is_binary(&output.stdout) {
+                        use std::io::Write;
+                        use tempfile::NamedTempFile;
 
-                    if temp_file.write_all(&output.stdout).is_ok() {
-                        let max_w = crate::constants::IMAGE_PREVIEW_WIDTH;
-                        let max_h = crate::constants::IMAGE_PREVIEW_HEIGHT;
-                        if let Some((bytes, w, h)) =
-                            self.decoder.load_from_path(temp_file.path(), max_w, max_h)
-                        {
-                            PreviewPayload::Image {
-                                bytes: Arc::new(bytes),
-                                width: w,
-                                height: h,
+                        let mut temp_file = match NamedTempFile::new() {
+                            Ok(tf) => tf,
+                            Err(e) => {
+                                return PreviewPayload::Error(format!("Tempfile error: {}", e));
+                            }
+                        };
+
+                        if temp_file.write_all(&output.stdout).is_ok() {
+                            if let Some((bytes, w, h)) = self.decoder.load_cancellable(
+                                temp_file.path(),
+                                size.width,
+                                size.height,
+                                cancel,
+                            ) {
+                                PreviewPayload::Image {
+                                    bytes: Arc::new(bytes),
+                                    width: w,
+                                    height: h,
+                                }
+                            } else {
+                                PreviewPayload::Error(
+                                    "Failed to decode dynamic binary image".to_string(),
+                                )
                             }
                         } else {
-                            PreviewPayload::Error(
-                                "Failed to decode dynamic binary image".to_string(),
-                            )
+                            PreviewPayload::Error("Failed to write dynamic temp file".to_string())
                         }
                     } else {
-                        PreviewPayload::Error("Failed to write dynamic temp file".to_string())
+                        PreviewPayload::Text(String::from_utf8_lossy(&output.stdout).to_string())
                     }
-                } else {
-                    PreviewPayload::Text(String::from_utf8_lossy(&output.stdout).to_string())
                 }
-            }
-            _ => PreviewPayload::Text(item.value.clone()),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => return cancelled(),
+                // Not cached, so a failing command is retried next time
+                _ => return PreviewPayload::Text(item.value.clone()),
+            };
+
+        if !matches!(payload, PreviewPayload::Error(_)) {
+            self.mem_cache.insert(mem_key, payload.clone());
         }
+        payload
+    }
+}
+
+/// Items whose preview comes from a command rather than the value itself
+fn is_command_preview(item: &Item) -> bool {
+    item.preview_template.is_some() || matches!(item.source, crate::domain::SourceMode::Dynamic)
+}
+
+/// Whether the item's own text, its value or the file it names, is rendered as Markdown
+fn is_markdown(item: &Item) -> bool {
+    match item.preview_format {
+        Some(format) => format == crate::domain::PreviewFormat::Markdown,
+        None => Path::new(&item.value)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| {
+                crate::constants::MARKDOWN_EXTENSIONS.contains(&ext.to_lowercase().as_str())
+            }),
+    }
+}
+
+fn as_markdown(item: &Item, payload: PreviewPayload) -> PreviewPayload {
+    match payload {
+        PreviewPayload::Text(text) if is_markdown(item) => PreviewPayload::Markdown(text),
+        payload => payload,
     }
 }
 
+/// Command output says nothing about its format, so it is only Markdown when asked for
+fn as_requested_markdown(item: &Item, payload: PreviewPayload) -> PreviewPayload {
+    match payload {
+        PreviewPayload::Text(text)
+            if item.preview_format == Some(crate::domain::PreviewFormat::Markdown) =>
+        {
+            PreviewPayload::Markdown(text)
+        }
+        payload => payload,
+    }
+}
+
+/// The start of a text file; binary files are refused rather than shown as mojibake
+fn read_text_preview(path: &Path) -> PreviewPayload {
+    use std::io::Read;
+
+    let mut data = Vec::new();
+    let read = std::fs::File::open(path).and_then(|file| {
+        file.take(crate::constants::MAX_TEXT_PREVIEW_BYTES)
+            .read_to_end(&mut data)
+    });
+    match read {
+        Ok(_) if detector::is_binary(&data) => PreviewPayload::Error("Binary file".to_string()),
+        Ok(_) => PreviewPayload::Text(String::from_utf8_lossy(&data).into_owned()),
+        Err(e) => PreviewPayload::Error(format!("Failed to read file: {}", e)),
+    }
+}
+
+/// Placeholder for abandoned work; never cached and discarded by the caller
+fn cancelled() -> PreviewPayload {
+    PreviewPayload::Error("Cancelled".to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::domain::DisplayMode;
+    use crate::domain::{DisplayMode, PreviewFormat};
     use crate::services::process::MockExec;
     use std::collections::HashMap;
     use std::io;
@@ -257,10 +823,18 @@ mod tests {
     }
 
     impl CacheAdapter for MockCache {
-        fn get_cache_path(&self, category: &str, original_path: &Path) -> PathBuf {
+        fn get_cache_path(
+            &self,
+            category: &str,
+            original_path: &Path,
+            max_width: i32,
+            max_height: i32,
+        ) -> PathBuf {
             PathBuf::from(format!(
-                "mock_cache/{}_{}",
+                "mock_cache/{}_{}x{}_{}",
                 category,
+                max_width,
+                max_height,
                 original_path
                     .file_name()
                     .unwrap_or_default()
@@ -275,6 +849,7 @@ mod tests {
         fn save_raw_cache(
             &self,
             path: &Path,
+            _original_path: &Path,
             raw_data: &[u8],
             width: i32,
             height: i32,
@@ -294,11 +869,31 @@ mod tests {
     #[derive(Clone)]
     struct MockDecoder {
         result: Option<(Vec<u8>, i32, i32)>,
+        animation: Option<(Vec<AnimationFrame>, i32, i32)>,
+        decode_error: Option<String>,
     }
 
     impl MockDecoder {
         fn new() -> Self {
-            Self { result: None }
+            Self {
+                result: None,
+                animation: None,
+                decode_error: None,
+            }
+        }
+
+        fn with_decode_error(mut self, reason: &str) -> Self {
+            self.decode_error = Some(reason.to_string());
+            self
+        }
+
+        fn with_animation(mut self, frames: usize, w: i32, h: i32) -> Self {
+            let frame = AnimationFrame {
+                pixels: Arc::from(vec![0u8; (w * h * 4) as usize]),
+                delay: Duration::from_millis(100),
+            };
+            self.animation = Some((vec![frame; frames], w, h));
+            self
         }
 
         fn with_result(mut self, data: Vec<u8>, w: i32, h: i32) -> Self {
@@ -316,6 +911,24 @@ mod tests {
         ) -> Option<(Vec<u8>, i32, i32)> {
             self.result.clone()
         }
+
+        fn dimensions(&self, _path: &Path) -> Option<(i32, i32)> {
+            self.result.as_ref().map(|(_, w, h)| (*w, *h))
+        }
+
+        fn decode_error(&self, _path: &Path) -> Option<String> {
+            self.decode_error.clone()
+        }
+
+        fn load_animation(
+            &self,
+            _path: &Path,
+            _max_width: i32,
+            _max_height: i32,
+            _cancel: &CancelToken,
+        ) -> Option<(Vec<AnimationFrame>, i32, i32)> {
+            self.animation.clone()
+        }
     }
 
     fn text_item(value: &str) -> Item {
@@ -339,18 +952,126 @@ mod tests {
         let svc = PreviewService::new(MockCache::new(), MockExec::new(), MockDecoder::new());
         let item = text_item("hello world");
         assert!(matches!(
-            svc.resolve_payload(&item),
+            svc.resolve_payload(&item, PreviewSize::default()),
             PreviewPayload::Text(ref s) if s == "hello world"
         ));
     }
 
+    #[test]
+    fn plain_text_served_without_worker() {
+        let svc = PreviewService::new(MockCache::new(), MockExec::new(), MockDecoder::new());
+        assert!(matches!(
+            svc.try_cache(&text_item("hello"), PreviewSize::default()),
+            Some(PreviewPayload::Text(text)) if text == "hello"
+        ));
+        let item = text_item("hello").with_preview_template(Some("echo {}".to_string()));
+        assert!(svc.try_cache(&item, PreviewSize::default()).is_none());
+        // Relative values are text even when they happen to name a folder
+        assert!(matches!(
+            svc.try_cache(&text_item("src"), PreviewSize::default()),
+            Some(PreviewPayload::Text(text)) if text == "src"
+        ));
+    }
+
+    #[test]
+    fn extensionless_files_are_sniffed_on_worker() {
+        let dir = tempfile::tempdir().unwrap();
+        let notes = dir.path().join("NOTES");
+        std::fs::write(&notes, "plain words").unwrap();
+        let svc = PreviewService::new(MockCache::new(), MockExec::new(), MockDecoder::new());
+        let item = text_item(notes.to_str().unwrap());
+        assert!(svc.try_cache(&item, PreviewSize::default()).is_none());
+        assert!(matches!(
+            svc.resolve_payload(&item, PreviewSize::default()),
+            PreviewPayload::Text(text) if text == notes.to_str().unwrap()
+        ));
+    }
+
+    #[test]
+    fn full_resolution_for_image_files_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let photo = dir.path().join("photo.png");
+        std::fs::write(&photo, b"png").unwrap();
+        let clip = dir.path().join("clip.mp4");
+        std::fs::write(&clip, b"mp4").unwrap();
+        let decoder = MockDecoder::new().with_result(vec![0; 400], 3000, 2000);
+        let svc = PreviewService::new(MockCache::new(), MockExec::new(), decoder);
+        let cancel = CancelToken::never();
+
+        let item = picture_item(photo.to_str().unwrap());
+        assert!(svc.has_full_resolution(&item));
+        assert!(matches!(
+            svc.resolve_full_resolution(&item, &cancel),
+            Some(PreviewPayload::Image {
+                width: 3000,
+                height: 2000,
+                ..
+            })
+        ));
+        let key = MemKey::File(svc.cache.get_cache_path("cat", &photo, 3000, 2000));
+        assert!(svc.mem_cache.get(&key).is_some());
+        // Fits the budget, but not within a quarter of it
+        let svc = svc.with_memory_budget(1000);
+        assert!(svc.resolve_full_resolution(&item, &cancel).is_some());
+        assert!(svc.mem_cache.get(&key).is_none());
+        assert!(!svc.has_full_resolution(&picture_item(clip.to_str().unwrap())));
+        assert!(!svc.has_full_resolution(&text_item(photo.to_str().unwrap())));
+        assert!(
+            svc.resolve_full_resolution(&picture_item("/nonexistent.png"), &cancel)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn markdown_files_read_on_worker() {
+        let dir = tempfile::tempdir().unwrap();
+        let notes = dir.path().join("todo.MD");
+        std::fs::write(&notes, "# Todo\n- milk\n").unwrap();
+        let svc = PreviewService::new(MockCache::new(), MockExec::new(), MockDecoder::new());
+        let item = text_item(notes.to_str().unwrap());
+        assert!(svc.try_cache(&item, PreviewSize::default()).is_none());
+        assert!(matches!(
+            svc.resolve_payload(&item, PreviewSize::default()),
+            PreviewPayload::Markdown(text) if text == "# Todo\n- milk\n"
+        ));
+
+        let plain = item.with_preview_format(Some(PreviewFormat::Text));
+        assert!(matches!(
+            svc.try_cache(&plain, PreviewSize::default()),
+            Some(PreviewPayload::Text(text)) if text == notes.to_str().unwrap()
+        ));
+    }
+
+    #[test]
+    fn markdown_format_applies_to_command_output() {
+        let exec = MockExec::new().push_ok(true, b"**bold**".to_vec());
+        let svc = PreviewService::new(MockCache::new(), exec, MockDecoder::new());
+        let item = dynamic_item_with_template("note", "cat {}")
+            .with_preview_format(Some(PreviewFormat::Markdown));
+        assert!(matches!(
+            svc.resolve_payload(&item, PreviewSize::default()),
+            PreviewPayload::Markdown(text) if text == "**bold**"
+        ));
+    }
+
+    #[test]
+    fn command_output_for_markdown_files_stays_text() {
+        let exec = MockExec::new().push_ok(true, b"# rendered by bat".to_vec());
+        let svc = PreviewService::new(MockCache::new(), exec, MockDecoder::new());
+        let item = dynamic_item_with_template("notes.md", "bat {}");
+        assert!(matches!(
+            svc.resolve_payload(&item, PreviewSize::default()),
+            PreviewPayload::Text(text) if text == "# rendered by bat"
+        ));
+    }
+
     #[test]
     fn dynamic_text_stdout() {
         let exec = MockExec::new().push_ok(true, b"clipboard text".to_vec());
         let svc = PreviewService::new(MockCache::new(), exec, MockDecoder::new());
         let item = dynamic_item("id123");
         assert!(matches!(
-            svc.resolve_payload(&item),
+            svc.resolve_payload(&item, PreviewSize::default()),
             PreviewPayload::Text(ref s) if s == "clipboard text"
         ));
     }
@@ -361,18 +1082,49 @@ mod tests {
         let svc = PreviewService::new(MockCache::new(), exec, MockDecoder::new());
         let item = dynamic_item("fallback");
         assert!(matches!(
-            svc.resolve_payload(&item),
+            svc.resolve_payload(&item, PreviewSize::default()),
             PreviewPayload::Text(ref s) if s == "fallback"
         ));
     }
 
+    #[test]
+    fn dynamic_output_is_reused_from_memory() {
+        // Only one response queued: a second run would see empty output
+        let exec = MockExec::new().push_ok(true, b"clipboard text".to_vec());
+        let svc = PreviewService::new(MockCache::new(), exec, MockDecoder::new());
+        let item = dynamic_item("id123");
+        for _ in 0..2 {
+            assert!(matches!(
+                svc.resolve_payload(&item, PreviewSize::default()),
+                PreviewPayload::Text(ref s) if s == "clipboard text"
+            ));
+        }
+    }
+
+    #[test]
+    fn dynamic_failure_is_not_cached() {
+        let exec = MockExec::new()
+            .push_ok(true, b"clipboard text".to_vec())
+            .push_err(io::Error::other("busy"));
+        let svc = PreviewService::new(MockCache::new(), exec, MockDecoder::new());
+        let item = dynamic_item("id123");
+        assert!(matches!(
+            svc.resolve_payload(&item, PreviewSize::default()),
+            PreviewPayload::Text(ref s) if s == "id123"
+        ));
+        assert!(matches!(
+            svc.resolve_payload(&item, PreviewSize::default()),
+            PreviewPayload::Text(ref s) if s == "clipboard text"
+        ));
+    }
+
     #[test]
     fn dynamic_nonzero_exit_returns_value() {
         let exec = MockExec::new().push_ok(false, Vec::new());
         let svc = PreviewService::new(MockCache::new(), exec, MockDecoder::new());
         let item = dynamic_item("val");
         assert!(matches!(
-            svc.resolve_payload(&item),
+            svc.resolve_payload(&item, PreviewSize::default()),
             PreviewPayload::Text(ref s) if s == "val"
         ));
     }
@@ -383,7 +1135,7 @@ mod tests {
         let decoder = MockDecoder::new().with_result(vec![255; 400], 20, 20);
         let svc = PreviewService::new(MockCache::new(), exec, decoder);
         let item = dynamic_item("bin123");
-        match svc.resolve_payload(&item) {
+        match svc.resolve_payload(&item, PreviewSize::default()) {
             PreviewPayload::Image {
                 bytes,
                 width,
@@ -397,13 +1149,69 @@ mod tests {
         }
     }
 
+    #[test]
+    fn clipboard_entries_preview_their_contents() {
+        let exec = MockExec::new()
+            .push_ok(true, vec![0x89, 0x00, 0x01])
+            .push_ok(true, b"copied text".to_vec());
+        let decoder = MockDecoder::new().with_result(vec![255; 16], 2, 2);
+        let svc = PreviewService::new(MockCache::new(), exec, decoder);
+        let text = Item::clipboard("copied text", "2", "clip", crate::domain::ClipKind::Text);
+        assert!(svc.try_cache(&text, PreviewSize::default()).is_none());
+        assert!(matches!(
+            svc.resolve_payload(&text, PreviewSize::default()),
+            PreviewPayload::Text(ref s) if s == "copied text"
+        ));
+        let image = Item::clipboard(
+            "PNG image",
+            "1",
+            "clip",
+            crate::domain::ClipKind::Image {
+                format: "png".to_string(),
+            },
+        );
+        assert!(matches!(
+            svc.resolve_payload(&image, PreviewSize::default()),
+            PreviewPayload::Image { width: 2, .. }
+        ));
+    }
+
+    #[test]
+    fn dynamic_template_line_field() {
+        let exec = MockExec::new();
+        let svc = PreviewService::new(MockCache::new(), exec, MockDecoder::new());
+        let item = dynamic_item_with_template("a.rs", "bat -H {line} {}").with_line(Some(7));
+        let _ = svc.resolve_payload(&item, PreviewSize::default());
+        let key = MemKey::Command {
+            command: "bat -H 7 'a.rs'".to_string(),
+            size: PreviewSize::default(),
+        };
+        assert!(svc.mem_cache.get(&key).is_some());
+    }
+
+    #[test]
+    fn dynamic_template_expands_tilde() {
+        let svc = PreviewService::new(MockCache::new(), MockExec::new(), MockDecoder::new());
+        let item = dynamic_item_with_template("~/notes/todo.md", "bat {}");
+        let _ = svc.resolve_payload(&item, PreviewSize::default());
+        let expanded = crate::utils::expand_tilde("~/notes/todo.md");
+        let key = MemKey::Command {
+            command: format!(
+                "bat {}",
+                crate::utils::escape_shell_arg(&expanded.to_string_lossy())
+            ),
+            size: PreviewSize::default(),
+        };
+        assert!(svc.mem_cache.get(&key).is_some());
+    }
+
     #[test]
     fn dynamic_template_expansion() {
         let exec = MockExec::new().push_ok(true, b"expanded output".to_vec());
         let svc = PreviewService::new(MockCache::new(), exec, MockDecoder::new());
         let item = dynamic_item_with_template("myid", "echo {}");
         assert!(matches!(
-            svc.resolve_payload(&item),
+            svc.resolve_payload(&item, PreviewSize::default()),
             PreviewPayload::Text(ref s) if s == "expanded output"
         ));
     }
@@ -413,7 +1221,7 @@ mod tests {
         let svc = PreviewService::new(MockCache::new(), MockExec::new(), MockDecoder::new());
         let item = picture_item("/nonexistent/path/image.png");
         assert!(matches!(
-            svc.resolve_payload(&item),
+            svc.resolve_payload(&item, PreviewSize::default()),
             PreviewPayload::Text(ref s) if s == "/nonexistent/path/image.png"
         ));
     }
@@ -425,12 +1233,13 @@ mod tests {
 
         let mut cache = MockCache::new();
         let cached_data = vec![128; 100];
-        let cache_path = cache.get_cache_path("cat", &path);
+        let size = PreviewSize::default();
+        let cache_path = cache.get_cache_path("cat", &path, size.width, size.height);
         cache = cache.with_valid(cache_path, cached_data.clone(), 5, 5);
 
         let svc = PreviewService::new(cache, MockExec::new(), MockDecoder::new());
         let item = picture_item(&path.to_string_lossy());
-        match svc.resolve_payload(&item) {
+        match svc.resolve_payload(&item, PreviewSize::default()) {
             PreviewPayload::Image {
                 bytes,
                 width,
@@ -452,7 +1261,7 @@ mod tests {
         let decoder = MockDecoder::new().with_result(vec![64; 80], 4, 5);
         let svc = PreviewService::new(MockCache::new(), MockExec::new(), decoder);
         let item = picture_item(&path.to_string_lossy());
-        match svc.resolve_payload(&item) {
+        match svc.resolve_payload(&item, PreviewSize::default()) {
             PreviewPayload::Image {
                 bytes,
                 width,
@@ -466,6 +1275,82 @@ mod tests {
         }
     }
 
+    #[test]
+    fn prefetch_fills_memory_cache() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let path = tmp.path().to_path_buf();
+
+        let decoder = MockDecoder::new().with_result(vec![64; 80], 4, 5);
+        let svc = PreviewService::new(MockCache::new(), MockExec::new(), decoder);
+        let item = picture_item(&path.to_string_lossy());
+        assert!(svc.try_cache(&item, PreviewSize::default()).is_none());
+        svc.prefetch(&item, PreviewSize::default(), &CancelToken::never());
+        assert!(svc.try_cache(&item, PreviewSize::default()).is_some());
+    }
+
+    #[test]
+    fn prefetch_never_runs_commands() {
+        let exec = MockExec::new().push_ok(true, b"clipboard text".to_vec());
+        let svc = PreviewService::new(MockCache::new(), exec, MockDecoder::new());
+        let item = dynamic_item("id123");
+        svc.prefetch(&item, PreviewSize::default(), &CancelToken::never());
+        // The queued response is still there for the real request
+        assert!(matches!(
+            svc.resolve_payload(&item, PreviewSize::default()),
+            PreviewPayload::Text(ref s) if s == "clipboard text"
+        ));
+    }
+
+    #[test]
+    fn prefetch_skips_videos_and_audio() {
+        for suffix in [".mp4", ".mp3"] {
+            let tmp = tempfile::Builder::new().suffix(suffix).tempfile().unwrap();
+            let exec = MockExec::new().push_ok(true, b"untouched".to_vec());
+            let svc = PreviewService::new(MockCache::new(), exec.clone(), MockDecoder::new());
+            let item = picture_item(&tmp.path().to_string_lossy());
+            svc.prefetch(&item, PreviewSize::default(), &CancelToken::never());
+            assert_eq!(exec.execute("ffmpeg", &[]).unwrap().stdout, b"untouched");
+        }
+    }
+
+    #[test]
+    fn cancelled_resolve_skips_work() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let item = picture_item(&tmp.path().to_string_lossy());
+        let decoder = MockDecoder::new().with_result(vec![64; 80], 4, 5);
+        let svc = PreviewService::new(MockCache::new(), MockExec::new(), decoder);
+
+        let active = Arc::new(std::sync::atomic::AtomicU64::new(2));
+        let cancel = CancelToken::for_task(active, 1);
+        assert!(matches!(
+            svc.resolve_cancellable(&item, PreviewSize::default(), &cancel),
+            PreviewPayload::Error(_)
+        ));
+        assert!(svc.try_cache(&item, PreviewSize::default()).is_none());
+    }
+
+    #[test]
+    fn animation_kept_in_memory_only() {
+        let tmp = tempfile::Builder::new().suffix(".gif").tempfile().unwrap();
+        let path = tmp.path().to_path_buf();
+
+        let decoder = MockDecoder::new()
+            .with_result(vec![64; 80], 4, 5)
+            .with_animation(3, 4, 5);
+        let cache = MockCache::new();
+        let svc = PreviewService::new(cache, MockExec::new(), decoder);
+        let item = picture_item(&path.to_string_lossy());
+        match svc.resolve_payload(&item, PreviewSize::default()) {
+            PreviewPayload::Animation { frames, .. } => assert_eq!(frames.len(), 3),
+            other => panic!("expected Animation, got {:?}", other),
+        }
+        assert!(svc.cache.stored.read().unwrap().is_empty());
+        assert!(matches!(
+            svc.try_cache(&item, PreviewSize::default()),
+            Some(PreviewPayload::Animation { .. })
+        ));
+    }
+
     #[test]
     fn picture_video_uses_ffmpeg() {
         let tmp = tempfile::Builder::new().suffix(".mp4").tempfile().unwrap();
@@ -475,7 +1360,7 @@ mod tests {
         let decoder = MockDecoder::new().with_result(vec![200; 160], 8, 10);
         let svc = PreviewService::new(MockCache::new(), exec, decoder);
         let item = picture_item(&path.to_string_lossy());
-        match svc.resolve_payload(&item) {
+        match svc.resolve_payload(&item, PreviewSize::default()) {
             PreviewPayload::Image {
                 bytes,
                 width,
@@ -488,4 +1373,320 @@ mod tests {
             other => panic!("expected video thumbnail Image, got {:?}", other),
         }
     }
+
+    #[test]
+    fn long_video_gets_storyboard() {
+        let tmp = tempfile::Builder::new().suffix(".mkv").tempfile().unwrap();
+        let exec = MockExec::new().push_ok(true, b"duration=600.0\n".to_vec());
+        let decoder = MockDecoder::new().with_result(vec![200; 160], 8, 10);
+        let svc = PreviewService::new(MockCache::new(), exec, decoder);
+        let item = picture_item(&tmp.path().to_string_lossy());
+
+        let size = PreviewSize::default();
+        match svc.resolve_payload(&item, size) {
+            PreviewPayload::Image { width, height, .. } => {
+                assert_eq!(width, size.width / 3 * 3);
+                assert_eq!(height, size.height / 3 * 3);
+            }
+            other => panic!("expected storyboard Image, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn short_video_retries_first_frame() {
+        let tmp = tempfile::Builder::new().suffix(".mp4").tempfile().unwrap();
+        // Responses pop from the back: ffprobe, then a failed seek, then the retry
+        let exec = MockExec::new()
+            .push_ok(false, Vec::new())
+            .push_ok(true, b"duration=0.5\n".to_vec());
+        let decoder = MockDecoder::new().with_result(vec![200; 160], 8, 10);
+        let svc = PreviewService::new(MockCache::new(), exec, decoder);
+        let item = picture_item(&tmp.path().to_string_lossy());
+        assert!(matches!(
+            svc.resolve_payload(&item, PreviewSize::default()),
+            PreviewPayload::Image { width: 8, .. }
+        ));
+    }
+
+    #[test]
+    fn video_details_come_from_ffprobe() {
+        let tmp = tempfile::Builder::new().suffix(".webm").tempfile().unwrap();
+        let exec = MockExec::new().push_ok(true, b"codec_name=vp9\nduration=75\n".to_vec());
+        let svc = PreviewService::new(MockCache::new(), exec, MockDecoder::new());
+        let item = picture_item(&tmp.path().to_string_lossy());
+        let details = svc.media_details(&item, &CancelToken::never());
+        assert_eq!(
+            details,
+            vec![
+                ("Duration".to_string(), "1:15".to_string()),
+                ("Codec".to_string(), "vp9".to_string()),
+            ]
+        );
+        assert!(!svc.has_media_details(&text_item("clip.mp4")));
+    }
+
+    #[test]
+    fn video_is_probed_once() {
+        let tmp = tempfile::Builder::new().suffix(".mp4").tempfile().unwrap();
+        // Popped from the back: ffprobe, the frame, then whatever a second probe would get
+        let exec = MockExec::new()
+            .push_ok(true, b"codec_name=none\n".to_vec())
+            .push_ok(true, Vec::new())
+            .push_ok(true, b"codec_name=h264\nduration=0.5\n".to_vec());
+        let decoder = MockDecoder::new().with_result(vec![200; 160], 8, 10);
+        let svc = PreviewService::new(MockCache::new(), exec, decoder);
+        let item = picture_item(&tmp.path().to_string_lossy());
+        assert!(matches!(
+            svc.resolve_payload(&item, PreviewSize::default()),
+            PreviewPayload::Image { width: 8, .. }
+        ));
+        let details = svc.media_details(&item, &CancelToken::never());
+        assert!(details.contains(&("Codec".to_string(), "h264".to_string())));
+    }
+
+    #[test]
+    fn missing_loader_is_reported() {
+        let tmp = tempfile::Builder::new().suffix(".avif").tempfile().unwrap();
+        let decoder = MockDecoder::new().with_decode_error("No image loader for image/avif");
+        let svc = PreviewService::new(MockCache::new(), MockExec::new(), decoder);
+        let item = picture_item(&tmp.path().to_string_lossy());
+        match svc.resolve_payload(&item, PreviewSize::default()) {
+            PreviewPayload::Error(reason) => assert_eq!(reason, "No image loader for image/avif"),
+            _ => panic!("expected an error payload"),
+        }
+    }
+
+    #[test]
+    fn audio_previews_stack_cover_and_waveform() {
+        let tmp = tempfile::Builder::new().suffix(".flac").tempfile().unwrap();
+        let decoder = MockDecoder::new().with_result(vec![255u8; 2 * 2 * 4], 2, 2);
+        let svc = PreviewService::new(MockCache::new(), MockExec::new(), decoder);
+        let item = picture_item(&tmp.path().to_string_lossy());
+        match svc.resolve_payload(&item, PreviewSize::default()) {
+            PreviewPayload::Image { width, height, .. } => assert_eq!((width, height), (2, 4)),
+            _ => panic!("expected cover and waveform"),
+        }
+        assert!(!svc.has_full_resolution(&item));
+    }
+
+    #[test]
+    fn audio_details_come_from_ffprobe() {
+        let tmp = tempfile::Builder::new().suffix(".mp3").tempfile().unwrap();
+        let exec = MockExec::new().push_ok(true, b"TAG:artist=Nina\nduration=200\n".to_vec());
+        let svc = PreviewService::new(MockCache::new(), exec, MockDecoder::new());
+        let item = picture_item(&tmp.path().to_string_lossy());
+        assert_eq!(
+            svc.media_details(&item, &CancelToken::never()),
+            vec![
+                ("Artist".to_string(), "Nina".to_string()),
+                ("Duration".to_string(), "3:20".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn directories_preview_as_trees() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.txt"), b"abc").unwrap();
+        let svc = PreviewService::new(MockCache::new(), MockExec::new(), MockDecoder::new());
+        let item = text_item(&dir.path().to_string_lossy());
+        assert!(svc.try_cache(&item, PreviewSize::default()).is_none());
+        match svc.resolve_payload(&item, PreviewSize::default()) {
+            PreviewPayload::Listing { tree, cover } => {
+                assert_eq!(tree, "1 file, 0 folders, 3 B\n\n└── a.txt  3 B\n");
+                assert!(cover.is_none());
+            }
+            other => panic!("expected a listing, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn archives_list_with_a_cover() {
+        let tmp = tempfile::Builder::new().suffix(".cbz").tempfile().unwrap();
+        let listing = "-rw-r--r--  0 0 0  10 Jan  1  2024 02.png\n\
+                       -rw-r--r--  0 0 0  20 Jan  1  2024 01.png\n";
+        // Responses pop last first: the listing, then the extracted cover
+        let exec = MockExec::new()
+            .push_ok(true, b"\x89PNG".to_vec())
+            .push_ok(true, listing.as_bytes().to_vec());
+        let decoder = MockDecoder::new().with_result(vec![0u8; 4], 1, 1);
+        let svc = PreviewService::new(MockCache::new(), exec, decoder);
+        let item = text_item(&tmp.path().to_string_lossy());
+        match svc.resolve_payload(&item, PreviewSize::default()) {
+            PreviewPayload::Listing { tree, cover } => {
+                assert!(tree.starts_with("2 files, 0 folders, 30 B"));
+                assert_eq!(cover.map(|(_, w, h)| (w, h)), Some((1, 1)));
+            }
+            other => panic!("expected a listing, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn image_details_come_from_the_file() {
+        let tmp = tempfile::Builder::new().suffix(".png").tempfile().unwrap();
+        std::fs::write(tmp.path(), b"\x89PNG\r\n\x1a\n").unwrap();
+        let decoder = MockDecoder::new().with_result(Vec::new(), 1920, 1080);
+        let svc = PreviewService::new(MockCache::new(), MockExec::new(), decoder);
+        let item = picture_item(&tmp.path().to_string_lossy());
+        assert!(svc.has_media_details(&item));
+        let details = svc.media_details(&item, &CancelToken::never());
+        assert_eq!(
+            details[..2],
+            [
+                ("Dimensions".to_string(), "1920×1080".to_string()),
+                ("File size".to_string(), crate::utils::format_size(8)),
+            ]
+        );
+        assert!(!svc.has_media_details(&picture_item("/nonexistent/photo.jpg")));
+    }
+
+    fn providers(rules: &[(&str, &str)]) -> ProviderRegistry {
+        ProviderRegistry::try_from(
+            rules
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<std::collections::HashMap<_, _>>(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn provider_command_previews_text_item() {
+        let tmp = tempfile::Builder::new().suffix(".pdf").tempfile().unwrap();
+        std::fs::write(tmp.path(), b"%PDF-1.4\n").unwrap();
+        let exec = MockExec::new().push_ok(true, b"Page one\n".to_vec());
+        let svc = PreviewService::new(MockCache::new(), exec, MockDecoder::new())
+            .with_providers(providers(&[("application/pdf", "pdftotext {} -")]));
+        let item = text_item(&tmp.path().to_string_lossy());
+        match svc.resolve_payload(&item, PreviewSize::default()) {
+            PreviewPayload::Text(text) => assert_eq!(text, "Page one\n"),
+            other => panic!("expected provider output, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn builtin_text_provider_reads_file() {
+        let tmp = tempfile::Builder::new().suffix(".rs").tempfile().unwrap();
+        std::fs::write(tmp.path(), "fn main() {}\n").unwrap();
+        let svc = PreviewService::new(MockCache::new(), MockExec::new(), MockDecoder::new())
+            .with_providers(providers(&[("text/*", "builtin:text")]));
+        let item = text_item(&tmp.path().to_string_lossy());
+        match svc.resolve_payload(&item, PreviewSize::default()) {
+            PreviewPayload::Text(text) => assert_eq!(text, "fn main() {}\n"),
+            other => panic!("expected file contents, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn builtin_image_provider_decodes_text_item() {
+        let tmp = tempfile::Builder::new().suffix(".png").tempfile().unwrap();
+        let decoder = MockDecoder::new().with_result(vec![9; 16], 2, 2);
+        let svc = PreviewService::new(MockCache::new(), MockExec::new(), decoder)
+            .with_providers(providers(&[("*.png", "builtin:image")]));
+        let item = text_item(&tmp.path().to_string_lossy());
+        assert!(matches!(
+            svc.resolve_payload(&item, PreviewSize::default()),
+            PreviewPayload::Image { width: 2, .. }
+        ));
+    }
+
+    #[test]
+    fn picture_cache_miss_at_larger_size() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let path = tmp.path().to_path_buf();
+
+        let mut cache = MockCache::new();
+        let small = PreviewSize::default();
+        let cache_path = cache.get_cache_path("cat", &path, small.width, small.height);
+        cache = cache.with_valid(cache_path, vec![128; 100], 5, 5);
+
+        let decoder = MockDecoder::new().with_result(vec![64; 80], 4, 5);
+        let svc = PreviewService::new(cache, MockExec::new(), decoder);
+        let item = picture_item(&path.to_string_lossy());
+        let large = PreviewSize::bucketed(2560, 1440);
+        assert!(svc.try_cache(&item, small).is_some());
+        assert!(svc.try_cache(&item, large).is_none());
+        match svc.resolve_payload(&item, large) {
+            PreviewPayload::Image { width, height, .. } => {
+                assert_eq!(width, 4);
+                assert_eq!(height, 5);
+            }
+            other => panic!("expected re-decoded Image, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn picture_shared_cache_used_before_decode() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let path = tmp.path().to_path_buf();
+        let size = PreviewSize::default();
+
+        let shared = MockCache::new();
+        let shared_path = shared.get_cache_path("", &path, size.width, size.height);
+        let shared = shared.with_valid(shared_path, vec![7; 1024 * 768 * 4], 1024, 768);
+
+        let svc = PreviewService::new(MockCache::new(), MockExec::new(), MockDecoder::new())
+            .with_shared_cache(Arc::new(shared));
+        let item = picture_item(&path.to_string_lossy());
+        match svc.resolve_payload(&item, size) {
+            PreviewPayload::Image { width, height, .. } => {
+                assert_eq!((width, height), (1024, 768));
+            }
+            other => panic!("expected shared thumbnail, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn picture_shared_cache_too_small_is_ignored() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let path = tmp.path().to_path_buf();
+        let size = PreviewSize::default();
+
+        let shared = MockCache::new();
+        let shared_path = shared.get_cache_path("", &path, size.width, size.height);
+        let shared = shared.with_valid(shared_path, vec![7; 256 * 192 * 4], 256, 192);
+
+        let decoder = MockDecoder::new().with_result(vec![64; 80], 4, 5);
+        let svc = PreviewService::new(MockCache::new(), MockExec::new(), decoder)
+            .with_shared_cache(Arc::new(shared));
+        let item = picture_item(&path.to_string_lossy());
+        match svc.resolve_payload(&item, size) {
+            PreviewPayload::Image { width, height, .. } => {
+                assert_eq!((width, height), (4, 5));
+            }
+            other => panic!("expected decoded Image, got {:?}", other),
+        }
+        assert!(matches!(
+            svc.resolve_first_paint(&item, size),
+            Some(PreviewPayload::Image {
+                width: 256,
+                height: 192,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn preview_size_bucketed_rounds_up() {
+        let size = PreviewSize::bucketed(1000, 700);
+        assert_eq!(size.width, 1024);
+        assert_eq!(size.height, 768);
+    }
+
+    #[test]
+    fn preview_size_bucketed_clamps() {
+        assert_eq!(PreviewSize::bucketed(0, 0), PreviewSize::default());
+        assert_eq!(PreviewSize::bucketed(800, 600), PreviewSize::default());
+        let huge = PreviewSize::bucketed(100_000, 100_000);
+        assert_eq!(huge.width, crate::constants::MAX_PREVIEW_DECODE_SIZE);
+        assert_eq!(huge.height, crate::constants::MAX_PREVIEW_DECODE_SIZE);
+    }
+
+    #[test]
+    fn preview_size_covers() {
+        let small = PreviewSize::default();
+        let large = PreviewSize::bucketed(2000, 2000);
+        assert!(large.covers(&small));
+        assert!(!small.covers(&large));
+    }
 }
@@ -0,0 +1,236 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::detector;
+
+/// How a file matched by a `[providers]` rule is previewed
+#[derive(Debug, Clone, PartialEq)]
+pub enum Provider {
+    /// `builtin:text`: the file's contents
+    Text,
+    /// `builtin:image`: the normal image decoder and caches
+    Image,
+    /// Shell command with `{}` replaced by the path; its output is shown like a preview command's
+    Command(String),
+}
+
+impl Provider {
+    fn parse(spec: &str) -> Result<Self, String> {
+        match spec.trim() {
+            "builtin:text" => Ok(Provider::Text),
+            "builtin:image" => Ok(Provider::Image),
+            s if s.starts_with("builtin:") => Err(format!(
+                "Unknown builtin provider '{}' (expected builtin:text or builtin:image)",
+                s
+            )),
+            "" => Err("Provider command is empty".to_string()),
+            s => Ok(Provider::Command(s.to_string())),
+        }
+    }
+}
+
+/// A `[providers]` key: a MIME type such as `application/pdf` or `text/*`,
+/// or a file name glob such as `*.md`. Keys containing `/` are MIME types.
+#[derive(Debug, Clone, PartialEq)]
+enum Pattern {
+    Glob(String),
+    Mime(String),
+}
+
+impl Pattern {
+    fn parse(key: &str) -> Self {
+        if key.contains('/') {
+            Pattern::Mime(key.to_ascii_lowercase())
+        } else {
+            Pattern::Glob(key.to_string())
+        }
+    }
+
+    /// Lower ranks are tried first: globs, exact MIME types, `type/*`, then `*/*`
+    fn rank(&self) -> u8 {
+        match self {
+            Pattern::Glob(_) => 0,
+            Pattern::Mime(m) if m == "*/*" => 3,
+            Pattern::Mime(m) if m.ends_with("/*") => 2,
+            Pattern::Mime(_) => 1,
+        }
+    }
+
+    fn matches(&self, file_name: &str, mime: &str) -> bool {
+        match self {
+            Pattern::Glob(glob) => glob_match(glob, file_name),
+            Pattern::Mime(m) if m == "*/*" => true,
+            Pattern::Mime(m) => match m.strip_suffix("/*") {
+                Some(kind) => mime.split('/').next() == Some(kind),
+                None => m == mime,
+            },
+        }
+    }
+}
+
+/// Preview providers from the `[providers]` table, most specific first
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(try_from = "HashMap<String, String>")]
+pub struct ProviderRegistry {
+    rules: Vec<(Pattern, Provider)>,
+}
+
+impl TryFrom<HashMap<String, String>> for ProviderRegistry {
+    type Error = String;
+
+    fn try_from(table: HashMap<String, String>) -> Result<Self, Self::Error> {
+        let mut rules = table
+            .into_iter()
+            .map(|(key, spec)| {
+                let provider = Provider::parse(&spec)
+                    .map_err(|e| format!("In provider \"{}\": {}", key, e))?;
+                Ok((Pattern::parse(&key), provider, key))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        // Longer patterns are more specific; the key itself keeps the order stable
+        rules.sort_by(|(a, _, a_key), (b, _, b_key)| {
+            (a.rank(), std::cmp::Reverse(a_key.len()), a_key).cmp(&(
+                b.rank(),
+                std::cmp::Reverse(b_key.len()),
+                b_key,
+            ))
+        });
+        Ok(Self {
+            rules: rules
+                .into_iter()
+                .map(|(pattern, provider, _)| (pattern, provider))
+                .collect(),
+        })
+    }
+}
+
+impl ProviderRegistry {
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// The provider for a file, sniffing its contents only if a MIME rule needs it
+    pub fn lookup(&self, path: &Path) -> Option<&Provider> {
+        let file_name = path.file_name()?.to_string_lossy();
+        let mut mime = None;
+        self.rules
+            .iter()
+            .find(|(pattern, _)| {
+                let mime = match pattern {
+                    Pattern::Glob(_) => "",
+                    Pattern::Mime(_) => {
+                        *mime.get_or_insert_with(|| detector::detect_mime(path).unwrap_or(""))
+                    }
+                };
+                pattern.matches(&file_name, mime)
+            })
+            .map(|(_, provider)| provider)
+    }
+}
+
+/// Shell-style `*` and `?` matching, ignoring ASCII case
+fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c.eq_ignore_ascii_case(&name[n]) => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                // Let the last `*` swallow one more character
+                Some((star, matched)) => {
+                    backtrack = Some((star, matched + 1));
+                    p = star + 1;
+                    n = matched + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry(rules: &[(&str, &str)]) -> Result<ProviderRegistry, String> {
+        ProviderRegistry::try_from(
+            rules
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<HashMap<_, _>>(),
+        )
+    }
+
+    #[test]
+    fn glob_matching() {
+        assert!(glob_match("*.md", "README.MD"));
+        assert!(glob_match("Makefile", "makefile"));
+        assert!(glob_match("*.tar.*", "a.tar.gz"));
+        assert!(glob_match("img_??.png", "img_01.png"));
+        assert!(!glob_match("*.md", "notes.txt"));
+        assert!(!glob_match("img_??.png", "img_1.png"));
+    }
+
+    #[test]
+    fn mime_patterns() {
+        let exact = Pattern::parse("application/pdf");
+        assert!(exact.matches("x", "application/pdf"));
+        assert!(!exact.matches("x", "application/zip"));
+        assert!(Pattern::parse("text/*").matches("x", "text/plain"));
+        assert!(!Pattern::parse("text/*").matches("x", "image/png"));
+        assert!(Pattern::parse("*/*").matches("x", "image/png"));
+    }
+
+    #[test]
+    fn specific_rules_win() {
+        let dir = tempfile::tempdir().unwrap();
+        let notes = dir.path().join("notes.md");
+        std::fs::write(&notes, "# Notes\n").unwrap();
+        let plain = dir.path().join("plain.txt");
+        std::fs::write(&plain, "hello\n").unwrap();
+        let pdf = dir.path().join("paper");
+        std::fs::write(&pdf, b"%PDF-1.4\n").unwrap();
+
+        let registry = registry(&[
+            ("*/*", "file {}"),
+            ("text/*", "builtin:text"),
+            ("*.md", "glow {}"),
+            ("application/pdf", "pdftotext {} -"),
+        ])
+        .unwrap();
+        assert_eq!(
+            registry.lookup(&notes),
+            Some(&Provider::Command("glow {}".to_string()))
+        );
+        assert_eq!(registry.lookup(&plain), Some(&Provider::Text));
+        assert_eq!(
+            registry.lookup(&pdf),
+            Some(&Provider::Command("pdftotext {} -".to_string()))
+        );
+    }
+
+    #[test]
+    fn unmatched_file_has_no_provider() {
+        let registry = registry(&[("*.md", "builtin:text")]).unwrap();
+        assert_eq!(registry.lookup(Path::new("/tmp/photo.jpg")), None);
+    }
+
+    #[test]
+    fn unknown_builtin_rejected() {
+        let err = registry(&[("*.md", "builtin:markdown")]).unwrap_err();
+        assert!(err.contains("*.md"), "{}", err);
+        assert!(registry(&[("*.md", " ")]).is_err());
+    }
+}
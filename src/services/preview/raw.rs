@@ -0,0 +1,308 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use super::detector;
+use crate::constants::RAW_EXTENSIONS;
+
+/// Embedded previews bigger than this are not a camera's JPEG
+const MAX_PREVIEW_BYTES: u32 = 64 * 1024 * 1024;
+/// IFDs followed through chains and sub-IFDs before giving up on a file
+const MAX_IFDS: usize = 16;
+
+const TAG_SUB_IFDS: u16 = 0x014a;
+const TAG_STRIP_OFFSETS: u16 = 0x0111;
+const TAG_STRIP_BYTE_COUNTS: u16 = 0x0117;
+const TAG_JPEG_OFFSET: u16 = 0x0201;
+const TAG_JPEG_LENGTH: u16 = 0x0202;
+/// Panasonic's full-size preview, stored as one undefined-type blob
+const TAG_JPG_FROM_RAW: u16 = 0x002e;
+
+/// Whether a file is a camera RAW, by extension or by the signatures the TIFF
+/// sniff doesn't cover
+pub fn is_raw(path: &Path) -> bool {
+    let by_name = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| RAW_EXTENSIONS.contains(&ext.to_lowercase().as_str()));
+    by_name || detector::detect_mime(path).is_some_and(is_raw_mime)
+}
+
+pub fn is_raw_mime(mime: &str) -> bool {
+    matches!(
+        mime,
+        "image/x-canon-cr2" | "image/x-olympus-orf" | "image/x-panasonic-rw2" | "image/x-fuji-raf"
+    )
+}
+
+/// The largest JPEG a camera embedded in a RAW file. No RAW decoding happens, so what is
+/// shown is the camera's own rendering, at whatever size it stored it.
+pub fn embedded_preview(path: &Path) -> Option<Vec<u8>> {
+    let mut file = File::open(path).ok()?;
+    let (offset, len) = preview_range(&mut file)?;
+    let mut jpeg = vec![0u8; len as usize];
+    file.seek(SeekFrom::Start(offset as u64)).ok()?;
+    file.read_exact(&mut jpeg).ok()?;
+    Some(jpeg)
+}
+
+fn preview_range<R: Read + Seek>(reader: &mut R) -> Option<(u32, u32)> {
+    let mut head = [0u8; 92];
+    reader.read_exact(&mut head[..8]).ok()?;
+    // Fujifilm wraps its JPEG in its own header rather than TIFF
+    if head.starts_with(b"FUJIFILM") {
+        reader.read_exact(&mut head[8..]).ok()?;
+        let at = |i: usize| u32::from_be_bytes(head[i..i + 4].try_into().unwrap());
+        return Some((at(84), at(88))).filter(|&(offset, len)| is_jpeg(reader, offset, len));
+    }
+    let little = match &head[..2] {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+    let mut tiff = Tiff { reader, little };
+    // Variants change the magic number (RW2, ORF) but keep the layout
+    let first = tiff.u32_from(&head[4..8]);
+
+    let mut pending = vec![first];
+    let mut seen = 0;
+    let mut best: Option<(u32, u32)> = None;
+    while let Some(offset) = pending.pop() {
+        if offset == 0 || seen >= MAX_IFDS {
+            continue;
+        }
+        seen += 1;
+        let Some((entries, next)) = tiff.ifd(offset) else {
+            continue;
+        };
+        pending.push(next);
+        let find = |tag: u16| entries.iter().find(|entry| entry.tag == tag);
+
+        let mut candidates = Vec::new();
+        if let (Some(start), Some(len)) = (find(TAG_JPEG_OFFSET), find(TAG_JPEG_LENGTH)) {
+            candidates.push((start.value, len.value));
+        }
+        if let (Some(start), Some(len)) = (find(TAG_STRIP_OFFSETS), find(TAG_STRIP_BYTE_COUNTS))
+            && start.count == 1
+        {
+            candidates.push((start.value, len.value));
+        }
+        if let Some(blob) = find(TAG_JPG_FROM_RAW) {
+            candidates.push((blob.value, blob.count));
+        }
+        if let Some(sub) = find(TAG_SUB_IFDS) {
+            pending.extend(tiff.offsets(sub));
+        }
+
+        for (start, len) in candidates {
+            if best.is_none_or(|(_, best_len)| len > best_len)
+                && is_jpeg(&mut *tiff.reader, start, len)
+            {
+                best = Some((start, len));
+            }
+        }
+    }
+    best
+}
+
+fn is_jpeg<R: Read + Seek>(reader: &mut R, offset: u32, len: u32) -> bool {
+    let mut soi = [0u8; 2];
+    len > 2
+        && len <= MAX_PREVIEW_BYTES
+        && reader.seek(SeekFrom::Start(offset as u64)).is_ok()
+        && reader.read_exact(&mut soi).is_ok()
+        && soi == [0xff, 0xd8]
+}
+
+/// Width and height from a JPEG's frame header, without decoding it
+pub fn jpeg_size(jpeg: &[u8]) -> Option<(i32, i32)> {
+    let mut i = 2;
+    while i + 9 < jpeg.len() {
+        if jpeg[i] != 0xff {
+            return None;
+        }
+        let marker = jpeg[i + 1];
+        let len = u16::from_be_bytes([jpeg[i + 2], jpeg[i + 3]]) as usize;
+        // Start-of-frame markers, except DHT (C4), JPG (C8) and DAC (CC)
+        if (0xc0..=0xcf).contains(&marker) && !matches!(marker, 0xc4 | 0xc8 | 0xcc) {
+            let height = u16::from_be_bytes([jpeg[i + 5], jpeg[i + 6]]);
+            let width = u16::from_be_bytes([jpeg[i + 7], jpeg[i + 8]]);
+            return Some((width as i32, height as i32));
+        }
+        i += 2 + len;
+    }
+    None
+}
+
+struct Entry {
+    tag: u16,
+    count: u32,
+    /// The value itself when it fits in the entry, else the offset of the values
+    value: u32,
+}
+
+struct Tiff<'a, R> {
+    reader: &'a mut R,
+    little: bool,
+}
+
+impl<R: Read + Seek> Tiff<'_, R> {
+    fn u16_from(&self, bytes: &[u8]) -> u16 {
+        let bytes = [bytes[0], bytes[1]];
+        if self.little {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        }
+    }
+
+    fn u32_from(&self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if self.little {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        }
+    }
+
+    /// The entries of the IFD at `offset` and the offset of the next one
+    fn ifd(&mut self, offset: u32) -> Option<(Vec<Entry>, u32)> {
+        self.reader.seek(SeekFrom::Start(offset as u64)).ok()?;
+        let mut count = [0u8; 2];
+        self.reader.read_exact(&mut count).ok()?;
+        let count = self.u16_from(&count) as usize;
+        let mut raw = vec![0u8; count * 12 + 4];
+        self.reader.read_exact(&mut raw).ok()?;
+
+        let entries = raw[..count * 12]
+            .chunks(12)
+            .map(|entry| {
+                let kind = self.u16_from(&entry[2..4]);
+                // SHORT values sit in the first half of the field
+                let value = if kind == 3 {
+                    self.u16_from(&entry[8..10]) as u32
+                } else {
+                    self.u32_from(&entry[8..12])
+                };
+                Entry {
+                    tag: self.u16_from(&entry[0..2]),
+                    count: self.u32_from(&entry[4..8]),
+                    value,
+                }
+            })
+            .collect();
+        Some((entries, self.u32_from(&raw[count * 12..])))
+    }
+
+    /// IFD offsets listed by a SubIFDs entry
+    fn offsets(&mut self, entry: &Entry) -> Vec<u32> {
+        if entry.count <= 1 {
+            return vec![entry.value];
+        }
+        let count = entry.count.min(MAX_IFDS as u32) as usize;
+        let mut raw = vec![0u8; count * 4];
+        if self
+            .reader
+            .seek(SeekFrom::Start(entry.value as u64))
+            .is_err()
+            || self.reader.read_exact(&mut raw).is_err()
+        {
+            return Vec::new();
+        }
+        raw.chunks(4).map(|bytes| self.u32_from(bytes)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// A JPEG with only a frame header, `width`×`height`
+    fn jpeg(width: u16, height: u16) -> Vec<u8> {
+        let mut out = vec![0xff, 0xd8, 0xff, 0xc0, 0, 11, 8];
+        out.extend_from_slice(&height.to_be_bytes());
+        out.extend_from_slice(&width.to_be_bytes());
+        out.extend_from_slice(&[1, 1, 0x11, 0, 0xff, 0xd9]);
+        out
+    }
+
+    fn entry(tag: u16, kind: u16, count: u32, value: u32) -> Vec<u8> {
+        let mut out = tag.to_le_bytes().to_vec();
+        out.extend_from_slice(&kind.to_le_bytes());
+        out.extend_from_slice(&count.to_le_bytes());
+        out.extend_from_slice(&value.to_le_bytes());
+        out
+    }
+
+    /// A little-endian TIFF with a small JPEG thumbnail in IFD0 and a larger preview in
+    /// a sub-IFD, as NEF and DNG files store them
+    fn tiff_with_previews(thumbnail: &[u8], preview: &[u8]) -> Vec<u8> {
+        let ifd0 = 8u32;
+        let sub_ifd = ifd0 + 2 + 3 * 12 + 4;
+        let thumbnail_at = sub_ifd + 2 + 2 * 12 + 4;
+        let preview_at = thumbnail_at + thumbnail.len() as u32;
+
+        let mut out = b"II*\0".to_vec();
+        out.extend_from_slice(&ifd0.to_le_bytes());
+        out.extend_from_slice(&3u16.to_le_bytes());
+        out.extend(entry(TAG_SUB_IFDS, 4, 1, sub_ifd));
+        out.extend(entry(TAG_JPEG_OFFSET, 4, 1, thumbnail_at));
+        out.extend(entry(TAG_JPEG_LENGTH, 4, 1, thumbnail.len() as u32));
+        out.extend_from_slice(&0u32.to_le_bytes());
+        out.extend_from_slice(&2u16.to_le_bytes());
+        out.extend(entry(TAG_STRIP_OFFSETS, 4, 1, preview_at));
+        out.extend(entry(TAG_STRIP_BYTE_COUNTS, 4, 1, preview.len() as u32));
+        out.extend_from_slice(&0u32.to_le_bytes());
+        out.extend_from_slice(thumbnail);
+        out.extend_from_slice(preview);
+        out
+    }
+
+    #[test]
+    fn largest_embedded_jpeg_wins() {
+        let (thumbnail, preview) = (jpeg(160, 120), jpeg(1620, 1080));
+        let mut padded = preview.clone();
+        padded.extend_from_slice(&[0; 64]);
+        let file = tiff_with_previews(&thumbnail, &padded);
+        let (offset, len) = preview_range(&mut Cursor::new(&file)).unwrap();
+        let found = &file[offset as usize..(offset + len) as usize];
+        assert_eq!(jpeg_size(found), Some((1620, 1080)));
+    }
+
+    #[test]
+    fn fuji_header_points_at_the_jpeg() {
+        let preview = jpeg(1920, 1280);
+        let mut file = b"FUJIFILMCCD-RAW 0201FF383501".to_vec();
+        file.resize(84, 0);
+        file.extend_from_slice(&100u32.to_be_bytes());
+        file.extend_from_slice(&(preview.len() as u32).to_be_bytes());
+        file.resize(100, 0);
+        file.extend_from_slice(&preview);
+        assert_eq!(
+            preview_range(&mut Cursor::new(&file)),
+            Some((100, preview.len() as u32))
+        );
+    }
+
+    #[test]
+    fn non_jpeg_strips_are_skipped() {
+        let file = tiff_with_previews(&[0u8; 16], &[1u8; 32]);
+        assert_eq!(preview_range(&mut Cursor::new(&file)), None);
+        assert_eq!(preview_range(&mut Cursor::new(b"not a raw file")), None);
+    }
+
+    #[test]
+    fn raw_files_recognised_by_name_and_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let nef = dir.path().join("DSC_0001.NEF");
+        std::fs::write(&nef, b"II*\0").unwrap();
+        assert!(is_raw(&nef));
+        let cr2 = dir.path().join("photo");
+        std::fs::write(&cr2, b"II*\0\x10\0\0\0CR\x02\0").unwrap();
+        assert!(is_raw(&cr2));
+        let tiff = dir.path().join("scan.tif");
+        std::fs::write(&tiff, b"II*\0\x08\0\0\0").unwrap();
+        assert!(!is_raw(&tiff));
+    }
+}
@@ -1,9 +1,17 @@
-use crate::cache::CacheAdapter;
+use crate::constants::{
+    FFMPEG_THUMB_QUALITY, FFMPEG_TIMEOUT_SECS, FFPROBE_TIMEOUT_SECS, STORYBOARD_GRID,
+    STORYBOARD_MIN_SECS,
+};
+use crate::services::jobs::CancelToken;
 use crate::services::process::CommandExecutor;
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
-use super::PreviewPayload;
 use super::decoder::ImageDecoder;
+use super::{PreviewPayload, PreviewSize};
+
+type Frame = (Vec<u8>, i32, i32);
 
 pub fn is_video(path: &Path) -> bool {
     match path.extension().and_then(|s| s.to_str()) {
@@ -11,65 +19,332 @@ pub fn is_video(path: &Path) -> bool {
             let ext_lower = ext.to_lowercase();
             crate::constants::VIDEO_EXTENSIONS.contains(&ext_lower.as_str())
         }
-        None => false,
+        // Files without an extension, as directory expansion finds them by content
+        None => super::detector::detect_mime(path).is_some_and(|mime| mime.starts_with("video/")),
+    }
+}
+
+/// What ffprobe reports about the first video stream
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VideoInfo {
+    pub duration: Option<f64>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub codec: Option<String>,
+}
+
+impl VideoInfo {
+    /// Label/value pairs for the preview details
+    pub fn details(&self) -> Vec<(String, String)> {
+        let mut details = Vec::new();
+        if let Some(duration) = self.duration {
+            details.push(("Duration".to_string(), format_duration(duration)));
+        }
+        if let (Some(w), Some(h)) = (self.width, self.height) {
+            details.push(("Resolution".to_string(), format!("{}×{}", w, h)));
+        }
+        if let Some(codec) = &self.codec {
+            details.push(("Codec".to_string(), codec.clone()));
+        }
+        details
+    }
+}
+
+/// Size and mtime, as `source_fingerprint` reports them
+type Fingerprint = (u64, u64);
+
+/// ffprobe results by file, so the storyboard and the details pane share one probe
+#[derive(Debug, Default)]
+pub struct ProbeCache {
+    probes: Mutex<HashMap<PathBuf, (Fingerprint, VideoInfo)>>,
+}
+
+impl ProbeCache {
+    /// Probe `video_path` unless it was probed before and has not changed since
+    pub fn probe(
+        &self,
+        video_path: &Path,
+        executor: &dyn CommandExecutor,
+        cancel: &CancelToken,
+    ) -> Option<VideoInfo> {
+        let fingerprint = crate::cache::format::source_fingerprint(video_path)?;
+        if let Ok(probes) = self.probes.lock()
+            && let Some((known, info)) = probes.get(video_path)
+            && *known == fingerprint
+        {
+            return Some(info.clone());
+        }
+        let info = probe(video_path, executor, cancel)?;
+        if let Ok(mut probes) = self.probes.lock() {
+            probes.insert(video_path.to_path_buf(), (fingerprint, info.clone()));
+        }
+        Some(info)
+    }
+}
+
+pub fn probe(
+    video_path: &Path,
+    executor: &dyn CommandExecutor,
+    cancel: &CancelToken,
+) -> Option<VideoInfo> {
+    let video_str = video_path.to_string_lossy();
+    let args = [
+        "-v",
+        "error",
+        "-select_streams",
+        "v:0",
+        "-show_entries",
+        "format=duration:stream=codec_name,width,height",
+        "-of",
+        "default=noprint_wrappers=1",
+        &video_str,
+    ];
+    let output = executor
+        .execute_cancellable("ffprobe", &args, FFPROBE_TIMEOUT_SECS, cancel)
+        .ok()?;
+    if !output.success {
+        return None;
+    }
+    Some(parse_probe_output(&String::from_utf8_lossy(&output.stdout)))
+}
+
+fn parse_probe_output(output: &str) -> VideoInfo {
+    let mut info = VideoInfo::default();
+    for line in output.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim();
+        if value.is_empty() || value == "N/A" {
+            continue;
+        }
+        match key.trim() {
+            "duration" => info.duration = value.parse().ok().filter(|d: &f64| *d > 0.0),
+            "width" => info.width = value.parse().ok(),
+            "height" => info.height = value.parse().ok(),
+            "codec_name" => info.codec = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    info
+}
+
+pub(super) fn format_duration(seconds: f64) -> String {
+    let total = seconds.round() as u64;
+    let (h, m, s) = (total / 3600, total / 60 % 60, total % 60);
+    if h > 0 {
+        format!("{}:{:02}:{:02}", h, m, s)
+    } else {
+        format!("{}:{:02}", m, s)
     }
 }
 
+/// Seek positions for the preview: a grid spread over long videos, one frame otherwise
+fn frame_times(duration: Option<f64>) -> Vec<f64> {
+    let cells = (STORYBOARD_GRID * STORYBOARD_GRID) as usize;
+    match duration {
+        Some(d) if d >= STORYBOARD_MIN_SECS => (0..cells)
+            .map(|i| d * (i as f64 + 0.5) / cells as f64)
+            .collect(),
+        // Seeking to 1 s in a shorter clip yields no frame at all
+        Some(d) => vec![(d / 2.0).min(1.0)],
+        None => vec![0.0],
+    }
+}
+
+/// A frame, or a storyboard of frames for long videos, sized to fit `size`
 pub fn generate_thumbnail(
     video_path: &Path,
-    cache_path: &Path,
-    cache: &dyn CacheAdapter,
+    info: &VideoInfo,
+    size: PreviewSize,
     decoder: &dyn ImageDecoder,
     executor: &dyn CommandExecutor,
+    cancel: &CancelToken,
 ) -> PreviewPayload {
-    let video_stem = match video_path.file_stem().and_then(|s| s.to_str()) {
-        Some(s) => s,
-        None => return PreviewPayload::Error("Invalid video filename".to_string()),
+    let work_dir = match tempfile::tempdir() {
+        Ok(dir) => dir,
+        Err(e) => return PreviewPayload::Error(format!("Tempdir error: {}", e)),
+    };
+    let times = frame_times(info.duration);
+
+    let thumbnail = if times.len() > 1 {
+        let cell_w = size.width / STORYBOARD_GRID;
+        let cell_h = size.height / STORYBOARD_GRID;
+        let frames: Vec<Frame> = times
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &t)| {
+                let out = work_dir.path().join(format!("frame_{}.png", i));
+                extract_frame(
+                    video_path,
+                    &out,
+                    t,
+                    (cell_w, cell_h),
+                    decoder,
+                    executor,
+                    cancel,
+                )
+            })
+            .collect();
+        (!frames.is_empty())
+            .then(|| compose_grid(&frames, STORYBOARD_GRID as usize, cell_w, cell_h))
+    } else {
+        let out = work_dir.path().join("frame.png");
+        let frame_box = (size.width, size.height);
+        extract_frame(
+            video_path, &out, times[0], frame_box, decoder, executor, cancel,
+        )
+        .or_else(|| extract_frame(video_path, &out, 0.0, frame_box, decoder, executor, cancel))
     };
 
-    let temp_png = cache_path.with_file_name(format!("{}_thumb_temp.png", video_stem));
+    match thumbnail {
+        Some((raw_data, w, h)) => PreviewPayload::Image {
+            bytes: std::sync::Arc::new(raw_data),
+            width: w,
+            height: h,
+        },
+        None if cancel.is_cancelled() => super::cancelled(),
+        None => PreviewPayload::Error("FFmpeg execution failed".to_string()),
+    }
+}
 
+fn extract_frame(
+    video_path: &Path,
+    out: &Path,
+    seconds: f64,
+    (max_width, max_height): (i32, i32),
+    decoder: &dyn ImageDecoder,
+    executor: &dyn CommandExecutor,
+    cancel: &CancelToken,
+) -> Option<Frame> {
     let video_str = video_path.to_string_lossy();
-    let temp_str = temp_png.to_string_lossy();
-    let scale = crate::constants::FFMPEG_THUMB_SCALE;
-    let quality = crate::constants::FFMPEG_THUMB_QUALITY;
-    let args: [&str; 14] = [
+    let out_str = out.to_string_lossy();
+    let seek = format!("{:.3}", seconds);
+    let scale = format!(
+        "scale={}:{}:force_original_aspect_ratio=decrease",
+        max_width, max_height
+    );
+    let quality = FFMPEG_THUMB_QUALITY.to_string();
+    let args = [
         "-y",
         "-ss",
-        "1",
+        &seek,
         "-i",
         &video_str,
-        "-vframes",
+        "-frames:v",
         "1",
         "-vf",
-        &format!("scale={scale}:-1"),
-        "-preset",
-        "ultrafast",
+        &scale,
         "-q:v",
-        &quality.to_string(),
-        &temp_str,
+        &quality,
+        &out_str,
     ];
 
-    match executor.execute("ffmpeg", &args) {
-        Ok(output) if output.success => {
-            if let Some((raw_data, w, h)) =
-                decoder.load_from_path(&temp_png, scale, crate::constants::IMAGE_PREVIEW_HEIGHT)
-            {
-                let _ = cache.save_raw_cache(cache_path, &raw_data, w, h);
-                let _ = std::fs::remove_file(&temp_png);
-                PreviewPayload::Image {
-                    bytes: std::sync::Arc::new(raw_data),
-                    width: w,
-                    height: h,
-                }
-            } else {
-                let _ = std::fs::remove_file(&temp_png);
-                PreviewPayload::Error("Failed to decode video thumbnail".to_string())
-            }
-        }
-        _ => {
-            let _ = std::fs::remove_file(&temp_png);
-            PreviewPayload::Error("FFmpeg execution failed".to_string())
+    let output = executor
+        .execute_cancellable("ffmpeg", &args, FFMPEG_TIMEOUT_SECS, cancel)
+        .ok()?;
+    if !output.success {
+        return None;
+    }
+    let frame = decoder.load_cancellable(out, max_width, max_height, cancel);
+    let _ = std::fs::remove_file(out);
+    frame
+}
+
+/// Lay frames out left to right, top to bottom, each centred in its cell on black
+fn compose_grid(frames: &[Frame], cols: usize, cell_w: i32, cell_h: i32) -> Frame {
+    let rows = frames.len().div_ceil(cols);
+    let (cell_w, cell_h) = (cell_w as usize, cell_h as usize);
+    let (width, height) = (cols * cell_w, rows * cell_h);
+    let mut canvas: Vec<u8> = [0, 0, 0, 255].repeat(width * height);
+
+    for (i, (pixels, frame_w, frame_h)) in frames.iter().enumerate() {
+        // Frames larger than their cell are cropped, so rows still start a full frame apart
+        let src_stride = *frame_w as usize * 4;
+        let (w, h) = (
+            (*frame_w as usize).min(cell_w),
+            (*frame_h as usize).min(cell_h),
+        );
+        let x0 = (i % cols) * cell_w + (cell_w - w) / 2;
+        let y0 = (i / cols) * cell_h + (cell_h - h) / 2;
+        for row in 0..h {
+            let src = &pixels[row * src_stride..row * src_stride + w * 4];
+            let dst_start = ((y0 + row) * width + x0) * 4;
+            canvas[dst_start..dst_start + w * 4].copy_from_slice(src);
         }
     }
+    (canvas, width as i32, height as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ffprobe_output() {
+        let out = "codec_name=h264\nwidth=1920\nheight=1080\nduration=83.4\n";
+        let info = parse_probe_output(out);
+        assert_eq!(info.codec.as_deref(), Some("h264"));
+        assert_eq!((info.width, info.height), (Some(1920), Some(1080)));
+        assert_eq!(
+            info.details(),
+            vec![
+                ("Duration".to_string(), "1:23".to_string()),
+                ("Resolution".to_string(), "1920×1080".to_string()),
+                ("Codec".to_string(), "h264".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_duration_is_ignored() {
+        let info = parse_probe_output("codec_name=vp9\nduration=N/A\n");
+        assert_eq!(info.duration, None);
+    }
+
+    #[test]
+    fn long_videos_get_a_storyboard() {
+        let times = frame_times(Some(90.0));
+        assert_eq!(times.len(), 9);
+        assert_eq!(times[0], 5.0);
+        assert!(times.iter().all(|&t| t < 90.0));
+    }
+
+    #[test]
+    fn short_clips_seek_inside_the_clip() {
+        assert_eq!(frame_times(Some(0.4)), vec![0.2]);
+        assert_eq!(frame_times(Some(5.0)), vec![1.0]);
+        assert_eq!(frame_times(None), vec![0.0]);
+    }
+
+    #[test]
+    fn formats_long_durations_with_hours() {
+        assert_eq!(format_duration(3725.0), "1:02:05");
+        assert_eq!(format_duration(9.6), "0:10");
+    }
+
+    #[test]
+    fn grid_centres_frames_in_cells() {
+        let white = (vec![255u8; 2 * 2 * 4], 2, 2);
+        let (canvas, w, h) = compose_grid(&[white.clone(), white.clone(), white], 2, 4, 4);
+        assert_eq!((w, h), (8, 8));
+        let pixel = |x: usize, y: usize| &canvas[(y * 8 + x) * 4..(y * 8 + x) * 4 + 4];
+        assert_eq!(pixel(0, 0), &[0, 0, 0, 255]);
+        assert_eq!(pixel(1, 1), &[255, 255, 255, 255]);
+        assert_eq!(pixel(5, 2), &[255, 255, 255, 255]);
+        assert_eq!(pixel(1, 5), &[255, 255, 255, 255]);
+        assert_eq!(pixel(5, 5), &[0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn grid_crops_frames_wider_than_their_cell() {
+        // Pixel (x, y) of a 3×2 frame is grey level 10 * (3y + x)
+        let pixels: Vec<u8> = (0..6u8)
+            .flat_map(|i| [i * 10, i * 10, i * 10, 255])
+            .collect();
+        let (canvas, w, h) = compose_grid(&[(pixels, 3, 2)], 1, 2, 2);
+        assert_eq!((w, h), (2, 2));
+        let grey: Vec<u8> = canvas.chunks(4).map(|pixel| pixel[0]).collect();
+        assert_eq!(grey, vec![0, 10, 30, 40]);
+    }
 }
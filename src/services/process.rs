@@ -1,5 +1,8 @@
 use std::io;
-use std::time::Duration;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
+
+use crate::services::jobs::CancelToken;
 
 #[cfg(unix)]
 use std::os::unix::process::CommandExt;
@@ -12,12 +15,22 @@ pub struct CommandOutput {
 
 pub trait CommandExecutor: Send + Sync {
     fn execute(&self, program: &str, args: &[&str]) -> io::Result<CommandOutput>;
-    fn execute_with_timeout(
+    /// Like `execute_with_timeout`, but also stops the command once `cancel` fires
+    fn execute_cancellable(
         &self,
         program: &str,
         args: &[&str],
         timeout_secs: u64,
+        cancel: &CancelToken,
     ) -> io::Result<CommandOutput>;
+    fn execute_with_timeout(
+        &self,
+        program: &str,
+        args: &[&str],
+        timeout_secs: u64,
+    ) -> io::Result<CommandOutput> {
+        self.execute_cancellable(program, args, timeout_secs, &CancelToken::never())
+    }
 }
 
 #[derive(Clone)]
@@ -32,11 +45,12 @@ impl CommandExecutor for ShellExec {
         })
     }
 
-    fn execute_with_timeout(
+    fn execute_cancellable(
         &self,
         program: &str,
         args: &[&str],
         timeout_secs: u64,
+        cancel: &CancelToken,
     ) -> io::Result<CommandOutput> {
         let mut cmd = std::process::Command::new(program);
         cmd.args(args)
@@ -62,39 +76,56 @@ impl CommandExecutor for ShellExec {
             let _ = tx.send(result);
         });
 
-        match rx.recv_timeout(Duration::from_secs(timeout_secs)) {
-            Ok(result) => {
-                let output = result?;
-                Ok(CommandOutput {
-                    success: output.status.success(),
-                    stdout: output.stdout,
-                })
-            }
-            Err(_) => {
-                // Kill entire process group (negative PID = process group)
-                #[cfg(unix)]
-                unsafe {
-                    libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+        let deadline = Instant::now() + Duration::from_secs(timeout_secs);
+        loop {
+            match rx.recv_timeout(cancel.poll_interval()) {
+                Ok(result) => {
+                    let output = result?;
+                    return Ok(CommandOutput {
+                        success: output.status.success(),
+                        stdout: output.stdout,
+                    });
                 }
-
-                #[cfg(not(unix))]
-                {
-                    // Non-Unix fallback: only kills the parent process
-                    let _ = std::process::Command::new("kill")
-                        .arg("-9")
-                        .arg(pid.to_string())
-                        .output();
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(io::Error::other("Command output was lost"));
                 }
-
-                Err(io::Error::new(
-                    io::ErrorKind::TimedOut,
-                    format!("Command timed out after {} seconds", timeout_secs),
-                ))
+                Err(RecvTimeoutError::Timeout) if cancel.is_cancelled() => {
+                    kill_process_group(pid);
+                    return Err(io::Error::new(
+                        io::ErrorKind::Interrupted,
+                        "Command cancelled",
+                    ));
+                }
+                Err(RecvTimeoutError::Timeout) if Instant::now() >= deadline => {
+                    kill_process_group(pid);
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("Command timed out after {} seconds", timeout_secs),
+                    ));
+                }
+                Err(RecvTimeoutError::Timeout) => {}
             }
         }
     }
 }
 
+fn kill_process_group(pid: u32) {
+    // Kill entire process group (negative PID = process group)
+    #[cfg(unix)]
+    unsafe {
+        libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+    }
+
+    #[cfg(not(unix))]
+    {
+        // Non-Unix fallback: only kills the parent process
+        let _ = std::process::Command::new("kill")
+            .arg("-9")
+            .arg(pid.to_string())
+            .output();
+    }
+}
+
 #[cfg(test)]
 pub struct MockExec {
     responses: std::sync::Arc<std::sync::Mutex<Vec<io::Result<CommandOutput>>>>,
@@ -144,12 +175,19 @@ impl CommandExecutor for MockExec {
             }))
     }
 
-    fn execute_with_timeout(
+    fn execute_cancellable(
         &self,
         program: &str,
         args: &[&str],
         _timeout_secs: u64,
+        cancel: &CancelToken,
     ) -> io::Result<CommandOutput> {
+        if cancel.is_cancelled() {
+            return Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "Command cancelled",
+            ));
+        }
         self.execute(program, args)
     }
 }
@@ -183,4 +221,35 @@ mod tests {
         assert_eq!(r1.stdout, b"second");
         assert_eq!(r2.stdout, b"first");
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn shell_exec_kills_cancelled_command() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        let active = Arc::new(AtomicU64::new(1));
+        let token = CancelToken::for_task(active.clone(), 1);
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            active.store(2, Ordering::Relaxed);
+        });
+
+        let started = Instant::now();
+        let err = ShellExec
+            .execute_cancellable("sh", &["-c", "sleep 10"], 30, &token)
+            .unwrap_err();
+        canceller.join().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shell_exec_times_out() {
+        let err = ShellExec
+            .execute_with_timeout("sh", &["-c", "sleep 10"], 1)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    }
 }
@@ -0,0 +1,86 @@
+use crate::services::preview::AnimationFrame;
+use gtk4::prelude::*;
+use gtk4::{Picture, gdk, glib};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// A picture that loops through `frames`, pausing while its window is inactive.
+/// Playback stops by itself once the picture is dropped.
+pub fn create_animation_picture(
+    frames: Arc<Vec<AnimationFrame>>,
+    width: i32,
+    height: i32,
+) -> Picture {
+    let picture = Picture::for_paintable(&frame_texture(&frames[0], width, height));
+    let player = Player {
+        picture: picture.downgrade(),
+        frames,
+        width,
+        height,
+    };
+    player.schedule(0);
+    picture
+}
+
+#[derive(Clone)]
+struct Player {
+    picture: glib::WeakRef<Picture>,
+    frames: Arc<Vec<AnimationFrame>>,
+    width: i32,
+    height: i32,
+}
+
+impl Player {
+    fn schedule(self, index: usize) {
+        let delay = self.frames[index].delay;
+        glib::timeout_add_local_once(delay, move || self.advance(index));
+    }
+
+    fn advance(self, index: usize) {
+        let Some(picture) = self.picture.upgrade() else {
+            return;
+        };
+        if let Some(window) = picture.root().and_downcast::<gtk4::Window>()
+            && !window.is_active()
+        {
+            self.resume_on_focus(&window, index);
+            return;
+        }
+
+        let next = (index + 1) % self.frames.len();
+        picture.set_paintable(Some(&frame_texture(
+            &self.frames[next],
+            self.width,
+            self.height,
+        )));
+        self.schedule(next);
+    }
+
+    fn resume_on_focus(self, window: &gtk4::Window, index: usize) {
+        let handler: Rc<RefCell<Option<glib::SignalHandlerId>>> = Rc::default();
+        let handler_slot = handler.clone();
+        let id = window.connect_is_active_notify(move |window| {
+            if !window.is_active() {
+                return;
+            }
+            if let Some(id) = handler_slot.borrow_mut().take() {
+                window.disconnect(id);
+            }
+            self.clone().advance(index);
+        });
+        *handler.borrow_mut() = Some(id);
+    }
+}
+
+fn frame_texture(frame: &AnimationFrame, width: i32, height: i32) -> gdk::MemoryTexture {
+    // Shares the frame's pixels instead of copying them
+    let bytes = glib::Bytes::from_owned(frame.pixels.clone());
+    gdk::MemoryTexture::new(
+        width,
+        height,
+        gdk::MemoryFormat::R8g8b8a8,
+        &bytes,
+        (width * 4) as usize,
+    )
+}
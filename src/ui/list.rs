@@ -1,14 +1,24 @@
+use crate::constants::ROW_THUMBNAIL_SIZE;
+use crate::domain::ClipKind;
 use crate::domain::item::Item;
+use crate::services::preview::GdkPixbufDecoder;
+use crate::services::process::ShellExec;
+use crate::services::{ansi, clipboard};
 use crate::ui::item_object::ItemObject;
 use crate::ui::r#match::{fuzzy_match, relevance_score};
+use crate::ui::viewer::rgba_texture;
 use gtk4::prelude::*;
 use gtk4::{
     ApplicationWindow, Box as GtkBox, CustomFilter, CustomSorter, FilterChange, FilterListModel,
-    Label, ListItem, ListView, Orientation, SignalListItemFactory, SingleSelection, SortListModel,
-    SorterChange, gio,
+    Label, ListItem, ListView, Orientation, Picture, SignalListItemFactory, SingleSelection,
+    SortListModel, SorterChange, gdk, gio,
 };
+use std::cell::RefCell;
 use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fmt::Write;
+use std::rc::Rc;
+use std::sync::Arc;
 
 use crate::ui::search::SearchState;
 
@@ -61,6 +71,13 @@ impl ListState {
         }
     }
 
+    /// Swap the whole list for `items`, as when a file browser changes folder
+    pub fn replace_items(&self, items: &[Item]) {
+        let objects: Vec<ItemObject> = items.iter().cloned().map(ItemObject::new).collect();
+        self.store
+            .splice(0, self.store.n_items(), objects.as_slice());
+    }
+
     pub fn selected_item(&self) -> Option<Item> {
         self.selection
             .selected_item()
@@ -68,6 +85,25 @@ impl ListState {
             .and_then(|item_object| item_object.item())
     }
 
+    /// Item at a position in the filtered, sorted view
+    pub fn item_at(&self, index: u32) -> Option<Item> {
+        self.sort_model
+            .item(index)
+            .and_downcast::<ItemObject>()
+            .and_then(|item_object| item_object.item())
+    }
+
+    /// Select the item with `value` in the filtered, sorted view, if it is there
+    pub fn select_value(&self, value: &str) -> bool {
+        let found = (0..self.sort_model.n_items())
+            .find(|&index| self.item_at(index).is_some_and(|item| item.value == value));
+        if let Some(index) = found {
+            self.selection.set_selected(index);
+            self.scroll_to(index);
+        }
+        found.is_some()
+    }
+
     pub fn select_first(&self) {
         if self.sort_model.n_items() == 0 {
             self.selection.set_selected(gtk4::INVALID_LIST_POSITION);
@@ -76,6 +112,23 @@ impl ListState {
         }
     }
 
+    /// Drop the selected item, selecting whatever moves into its place
+    pub fn remove_selected(&self) {
+        let index = self.selection.selected();
+        let Some(obj) = self.selection.selected_item().and_downcast::<ItemObject>() else {
+            return;
+        };
+        if let Some(position) = self.store.find(&obj) {
+            self.store.remove(position);
+        }
+        let total = self.sort_model.n_items();
+        if total > 0 {
+            let next = index.min(total - 1);
+            self.selection.set_selected(next);
+            self.scroll_to(next);
+        }
+    }
+
     pub fn refresh_filter(&self) {
         self.filter.changed(FilterChange::Different);
         self.sorter.changed(SorterChange::Different);
@@ -148,17 +201,18 @@ impl ListState {
         }
     }
 
-    pub fn marked_values(&self) -> Vec<String> {
-        let mut values = Vec::new();
+    pub fn marked_items(&self) -> Vec<Item> {
+        let mut items = Vec::new();
         let n = self.store.n_items();
         for i in 0..n {
             if let Some(obj) = self.store.item(i).and_downcast::<ItemObject>()
                 && obj.is_marked()
+                && let Some(item) = obj.item()
             {
-                values.push(obj.value());
+                items.push(item);
             }
         }
-        values
+        items
     }
 
     pub fn connect_selection_changed<F>(&self, callback: F)
@@ -227,9 +281,20 @@ fn build_factory(query_state: SearchState) -> SignalListItemFactory {
             .downcast_ref::<ListItem>()
             .expect("factory setup object must be a ListItem");
 
-        let row = GtkBox::new(Orientation::Vertical, 2);
+        let row = GtkBox::new(Orientation::Horizontal, 10);
         row.add_css_class("bookmark-row");
 
+        // Clipboard images show a thumbnail, applications and files their icon
+        let thumbnail = Picture::new();
+        thumbnail.set_size_request(ROW_THUMBNAIL_SIZE, ROW_THUMBNAIL_SIZE);
+        thumbnail.set_content_fit(gtk4::ContentFit::Contain);
+        thumbnail.add_css_class("bookmark-thumbnail");
+        thumbnail.set_visible(false);
+
+        let text = GtkBox::new(Orientation::Vertical, 2);
+        text.set_hexpand(true);
+        text.set_valign(gtk4::Align::Center);
+
         let title_label = Label::new(None);
         title_label.set_xalign(0.0);
         title_label.set_use_markup(true);
@@ -242,11 +307,14 @@ fn build_factory(query_state: SearchState) -> SignalListItemFactory {
         value_label.set_ellipsize(gtk4::pango::EllipsizeMode::End);
         value_label.set_single_line_mode(true);
 
-        row.append(&title_label);
-        row.append(&value_label);
+        text.append(&title_label);
+        text.append(&value_label);
+        row.append(&thumbnail);
+        row.append(&text);
         list_item.set_child(Some(&row));
     });
 
+    let thumbnails = ThumbnailCache::default();
     factory.connect_bind(move |_, obj| {
         let list_item = obj
             .downcast_ref::<ListItem>()
@@ -254,61 +322,62 @@ fn build_factory(query_state: SearchState) -> SignalListItemFactory {
         let Some(item_object) = list_item.item().and_downcast::<ItemObject>() else {
             return;
         };
-        let Some(row) = list_item.child().and_downcast::<GtkBox>() else {
-            return;
-        };
-        let Some(title_label) = row.first_child().and_downcast::<Label>() else {
-            return;
-        };
-        let Some(value_label) = title_label.next_sibling().and_downcast::<Label>() else {
+        let Some((row, thumbnail, title_label, value_label)) = row_widgets(list_item) else {
             return;
         };
+        let item = item_object.item();
+        let icon = item.as_ref().and_then(|item| item.icon.as_deref());
+        match (item.as_ref().and_then(|item| item.clip.as_ref()), icon) {
+            (Some(ClipKind::Image { .. }), _) => {
+                thumbnail.set_visible(true);
+                show_thumbnail(&thumbnail, list_item, &item_object, &thumbnails);
+            }
+            (_, Some(icon)) => {
+                thumbnail.set_visible(true);
+                show_icon(&thumbnail, icon);
+            }
+            _ => thumbnail.set_visible(false),
+        }
 
         let marked = item_object.is_marked();
 
         let query = query_state.borrow();
-        if query.is_empty() {
-            let base = glib::markup_escape_text(&item_object.title());
-            if marked {
-                title_label.set_markup(&format!(
-                    "<span foreground='#3584e4' weight='bold'>✓ </span>{}",
-                    base
-                ));
-                row.add_css_class("marked-row");
-            } else {
-                title_label.set_markup(&base);
-                row.remove_css_class("marked-row");
-            }
+        // Search highlighting replaces a command's own colours while filtering
+        let colored = item_object
+            .item()
+            .and_then(|item| item.colored)
+            .filter(|_| query.is_empty());
+        let title = match &colored {
+            Some(colored) => ansi::to_pango_markup(&colored.title),
+            None if query.is_empty() => glib::markup_escape_text(&item_object.title()).to_string(),
+            None => highlight_title(&item_object.title(), &query),
+        };
+        if marked {
+            title_label.set_markup(&format!(
+                "<span foreground='#3584e4' weight='bold'>✓ </span>{}",
+                title
+            ));
+            row.add_css_class("marked-row");
         } else {
-            let highlighted = highlight_title(&item_object.title(), &query);
-            if marked {
-                title_label.set_markup(&format!(
-                    "<span foreground='#3584e4' weight='bold'>✓ </span>{}",
-                    highlighted
-                ));
-                row.add_css_class("marked-row");
-            } else {
-                title_label.set_markup(&highlighted);
-                row.remove_css_class("marked-row");
-            }
+            title_label.set_markup(&title);
+            row.remove_css_class("marked-row");
+        }
+        // `set_text` also turns markup back off for recycled rows
+        match colored {
+            Some(colored) => value_label.set_markup(&ansi::to_pango_markup(&colored.value)),
+            None => value_label.set_text(&item_object.value()),
         }
-        value_label.set_label(&item_object.value());
     });
 
     factory.connect_unbind(|_, obj| {
         let Some(list_item) = obj.downcast_ref::<ListItem>() else {
             return;
         };
-        let Some(row) = list_item.child().and_downcast::<GtkBox>() else {
-            return;
-        };
-        let Some(title_label) = row.first_child().and_downcast::<Label>() else {
-            return;
-        };
-        let Some(value_label) = title_label.next_sibling().and_downcast::<Label>() else {
+        let Some((_, thumbnail, title_label, value_label)) = row_widgets(list_item) else {
             return;
         };
 
+        thumbnail.set_paintable(None::<&gdk::Texture>);
         title_label.set_label("");
         value_label.set_label("");
     });
@@ -316,6 +385,76 @@ fn build_factory(query_state: SearchState) -> SignalListItemFactory {
     factory
 }
 
+/// The row box, thumbnail, title and value labels built in `connect_setup`
+fn row_widgets(list_item: &ListItem) -> Option<(GtkBox, Picture, Label, Label)> {
+    let row = list_item.child().and_downcast::<GtkBox>()?;
+    let thumbnail = row.first_child().and_downcast::<Picture>()?;
+    let text = thumbnail.next_sibling().and_downcast::<GtkBox>()?;
+    let title_label = text.first_child().and_downcast::<Label>()?;
+    let value_label = title_label.next_sibling().and_downcast::<Label>()?;
+    Some((row, thumbnail, title_label, value_label))
+}
+
+/// An icon from the icon theme, or an image file
+fn show_icon(thumbnail: &Picture, icon: &str) {
+    if std::path::Path::new(icon).is_absolute() {
+        thumbnail.set_filename(Some(icon));
+        return;
+    }
+    let theme = gtk4::IconTheme::for_display(&thumbnail.display());
+    let paintable = theme.lookup_icon(
+        icon,
+        &["application-x-executable"],
+        ROW_THUMBNAIL_SIZE,
+        thumbnail.scale_factor(),
+        gtk4::TextDirection::None,
+        gtk4::IconLookupFlags::empty(),
+    );
+    thumbnail.set_paintable(Some(&paintable));
+}
+
+/// Decoded clipboard thumbnails by cliphist id, `None` where decoding failed
+type ThumbnailCache = Rc<RefCell<HashMap<String, Option<gdk::Texture>>>>;
+
+fn show_thumbnail(
+    thumbnail: &Picture,
+    list_item: &ListItem,
+    item_object: &ItemObject,
+    cache: &ThumbnailCache,
+) {
+    let id = item_object.value();
+    if let Some(texture) = cache.borrow().get(&id) {
+        thumbnail.set_paintable(texture.as_ref());
+        return;
+    }
+    thumbnail.set_paintable(None::<&gdk::Texture>);
+
+    let thumbnail = thumbnail.clone();
+    let list_item = list_item.clone();
+    let item_object = item_object.clone();
+    let cache = cache.clone();
+    glib::spawn_future_local(async move {
+        let worker_id = id.clone();
+        let decoded = gio::spawn_blocking(move || {
+            clipboard::thumbnail(
+                &worker_id,
+                ROW_THUMBNAIL_SIZE,
+                &ShellExec,
+                &GdkPixbufDecoder,
+            )
+        })
+        .await
+        .ok()
+        .flatten();
+        let texture = decoded.map(|(bytes, w, h)| rgba_texture(Arc::new(bytes), w, h).upcast());
+        // The row may have been recycled for another item while decoding
+        if list_item.item().as_ref() == Some(item_object.upcast_ref()) {
+            thumbnail.set_paintable(texture.as_ref());
+        }
+        cache.borrow_mut().insert(id, texture);
+    });
+}
+
 fn highlight_title(title: &str, query: &str) -> String {
     let title_lower = title.to_lowercase();
     let query_lower = query.to_lowercase();
@@ -1,7 +1,9 @@
+pub mod animation;
 pub mod header;
 pub mod item_object;
 pub mod list;
 pub mod r#match;
 pub mod preview;
 pub mod search;
+pub mod viewer;
 pub mod window;
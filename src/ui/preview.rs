@@ -1,7 +1,16 @@
 use crate::domain::item::Item;
-use crate::services::preview::PreviewPayload;
+use crate::services::ansi;
+use crate::services::preview::highlight::{self, TokenKind};
+use crate::services::preview::markdown::{self, Element};
+use crate::services::preview::{PreviewPayload, PreviewSize};
+use crate::ui::animation::create_animation_picture;
+use crate::ui::viewer::{ImageViewer, rgba_texture};
 use gtk4::prelude::*;
-use gtk4::{Align, Grid, Label, Picture, ScrolledWindow, TextView};
+use gtk4::{Align, Grid, Label, ScrolledWindow, TextBuffer, TextTag, TextView};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::rc::Rc;
 
 #[derive(Clone)]
 pub struct PreviewArea {
@@ -10,7 +19,11 @@ pub struct PreviewArea {
     title_label: Label,
     category_label: Label,
     path_label: Label,
+    media_label: Label,
     details_scrolled: ScrolledWindow,
+    requested_size: Rc<Cell<PreviewSize>>,
+    wrap: Rc<Cell<bool>>,
+    viewer: Rc<RefCell<Option<ImageViewer>>>,
 }
 
 impl PreviewArea {
@@ -28,10 +41,16 @@ impl PreviewArea {
         path_label.set_ellipsize(gtk4::pango::EllipsizeMode::End);
         path_label.add_css_class("preview-path");
 
+        let media_label = Label::new(None);
+        media_label.set_halign(Align::Start);
+        media_label.set_visible(false);
+        media_label.add_css_class("preview-media");
+
         let details_box = gtk4::Box::new(gtk4::Orientation::Vertical, 4);
         details_box.append(&title_label);
         details_box.append(&category_label);
         details_box.append(&path_label);
+        details_box.append(&media_label);
         details_box.add_css_class("preview-details-box");
 
         let details_scrolled = ScrolledWindow::new();
@@ -39,7 +58,7 @@ impl PreviewArea {
         details_scrolled.set_vexpand(false);
         details_scrolled.set_hscrollbar_policy(gtk4::PolicyType::Automatic);
         details_scrolled.set_vscrollbar_policy(gtk4::PolicyType::Automatic);
-        details_scrolled.set_size_request(-1, 100);
+        details_scrolled.set_size_request(-1, crate::constants::PREVIEW_DETAILS_HEIGHT);
         details_scrolled.add_css_class("preview-details-scrolled");
 
         let content_scrolled = ScrolledWindow::new();
@@ -68,10 +87,55 @@ impl PreviewArea {
             title_label,
             category_label,
             path_label,
+            media_label,
             details_scrolled,
+            requested_size: Rc::new(Cell::new(PreviewSize::default())),
+            wrap: Rc::new(Cell::new(true)),
+            viewer: Rc::new(RefCell::new(None)),
         }
     }
 
+    /// Decode box for the current allocation, in device pixels
+    pub fn decode_size(&self) -> PreviewSize {
+        let scale = surface_scale(&self.content_scrolled);
+        PreviewSize::bucketed(
+            (self.content_scrolled.width() as f64 * scale).ceil() as i32,
+            (self.content_scrolled.height() as f64 * scale).ceil() as i32,
+        )
+    }
+
+    pub fn set_wrap(&self, wrap: bool) {
+        self.wrap.set(wrap);
+    }
+
+    pub fn set_requested_size(&self, size: PreviewSize) {
+        self.requested_size.set(size);
+    }
+
+    /// True when the pane has grown past the size the current preview was decoded for
+    pub fn needs_larger_decode(&self) -> bool {
+        !self.requested_size.get().covers(&self.decode_size())
+    }
+
+    /// Fires when the content pane is resized or moved to a surface with a different scale
+    pub fn connect_resized<F>(&self, callback: F)
+    where
+        F: Fn() + 'static,
+    {
+        let callback = Rc::new(callback);
+
+        let cb = callback.clone();
+        self.content_scrolled
+            .hadjustment()
+            .connect_page_size_notify(move |_| cb());
+        let cb = callback.clone();
+        self.content_scrolled
+            .vadjustment()
+            .connect_page_size_notify(move |_| cb());
+        self.content_scrolled
+            .connect_scale_factor_notify(move |_| callback());
+    }
+
     pub fn render(&self, payload: PreviewPayload, item: &Item) {
         if matches!(item.display, crate::domain::DisplayMode::Picture) {
             self.details_scrolled.set_visible(true);
@@ -80,38 +144,68 @@ impl PreviewArea {
             self.category_label
                 .set_text(&format!("Category: {}", item.category));
             self.path_label.set_text(&format!("Path: {}", item.value));
+            self.set_media_details(&[]);
         } else {
             self.details_scrolled.set_visible(false);
         }
 
         self.content_scrolled.set_child(None::<&gtk4::Widget>);
+        self.viewer.replace(None);
 
         match payload {
             PreviewPayload::Text(text) => {
-                let text_view = create_text_view(&text);
+                let text_view = create_text_view(&text, item, self.wrap.get());
                 let scrolled = create_text_scrolled(&text_view);
                 self.set_content(&scrolled);
+                if let Some(line) = item.line {
+                    scroll_to_line(&text_view, line);
+                }
+            }
+            PreviewPayload::Markdown(text) => {
+                let text_view = create_markdown_view(&text);
+                self.set_content(&create_text_scrolled(&text_view));
             }
             PreviewPayload::Image {
                 bytes,
                 width,
                 height,
             } => {
-                let gbytes = glib::Bytes::from(&bytes[..]);
-                let texture = gtk4::gdk::MemoryTexture::new(
-                    width,
-                    height,
-                    gtk4::gdk::MemoryFormat::R8g8b8a8,
-                    &gbytes,
-                    (width * 4) as usize,
-                );
-                let picture = Picture::for_paintable(&texture);
+                let viewer = ImageViewer::new(bytes, width, height);
+                self.set_content(viewer.widget());
+                self.viewer.replace(Some(viewer));
+            }
+            PreviewPayload::Animation {
+                frames,
+                width,
+                height,
+            } => {
+                let picture = create_animation_picture(frames, width, height);
                 picture.set_halign(Align::Center);
                 picture.set_valign(Align::Center);
                 picture.set_hexpand(true);
                 picture.set_vexpand(true);
                 self.set_content(&picture);
             }
+            PreviewPayload::Listing { tree, cover } => {
+                let text_view = new_text_view(false);
+                text_view.set_monospace(true);
+                text_view.buffer().set_text(&tree);
+                let scrolled = create_text_scrolled(&text_view);
+                match cover {
+                    Some((bytes, width, height)) => {
+                        let picture =
+                            gtk4::Picture::for_paintable(&rgba_texture(bytes, width, height));
+                        picture.set_can_shrink(true);
+                        picture.set_content_fit(gtk4::ContentFit::Contain);
+                        picture.set_vexpand(true);
+                        let layout = gtk4::Box::new(gtk4::Orientation::Vertical, 6);
+                        layout.append(&picture);
+                        layout.append(&scrolled);
+                        self.set_content(&layout);
+                    }
+                    None => self.set_content(&scrolled),
+                }
+            }
             PreviewPayload::Error(err) => {
                 let label = Label::new(Some(&err));
                 label.set_halign(Align::Center);
@@ -124,26 +218,333 @@ impl PreviewArea {
         }
     }
 
+    /// Zoom and rotation shortcuts for a still image; false when nothing handled the key
+    pub fn handle_viewer_key(&self, keyval: gtk4::gdk::Key, has_shift: bool) -> bool {
+        self.viewer
+            .borrow()
+            .as_ref()
+            .is_some_and(|viewer| viewer.handle_key(keyval, has_shift))
+    }
+
+    /// Ask for a full-resolution decode once the shown image is zoomed past its pixels
+    pub fn connect_full_resolution<F: Fn() + 'static>(&self, callback: F) {
+        if let Some(viewer) = &*self.viewer.borrow() {
+            viewer.connect_full_resolution(callback);
+        }
+    }
+
+    pub fn show_full_resolution(&self, payload: PreviewPayload) {
+        if let PreviewPayload::Image {
+            bytes,
+            width,
+            height,
+        } = payload
+            && let Some(viewer) = &*self.viewer.borrow()
+        {
+            viewer.set_full_resolution(bytes, width, height);
+        }
+    }
+
+    /// Extra lines under the path, such as a video's duration and codec
+    pub fn set_media_details(&self, details: &[(String, String)]) {
+        let text = details
+            .iter()
+            .map(|(label, value)| format!("{}: {}", label, value))
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.media_label.set_text(&text);
+        self.media_label.set_visible(!details.is_empty());
+    }
+
     fn set_content<W: IsA<gtk4::Widget>>(&self, widget: &W) {
         self.content_scrolled.set_child(Some(widget));
     }
 }
 
-fn create_text_view(text: &str) -> TextView {
+/// Device pixels per logical pixel, fractional on scaled outputs
+pub fn surface_scale(widget: &impl IsA<gtk4::Widget>) -> f64 {
+    widget
+        .native()
+        .and_then(|native| native.surface())
+        .map(|surface| surface.scale())
+        .unwrap_or_else(|| widget.scale_factor() as f64)
+}
+
+fn create_text_view(text: &str, item: &Item, wrap: bool) -> TextView {
+    let text_view = new_text_view(wrap);
+    fill_buffer(&text_view.buffer(), text, item);
+    text_view
+}
+
+/// Prose is always wrapped; its paragraphs are single long lines once laid out
+fn create_markdown_view(source: &str) -> TextView {
+    let text_view = new_text_view(true);
+    let buffer = text_view.buffer();
+    let doc = markdown::render(source);
+    buffer.set_text(&doc.text);
+    for (range, element) in doc.spans {
+        let tag = markdown_tag(&buffer, element);
+        buffer.apply_tag(
+            &tag,
+            &buffer.iter_at_offset(range.start as i32),
+            &buffer.iter_at_offset(range.end as i32),
+        );
+    }
+    text_view
+}
+
+/// The buffer's tag for an element, created the first time it is used
+fn markdown_tag(buffer: &TextBuffer, element: Element) -> TextTag {
+    let name = match element {
+        Element::Heading(level) => format!("md-h{}", level),
+        other => format!("md-{:?}", other).to_lowercase(),
+    };
+    let table = buffer.tag_table();
+    if let Some(tag) = table.lookup(&name) {
+        return tag;
+    }
+    let tag = TextTag::new(Some(&name));
+    match element {
+        Element::Heading(level) => {
+            const SCALES: [f64; 6] = [1.8, 1.5, 1.3, 1.15, 1.0, 1.0];
+            tag.set_scale(SCALES[(level.clamp(1, 6) - 1) as usize]);
+            tag.set_weight(700);
+        }
+        Element::Emphasis => tag.set_style(gtk4::pango::Style::Italic),
+        Element::Strong => tag.set_weight(700),
+        Element::Strikethrough => tag.set_strikethrough(true),
+        Element::Code => {
+            tag.set_family(Some("monospace"));
+            tag.set_background(Some("rgba(127, 127, 127, 0.15)"));
+        }
+        Element::CodeBlock => {
+            tag.set_family(Some("monospace"));
+            tag.set_paragraph_background(Some("rgba(127, 127, 127, 0.15)"));
+            tag.set_wrap_mode(gtk4::WrapMode::None);
+        }
+        Element::Quote => {
+            tag.set_left_margin(24);
+            tag.set_foreground(Some("#8a8a8a"));
+        }
+        Element::Link => {
+            tag.set_foreground(Some("#3584e4"));
+            tag.set_underline(gtk4::pango::Underline::Single);
+        }
+        Element::Marker => tag.set_foreground(Some("#8a8a8a")),
+    }
+    table.add(&tag);
+    tag
+}
+
+fn new_text_view(wrap: bool) -> TextView {
     let text_view = TextView::new();
     text_view.set_editable(false);
     text_view.set_cursor_visible(false);
-    text_view.set_wrap_mode(gtk4::WrapMode::Word);
+    text_view.set_wrap_mode(if wrap {
+        gtk4::WrapMode::WordChar
+    } else {
+        gtk4::WrapMode::None
+    });
     text_view.set_left_margin(10);
     text_view.set_right_margin(10);
     text_view.set_top_margin(10);
     text_view.set_bottom_margin(10);
-    text_view.buffer().set_text(text);
     text_view.set_hexpand(true);
     text_view.set_vexpand(true);
     text_view
 }
 
+/// Code gets line numbers and colours, command output keeps its ANSI colours;
+/// a search match's line is marked too
+fn fill_buffer(buffer: &TextBuffer, text: &str, item: &Item) {
+    let within_limit = text.len() <= crate::constants::MAX_HIGHLIGHT_BYTES;
+    if text.contains('\x1b') {
+        if !within_limit {
+            buffer.set_text(&ansi::strip(text));
+            return;
+        }
+        create_tags(buffer);
+        let lines = ansi::parse_lines(text);
+        let mut tags: HashMap<ansi::Style, TextTag> = HashMap::new();
+        let lines: Vec<_> = lines
+            .iter()
+            .map(|(line, spans)| {
+                let spans = spans
+                    .iter()
+                    .map(|(range, style)| {
+                        let tag = tags
+                            .entry(*style)
+                            .or_insert_with(|| ansi_tag(buffer, style));
+                        (range.clone(), tag.clone())
+                    })
+                    .collect();
+                (line.as_str(), spans)
+            })
+            .collect();
+        write_lines(buffer, &lines, item.line.is_some(), item.line);
+        return;
+    }
+
+    let path = crate::utils::expand_tilde(&item.value);
+    let path = path.is_file().then_some(path.as_path());
+    let syntax = within_limit
+        .then(|| highlight::detect(path, text))
+        .flatten();
+    if syntax.is_none() && item.line.is_none() {
+        buffer.set_text(text);
+        return;
+    }
+
+    create_tags(buffer);
+    let table = buffer.tag_table();
+    let spans = syntax.map(|syntax| {
+        log::debug!("Highlighting {} as {}", item.value, syntax.name);
+        highlight::highlight_lines(text, syntax)
+    });
+    let lines: Vec<_> = text
+        .lines()
+        .enumerate()
+        .map(|(index, line)| {
+            let spans = spans
+                .iter()
+                .flat_map(|spans| &spans[index])
+                .filter_map(|(range, kind)| Some((range.clone(), table.lookup(token_tag(*kind))?)))
+                .collect();
+            (line, spans)
+        })
+        .collect();
+    write_lines(buffer, &lines, true, item.line);
+}
+
+/// A line of text and the tags for byte ranges within it
+type TaggedLine<'a> = (&'a str, Vec<(Range<usize>, TextTag)>);
+
+/// Insert lines, tagging the given byte ranges of each
+fn write_lines(
+    buffer: &TextBuffer,
+    lines: &[TaggedLine],
+    numbered: bool,
+    current_line: Option<u32>,
+) {
+    let width = lines.len().max(1).to_string().len();
+    let mut iter = buffer.end_iter();
+
+    for (index, (line, spans)) in lines.iter().enumerate() {
+        if numbered {
+            let number = format!("{:>width$}  ", index + 1);
+            buffer.insert_with_tags_by_name(&mut iter, &number, &["line-number"]);
+        }
+        let line_start = iter.offset();
+        buffer.insert(&mut iter, line);
+        buffer.insert(&mut iter, "\n");
+
+        // Spans come in order, so char offsets are counted on from the previous span rather
+        // than from the line start, which would be quadratic on long minified lines
+        let (mut byte_offset, mut char_offset) = (0, line_start);
+        for (range, tag) in spans {
+            if range.start < byte_offset {
+                (byte_offset, char_offset) = (0, line_start);
+            }
+            let start = char_offset + line[byte_offset..range.start].chars().count() as i32;
+            let end = start + line[range.clone()].chars().count() as i32;
+            (byte_offset, char_offset) = (range.end, end);
+            buffer.apply_tag(
+                tag,
+                &buffer.iter_at_offset(start),
+                &buffer.iter_at_offset(end),
+            );
+        }
+        if current_line == Some(index as u32 + 1) {
+            let end = iter;
+            let mut start = end;
+            start.backward_line();
+            buffer.apply_tag_by_name("current-line", &start, &end);
+        }
+    }
+}
+
+/// An anonymous tag for one combination of SGR attributes
+fn ansi_tag(buffer: &TextBuffer, style: &ansi::Style) -> TextTag {
+    let hex = |(r, g, b): ansi::Rgb| format!("#{:02x}{:02x}{:02x}", r, g, b);
+    let tag = TextTag::new(None);
+    if let Some(fg) = style.fg {
+        tag.set_foreground(Some(&hex(fg)));
+    }
+    if let Some(bg) = style.bg {
+        tag.set_background(Some(&hex(bg)));
+    }
+    if style.bold {
+        tag.set_weight(700);
+    }
+    if style.italic {
+        tag.set_style(gtk4::pango::Style::Italic);
+    }
+    if style.underline {
+        tag.set_underline(gtk4::pango::Underline::Single);
+    }
+    buffer.tag_table().add(&tag);
+    tag
+}
+
+fn create_tags(buffer: &TextBuffer) {
+    let table = buffer.tag_table();
+    let tags = [
+        gtk4::TextTag::builder()
+            .name("line-number")
+            .foreground("#8a8a8a")
+            .build(),
+        gtk4::TextTag::builder()
+            .name("current-line")
+            .paragraph_background("rgba(53, 132, 228, 0.2)")
+            .build(),
+        gtk4::TextTag::builder()
+            .name("keyword")
+            .foreground("#c061cb")
+            .weight(700)
+            .build(),
+        gtk4::TextTag::builder()
+            .name("string")
+            .foreground("#2ec27e")
+            .build(),
+        gtk4::TextTag::builder()
+            .name("comment")
+            .foreground("#8a8a8a")
+            .style(gtk4::pango::Style::Italic)
+            .build(),
+        gtk4::TextTag::builder()
+            .name("number")
+            .foreground("#e66100")
+            .build(),
+    ];
+    for tag in &tags {
+        table.add(tag);
+    }
+}
+
+fn token_tag(kind: TokenKind) -> &'static str {
+    match kind {
+        TokenKind::Keyword => "keyword",
+        TokenKind::String => "string",
+        TokenKind::Comment => "comment",
+        TokenKind::Number => "number",
+    }
+}
+
+/// Scroll once the view has been laid out, keeping the line a third of the way down
+fn scroll_to_line(text_view: &TextView, line: u32) {
+    let buffer = text_view.buffer();
+    let Some(iter) = buffer.iter_at_line(line.saturating_sub(1) as i32) else {
+        return;
+    };
+    let mark = buffer.create_mark(None, &iter, true);
+    let text_view = text_view.downgrade();
+    glib::idle_add_local_once(move || {
+        if let Some(text_view) = text_view.upgrade() {
+            text_view.scroll_to_mark(&mark, 0.0, true, 0.0, 0.3);
+        }
+    });
+}
+
 fn create_text_scrolled(text_view: &TextView) -> ScrolledWindow {
     let scrolled = ScrolledWindow::new();
     scrolled.set_child(Some(text_view));
@@ -0,0 +1,440 @@
+use crate::constants::{MAX_ZOOM, MIN_ZOOM, ZOOM_STEP};
+use crate::services::preview::metadata::orient_rgba;
+use gtk4::gdk::{Key, ModifierType};
+use gtk4::prelude::*;
+use gtk4::{
+    Adjustment, EventControllerScroll, EventControllerScrollFlags, GestureClick, GestureDrag,
+    Picture, ScrolledWindow, gdk, gio, glib,
+};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// How the image is sized in the pane
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ZoomMode {
+    /// Whole image visible
+    Fit,
+    /// Pane covered, cropping the overflow
+    Fill,
+    /// One image pixel per device pixel
+    Actual,
+    /// Device pixels per image pixel
+    Scale(f64),
+}
+
+/// A still image that can be zoomed, panned by dragging and rotated
+#[derive(Clone)]
+pub struct ImageViewer {
+    inner: Rc<Viewer>,
+}
+
+struct Viewer {
+    scrolled: ScrolledWindow,
+    picture: Picture,
+    /// Unrotated RGBA pixels, width and height
+    source: RefCell<(Arc<Vec<u8>>, i32, i32)>,
+    quarter_turns: Cell<u8>,
+    /// Bumped on every texture change, so a rotation finishing late is dropped
+    texture_generation: Cell<u64>,
+    mode: Cell<ZoomMode>,
+    /// The pixels are the file's own resolution rather than a preview-sized decode
+    full_resolution: Cell<bool>,
+    full_resolution_requested: Cell<bool>,
+    on_full_resolution: RefCell<Option<Box<dyn Fn()>>>,
+}
+
+impl ImageViewer {
+    pub fn new(bytes: Arc<Vec<u8>>, width: i32, height: i32) -> Self {
+        let picture = Picture::new();
+        picture.set_hexpand(true);
+        picture.set_vexpand(true);
+
+        let scrolled = ScrolledWindow::new();
+        scrolled.set_child(Some(&picture));
+        scrolled.set_hexpand(true);
+        scrolled.set_vexpand(true);
+
+        let viewer = Self {
+            inner: Rc::new(Viewer {
+                scrolled,
+                picture,
+                source: RefCell::new((bytes, width, height)),
+                quarter_turns: Cell::new(0),
+                texture_generation: Cell::new(0),
+                mode: Cell::new(ZoomMode::Fit),
+                full_resolution: Cell::new(false),
+                full_resolution_requested: Cell::new(false),
+                on_full_resolution: RefCell::new(None),
+            }),
+        };
+        viewer.update_texture();
+        viewer.apply_mode();
+        viewer.connect_gestures();
+        viewer
+    }
+
+    pub fn widget(&self) -> &ScrolledWindow {
+        &self.inner.scrolled
+    }
+
+    /// Called once when zooming needs more pixels than the preview decode has
+    pub fn connect_full_resolution<F: Fn() + 'static>(&self, callback: F) {
+        self.inner
+            .on_full_resolution
+            .replace(Some(Box::new(callback)));
+        self.request_full_resolution_if_needed();
+    }
+
+    /// Swap in the full-resolution decode, keeping zoom and rotation as they appear
+    pub fn set_full_resolution(&self, bytes: Arc<Vec<u8>>, width: i32, height: i32) {
+        let old_width = self.inner.source.borrow().1;
+        if let ZoomMode::Scale(scale) = self.inner.mode.get() {
+            let ratio = width as f64 / old_width.max(1) as f64;
+            self.inner.mode.set(ZoomMode::Scale(scale / ratio));
+        }
+        self.inner.source.replace((bytes, width, height));
+        self.inner.full_resolution.set(true);
+        self.update_texture();
+        self.apply_mode();
+    }
+
+    /// Ctrl shortcuts: +/- zoom, 0 fit, 9 fill, 1 actual size, R/Shift+R rotate
+    pub fn handle_key(&self, keyval: Key, has_shift: bool) -> bool {
+        match keyval {
+            Key::plus | Key::equal | Key::KP_Add => self.zoom(true),
+            Key::minus | Key::underscore | Key::KP_Subtract => self.zoom(false),
+            Key::_0 | Key::KP_0 => self.set_mode(ZoomMode::Fit),
+            Key::_9 | Key::KP_9 => self.set_mode(ZoomMode::Fill),
+            Key::_1 | Key::KP_1 => self.set_mode(ZoomMode::Actual),
+            Key::r | Key::R => self.rotate(!has_shift),
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn set_mode(&self, mode: ZoomMode) {
+        self.keep_center(|| {
+            self.inner.mode.set(mode);
+            self.apply_mode();
+        });
+    }
+
+    pub fn zoom(&self, zoom_in: bool) {
+        let scale = self.current_scale();
+        self.set_mode(ZoomMode::Scale(step_zoom(scale, zoom_in)));
+    }
+
+    pub fn rotate(&self, clockwise: bool) {
+        let turns = self.inner.quarter_turns.get();
+        self.inner.quarter_turns.set(if clockwise {
+            (turns + 1) % 4
+        } else {
+            (turns + 3) % 4
+        });
+        self.update_texture();
+        self.apply_mode();
+    }
+
+    /// Rotated pixels are made on a worker, since turning a full-resolution decode takes
+    /// long enough to stall the UI
+    fn update_texture(&self) {
+        let (bytes, width, height) = self.inner.source.borrow().clone();
+        let turns = self.inner.quarter_turns.get();
+        let generation = self.inner.texture_generation.get() + 1;
+        self.inner.texture_generation.set(generation);
+        if turns == 0 {
+            let texture = rgba_texture(bytes, width, height);
+            self.inner.picture.set_paintable(Some(&texture));
+            return;
+        }
+
+        let viewer = Rc::downgrade(&self.inner);
+        glib::spawn_future_local(async move {
+            let rotated =
+                gio::spawn_blocking(move || rotate_rgba(&bytes, width, height, turns)).await;
+            if let Ok((rotated, w, h)) = rotated
+                && let Some(inner) = viewer.upgrade()
+                && inner.texture_generation.get() == generation
+            {
+                let texture = rgba_texture(Arc::new(rotated), w, h);
+                inner.picture.set_paintable(Some(&texture));
+            }
+        });
+    }
+
+    /// Width and height as displayed, after rotation
+    fn image_size(&self) -> (i32, i32) {
+        let (_, width, height) = *self.inner.source.borrow();
+        if self.inner.quarter_turns.get() % 2 == 1 {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    fn device_scale(&self) -> f64 {
+        super::preview::surface_scale(&self.inner.scrolled)
+    }
+
+    /// Device pixels per image pixel at the current mode
+    fn current_scale(&self) -> f64 {
+        let (width, height) = self.image_size();
+        let device = self.device_scale();
+        let view = (
+            self.inner.scrolled.width() as f64 * device,
+            self.inner.scrolled.height() as f64 * device,
+        );
+        match self.inner.mode.get() {
+            ZoomMode::Fit => fit_scale((width, height), view, false),
+            ZoomMode::Fill => fit_scale((width, height), view, true),
+            ZoomMode::Actual => 1.0,
+            ZoomMode::Scale(scale) => scale,
+        }
+    }
+
+    fn apply_mode(&self) {
+        let picture = &self.inner.picture;
+        let mode = self.inner.mode.get();
+        match mode {
+            ZoomMode::Fit | ZoomMode::Fill => {
+                picture.set_size_request(-1, -1);
+                picture.set_halign(gtk4::Align::Fill);
+                picture.set_valign(gtk4::Align::Fill);
+                picture.set_content_fit(if mode == ZoomMode::Fit {
+                    gtk4::ContentFit::Contain
+                } else {
+                    gtk4::ContentFit::Cover
+                });
+            }
+            ZoomMode::Actual | ZoomMode::Scale(_) => {
+                let (width, height) = self.image_size();
+                let logical = self.current_scale() / self.device_scale();
+                picture.set_size_request(
+                    (width as f64 * logical).round() as i32,
+                    (height as f64 * logical).round() as i32,
+                );
+                picture.set_content_fit(gtk4::ContentFit::Fill);
+                picture.set_halign(gtk4::Align::Center);
+                picture.set_valign(gtk4::Align::Center);
+            }
+        }
+        self.inner.scrolled.set_cursor_from_name(
+            matches!(mode, ZoomMode::Actual | ZoomMode::Scale(_)).then_some("grab"),
+        );
+        self.request_full_resolution_if_needed();
+    }
+
+    fn request_full_resolution_if_needed(&self) {
+        let magnified = match self.inner.mode.get() {
+            ZoomMode::Actual => true,
+            ZoomMode::Scale(scale) => scale > 1.0,
+            ZoomMode::Fit | ZoomMode::Fill => false,
+        };
+        if !magnified
+            || self.inner.full_resolution.get()
+            || self.inner.full_resolution_requested.get()
+        {
+            return;
+        }
+        if let Some(callback) = &*self.inner.on_full_resolution.borrow() {
+            self.inner.full_resolution_requested.set(true);
+            callback();
+        }
+    }
+
+    /// Keep the point at the centre of the pane in place across a zoom change
+    fn keep_center(&self, change: impl FnOnce()) {
+        let fractions = [
+            self.inner.scrolled.hadjustment(),
+            self.inner.scrolled.vadjustment(),
+        ]
+        .map(|adj| (adj.value() + adj.page_size() / 2.0) / adj.upper().max(1.0));
+        change();
+        let scrolled = self.inner.scrolled.downgrade();
+        // The new size is only allocated on the next frame
+        glib::idle_add_local_once(move || {
+            let Some(scrolled) = scrolled.upgrade() else {
+                return;
+            };
+            let adjustments = [scrolled.hadjustment(), scrolled.vadjustment()];
+            for (adj, fraction) in adjustments.iter().zip(fractions) {
+                adj.set_value(fraction * adj.upper() - adj.page_size() / 2.0);
+            }
+        });
+    }
+
+    fn connect_gestures(&self) {
+        let scrolled = &self.inner.scrolled;
+
+        // Ctrl+wheel zooms; the plain wheel keeps scrolling
+        let scroll = EventControllerScroll::new(EventControllerScrollFlags::VERTICAL);
+        scroll.set_propagation_phase(gtk4::PropagationPhase::Capture);
+        let viewer = Rc::downgrade(&self.inner);
+        scroll.connect_scroll(move |controller, _, dy| {
+            let Some(inner) = viewer.upgrade() else {
+                return glib::Propagation::Proceed;
+            };
+            if !controller
+                .current_event_state()
+                .contains(ModifierType::CONTROL_MASK)
+            {
+                return glib::Propagation::Proceed;
+            }
+            ImageViewer { inner }.zoom(dy < 0.0);
+            glib::Propagation::Stop
+        });
+        scrolled.add_controller(scroll);
+
+        let drag = GestureDrag::new();
+        let origin = Rc::new(Cell::new((0.0, 0.0)));
+        let (hadj, vadj) = (scrolled.hadjustment(), scrolled.vadjustment());
+        drag.connect_drag_begin({
+            let origin = origin.clone();
+            let (hadj, vadj) = (hadj.clone(), vadj.clone());
+            let scrolled = scrolled.downgrade();
+            move |_, _, _| {
+                origin.set((hadj.value(), vadj.value()));
+                if let Some(scrolled) = scrolled.upgrade()
+                    && scrolled.cursor().is_some()
+                {
+                    scrolled.set_cursor_from_name(Some("grabbing"));
+                }
+            }
+        });
+        drag.connect_drag_update(move |_, dx, dy| {
+            let (x, y) = origin.get();
+            pan(&hadj, x - dx);
+            pan(&vadj, y - dy);
+        });
+        let scrolled_weak = scrolled.downgrade();
+        drag.connect_drag_end(move |_, _, _| {
+            if let Some(scrolled) = scrolled_weak.upgrade()
+                && scrolled.cursor().is_some()
+            {
+                scrolled.set_cursor_from_name(Some("grab"));
+            }
+        });
+        scrolled.add_controller(drag);
+
+        // Double-click toggles between the whole image and actual size
+        let click = GestureClick::new();
+        let viewer = Rc::downgrade(&self.inner);
+        click.connect_pressed(move |_, n_press, _, _| {
+            let Some(inner) = viewer.upgrade() else {
+                return;
+            };
+            if n_press == 2 {
+                let viewer = ImageViewer { inner };
+                let mode = match viewer.inner.mode.get() {
+                    ZoomMode::Fit => ZoomMode::Actual,
+                    _ => ZoomMode::Fit,
+                };
+                viewer.set_mode(mode);
+            }
+        });
+        scrolled.add_controller(click);
+    }
+}
+
+fn pan(adjustment: &Adjustment, value: f64) {
+    let max = adjustment.upper() - adjustment.page_size();
+    adjustment.set_value(value.clamp(adjustment.lower(), max.max(adjustment.lower())));
+}
+
+/// Decoded pixels handed to GDK without copying them
+struct SharedPixels(Arc<Vec<u8>>);
+
+impl AsRef<[u8]> for SharedPixels {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+pub fn rgba_texture(bytes: Arc<Vec<u8>>, width: i32, height: i32) -> gdk::MemoryTexture {
+    gdk::MemoryTexture::new(
+        width,
+        height,
+        gdk::MemoryFormat::R8g8b8a8,
+        &glib::Bytes::from_owned(SharedPixels(bytes)),
+        (width * 4) as usize,
+    )
+}
+
+/// Scale that fits the image inside the view, or covers it when `cover`
+fn fit_scale((width, height): (i32, i32), (view_w, view_h): (f64, f64), cover: bool) -> f64 {
+    if width <= 0 || height <= 0 || view_w <= 0.0 || view_h <= 0.0 {
+        return 1.0;
+    }
+    let (sx, sy) = (view_w / width as f64, view_h / height as f64);
+    if cover { sx.max(sy) } else { sx.min(sy) }
+}
+
+fn step_zoom(scale: f64, zoom_in: bool) -> f64 {
+    let next = if zoom_in {
+        scale * ZOOM_STEP
+    } else {
+        scale / ZOOM_STEP
+    };
+    // Snap to 1:1 when a step crosses it, so actual size is reachable by keyboard
+    let crossed = (scale < 1.0 && next > 1.0) || (scale > 1.0 && next < 1.0);
+    if crossed {
+        1.0
+    } else {
+        next.clamp(MIN_ZOOM, MAX_ZOOM)
+    }
+}
+
+/// RGBA pixels turned clockwise by `quarter_turns` right angles
+pub fn rotate_rgba(
+    bytes: &[u8],
+    width: i32,
+    height: i32,
+    quarter_turns: u8,
+) -> (Vec<u8>, i32, i32) {
+    // EXIF orientations for 0, 90, 180 and 270 degrees clockwise
+    const ORIENTATIONS: [u32; 4] = [1, 6, 3, 8];
+    orient_rgba(
+        bytes,
+        width,
+        height,
+        ORIENTATIONS[(quarter_turns % 4) as usize],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2×1 image: red, green
+    fn pixels() -> Vec<u8> {
+        vec![255, 0, 0, 255, 0, 255, 0, 255]
+    }
+
+    #[test]
+    fn rotates_clockwise() {
+        let (out, w, h) = rotate_rgba(&pixels(), 2, 1, 1);
+        assert_eq!((w, h), (1, 2));
+        assert_eq!(out, pixels());
+        let (out, _, _) = rotate_rgba(&pixels(), 2, 1, 2);
+        assert_eq!(out, vec![0, 255, 0, 255, 255, 0, 0, 255]);
+        let (out, w, h) = rotate_rgba(&pixels(), 2, 1, 3);
+        assert_eq!((w, h), (1, 2));
+        assert_eq!(out, vec![0, 255, 0, 255, 255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn fit_and_fill_scales() {
+        assert_eq!(fit_scale((800, 400), (400.0, 400.0), false), 0.5);
+        assert_eq!(fit_scale((800, 400), (400.0, 400.0), true), 1.0);
+        assert_eq!(fit_scale((0, 400), (400.0, 400.0), true), 1.0);
+    }
+
+    #[test]
+    fn zoom_steps_snap_to_actual_size() {
+        assert_eq!(step_zoom(0.9, true), 1.0);
+        assert_eq!(step_zoom(1.1, false), 1.0);
+        assert_eq!(step_zoom(1.0, true), ZOOM_STEP);
+        assert_eq!(step_zoom(MAX_ZOOM, true), MAX_ZOOM);
+    }
+}
@@ -19,19 +19,6 @@ pub fn expand_tilde<P: AsRef<Path>>(path: P) -> PathBuf {
     }
 }
 
-/// Convert path to safe filename (replace illegal characters)
-pub fn path_to_safe_filename<P: AsRef<Path>>(path: P) -> String {
-    let path_str = path.as_ref().to_string_lossy();
-    path_str
-        .chars()
-        .map(|c| match c {
-            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
-            c if c.is_control() => '_',
-            c => c,
-        })
-        .collect()
-}
-
 pub fn escape_shell_arg(s: &str) -> String {
     if s.is_empty() {
         return "''".to_string();
@@ -40,6 +27,39 @@ pub fn escape_shell_arg(s: &str) -> String {
     format!("'{}'", escaped)
 }
 
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number.parse().map_err(|_| format!("invalid size: {}", s))?;
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        _ => return Err(format!("unknown size unit in {}", s)),
+    };
+    Ok((number * multiplier as f64) as u64)
+}
+
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -65,37 +85,43 @@ mod tests {
     }
 
     #[test]
-    fn safe_filename_replaces_illegal() {
-        assert_eq!(path_to_safe_filename("a/b:c*d"), "a_b_c_d");
+    fn escape_plain_text() {
+        assert_eq!(escape_shell_arg("hello"), "'hello'");
     }
 
     #[test]
-    fn safe_filename_preserves_normal() {
-        assert_eq!(path_to_safe_filename("hello.txt"), "hello.txt");
+    fn escape_injection_payload() {
+        assert_eq!(escape_shell_arg("123; rm -rf /"), "'123; rm -rf /'");
     }
 
     #[test]
-    fn safe_filename_handles_control_chars() {
-        assert_eq!(path_to_safe_filename("a\u{0000}b"), "a_b");
+    fn escape_internal_single_quotes() {
+        assert_eq!(escape_shell_arg("it's simple"), "'it'\\''s simple'");
     }
 
     #[test]
-    fn escape_plain_text() {
-        assert_eq!(escape_shell_arg("hello"), "'hello'");
+    fn escape_empty_string() {
+        assert_eq!(escape_shell_arg(""), "''");
     }
 
     #[test]
-    fn escape_injection_payload() {
-        assert_eq!(escape_shell_arg("123; rm -rf /"), "'123; rm -rf /'");
+    fn parse_size_units() {
+        assert_eq!(parse_size("500M").unwrap(), 500 * 1024 * 1024);
+        assert_eq!(parse_size("2G").unwrap(), 2 * 1024 * 1024 * 1024);
+        assert_eq!(parse_size("1.5K").unwrap(), 1536);
+        assert_eq!(parse_size("42").unwrap(), 42);
     }
 
     #[test]
-    fn escape_internal_single_quotes() {
-        assert_eq!(escape_shell_arg("it's simple"), "'it'\\''s simple'");
+    fn parse_size_rejects_garbage() {
+        assert!(parse_size("lots").is_err());
+        assert!(parse_size("5Q").is_err());
     }
 
     #[test]
-    fn escape_empty_string() {
-        assert_eq!(escape_shell_arg(""), "''");
+    fn format_size_human_readable() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(500 * 1024 * 1024), "500.0 MiB");
     }
 }